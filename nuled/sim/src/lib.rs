@@ -0,0 +1,93 @@
+//! Host builds of the NULED firmware modules that do not depend on hardware.
+//!
+//! Used by the simulator binary, and to run the firmware unit tests on the host.
+
+// The firmware modules describe themselves in a `///` comment at the top of the file.
+#![allow(clippy::empty_line_after_doc_comments)]
+
+#[macro_use]
+extern crate log;
+
+// Firmware modules are compiled as-is. They are written for no_std,
+// where the `Float` imports are needed.
+#[path = "../../src/color.rs"]
+#[allow(unused_imports, clippy::from_over_into, clippy::upper_case_acronyms)]
+pub mod color;
+#[path = "../../src/effect.rs"]
+#[allow(unused_imports)]
+pub mod effect;
+#[path = "../../src/settings.rs"]
+#[allow(unused_imports)]
+pub mod settings;
+
+// Transitions and the output stage, with the compile-time configuration it depends on.
+pub mod config {
+    /// Default power budget, as in `config.example`.
+    pub const POWER_BUDGET_MA: u16 = 4000;
+}
+#[path = "../../src/power.rs"]
+pub mod power;
+#[path = "../../src/output.rs"]
+#[allow(unused_imports)]
+pub mod output;
+#[path = "../../src/transition.rs"]
+pub mod transition;
+
+// Names that identify the device.
+#[path = "../../src/identity.rs"]
+pub mod identity;
+
+// LED state, and its record in flash.
+#[path = "../../src/state.rs"]
+pub mod state;
+
+// Network protocol support for the provisioning portal.
+#[path = "../../src/dhcp.rs"]
+pub mod dhcp;
+#[path = "../../src/dns.rs"]
+pub mod dns;
+#[path = "../../src/http.rs"]
+pub mod http;
+#[path = "../../src/portal.rs"]
+pub mod portal;
+
+// Realtime pixel input.
+#[path = "../../src/pixels.rs"]
+pub mod pixels;
+#[path = "../../src/dmx.rs"]
+pub mod dmx;
+#[path = "../../src/ddp.rs"]
+pub mod ddp;
+#[path = "../../src/wled.rs"]
+pub mod wled;
+#[path = "../../src/zmtp.rs"]
+pub mod zmtp;
+#[path = "../../src/ledserver.rs"]
+pub mod ledserver;
+
+// LIFX LAN protocol.
+#[path = "../../src/lifx.rs"]
+#[allow(unused_imports)]
+pub mod lifx;
+
+// MQTT client, for its unit tests. Declared last, as it defines its own logging macros.
+// It is vendored from upstream and kept close to it, so it is not linted.
+#[path = "../../src/rust_mqtt/mod.rs"]
+#[allow(unused_imports, clippy::all)]
+pub mod rust_mqtt;
+
+use crate::effect::Effect;
+
+/// Names of all effects, as used in the MQTT API.
+pub const EFFECTS: [&str; 4] = ["solid", "rainbow", "gradient", "polyrhythm"];
+
+/// Instantiate an effect by its MQTT name.
+pub fn new_effect<const N: usize>(name: &str) -> Option<Box<dyn Effect<N>>> {
+    Some(match name {
+        "solid" => Box::new(effect::Solid::<N>::default()),
+        "rainbow" => Box::new(effect::Rainbow::<N>::default()),
+        "gradient" => Box::new(effect::Gradient::<N>::default()),
+        "polyrhythm" => Box::new(effect::Polyrhythm::<N>::default()),
+        _ => return None,
+    })
+}
@@ -0,0 +1,199 @@
+//! NULED effect simulator.
+//!
+//! Runs the firmware effects on the host, renders each frame to the terminal
+//! as 24-bit color blocks, and writes all frames to a PPM image where every
+//! row of pixels is one frame of animation.
+
+use nuled_sim::color::RGB;
+use nuled_sim::effect::Params;
+use nuled_sim::{new_effect, EFFECTS};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Same frame interval as the LED task on the device.
+const FRAME_INTERVAL_MS: u64 = 42;
+
+const USAGE: &str = "\
+Usage: nuled-sim [OPTIONS]
+
+Options:
+  --effect <NAME>       name of the effect to run [default: rainbow]
+  --leds <COUNT>        number of LEDs in the strip [default: 30]
+  --frames <COUNT>      number of frames to render [default: 240]
+  --color1 <R,G,B>      first color, components in the range 0..255
+  --color2 <R,G,B>      second color, components in the range 0..255
+  --chroma <FLOAT>      chroma parameter
+  --luminance <FLOAT>   luminance parameter
+  --size <FLOAT>        size parameter
+  --speed <FLOAT>       speed parameter
+  --output <FILE>       write all frames to this PPM image [default: nuled-sim.ppm]
+  --step                print every frame on its own line without delay
+  --help                show this help text
+";
+
+/// Simulator settings, parsed from the command line.
+struct Options {
+    effect: String,
+    led_count: usize,
+    frames: usize,
+    params: Params,
+    output: String,
+    step: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            effect: "rainbow".into(),
+            led_count: 30,
+            frames: 240,
+            params: Params::default(),
+            output: "nuled-sim.ppm".into(),
+            step: false,
+        }
+    }
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item=String>) -> Result<Option<Self>, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                return Ok(None);
+            }
+            if arg == "--step" {
+                options.step = true;
+                continue;
+            }
+
+            let value = args.next().ok_or_else(|| format!("missing value for {arg}"))?;
+            match arg.as_str() {
+                "--effect" => options.effect = value,
+                "--leds" => options.led_count = parse_number(&arg, &value)?,
+                "--frames" => options.frames = parse_number(&arg, &value)?,
+                "--color1" => options.params.color1 = parse_rgb(&value).ok_or_else(|| format!("invalid color: {value}"))?,
+                "--color2" => options.params.color2 = parse_rgb(&value).ok_or_else(|| format!("invalid color: {value}"))?,
+                "--chroma" => options.params.chroma = parse_number(&arg, &value)?,
+                "--luminance" => options.params.luminance = parse_number(&arg, &value)?,
+                "--size" => options.params.size = parse_number(&arg, &value)?,
+                "--speed" => options.params.speed = parse_number(&arg, &value)?,
+                "--output" => options.output = value,
+                _ => return Err(format!("unknown option: {arg}")),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {arg}: {value}"))
+}
+
+/// Parse colors in the same `r,g,b` format as the MQTT API.
+fn parse_rgb(s: &str) -> Option<RGB> {
+    let mut parts = s.split(",");
+    let r = parts.next()?;
+    let g = parts.next()?;
+    let b = parts.next()?;
+    Some(RGB {
+        r: f32::from_str(r.trim()).ok()?,
+        g: f32::from_str(g.trim()).ok()?,
+        b: f32::from_str(b.trim()).ok()?,
+    })
+}
+
+/// Effects take the strip length as a const generic parameter,
+/// so the simulator is compiled for a fixed set of common strip lengths.
+macro_rules! simulate_led_count {
+    ($options:expr, [$($n:literal),*]) => {
+        match $options.led_count {
+            $($n => simulate::<$n>($options),)*
+            count => Err(format!(
+                "unsupported LED count {count}, use one of: {}",
+                [$($n.to_string()),*].join(", "),
+            )),
+        }
+    };
+}
+
+fn simulate<const N: usize>(options: &Options) -> Result<(), String> {
+    let mut effect = new_effect::<N>(&options.effect)
+        .ok_or_else(|| format!("unknown effect {}, use one of: {}", options.effect, EFFECTS.join(", ")))?;
+    effect.configure(options.params);
+
+    let mut frames: Vec<[smart_leds::RGB8; N]> = Vec::with_capacity(options.frames);
+    let mut stdout = std::io::stdout().lock();
+
+    for strip in effect.take(options.frames) {
+        let pixels = strip.to_rgb8();
+        let line = render_terminal(&pixels);
+        if options.step {
+            writeln!(stdout, "{line}").map_err(|err| err.to_string())?;
+        } else {
+            write!(stdout, "\r{line}").map_err(|err| err.to_string())?;
+            stdout.flush().map_err(|err| err.to_string())?;
+            std::thread::sleep(Duration::from_millis(FRAME_INTERVAL_MS));
+        }
+        frames.push(pixels);
+    }
+
+    if !options.step {
+        writeln!(stdout).map_err(|err| err.to_string())?;
+    }
+
+    write_ppm(&options.output, &frames).map_err(|err| format!("{}: {err}", options.output))?;
+    eprintln!("Wrote {} frames of {} LEDs to {}", frames.len(), N, options.output);
+
+    Ok(())
+}
+
+/// Render one frame as a row of truecolor blocks, two characters per LED.
+fn render_terminal(pixels: &[smart_leds::RGB8]) -> String {
+    let mut line = String::with_capacity(pixels.len() * 24);
+    for pixel in pixels {
+        line += &format!("\x1b[48;2;{};{};{}m  ", pixel.r, pixel.g, pixel.b);
+    }
+    line += "\x1b[0m";
+    line
+}
+
+/// Write frames as a binary PPM image, one frame per row.
+fn write_ppm<const N: usize>(path: &str, frames: &[[smart_leds::RGB8; N]]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", N, frames.len())?;
+    for pixel in frames.iter().flatten() {
+        file.write_all(&[pixel.r, pixel.g, pixel.b])?;
+    }
+    file.flush()
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = simulate_led_count!(&options, [
+        1, 8, 10, 12, 16, 20, 24, 30, 32, 40, 48, 50, 60, 64, 72, 96, 100, 120, 144, 150, 180, 240, 300
+    ]);
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("nuled-sim: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
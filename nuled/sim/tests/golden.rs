@@ -0,0 +1,132 @@
+//! Golden-frame regression tests for all effects.
+//!
+//! Every effect is run for a fixed number of frames with fixed parameters,
+//! and the output is compared against the frames stored in `tests/golden`.
+//!
+//! When a change in output is intentional, regenerate the golden files with:
+//!
+//!     NULED_UPDATE_GOLDEN=1 cargo test --test golden
+
+use nuled_sim::color::RGB;
+use nuled_sim::effect::Params;
+use nuled_sim::{new_effect, EFFECTS};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+const LED_COUNT: usize = 16;
+const FRAMES: usize = 48;
+
+/// Maximum allowed difference per color component, on the 0..255 scale.
+const TOLERANCE: f32 = 0.05;
+
+const UPDATE_ENV: &str = "NULED_UPDATE_GOLDEN";
+
+fn params() -> Params {
+    Params {
+        color1: RGB { r: 255.0, g: 96.0, b: 0.0 },
+        color2: RGB { r: 0.0, g: 64.0, b: 255.0 },
+        chroma: 0.8,
+        luminance: 0.8,
+        size: 0.5,
+        speed: 0.5,
+        transition: 0.0,
+    }
+}
+
+fn golden_path(effect: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{effect}.txt"))
+}
+
+fn render(effect: &str) -> Vec<Vec<RGB>> {
+    let mut effect = new_effect::<LED_COUNT>(effect).expect("unknown effect");
+    effect.configure(params());
+    effect.take(FRAMES).map(|strip| strip.0.to_vec()).collect()
+}
+
+/// Golden files contain one frame per line, with space separated `r,g,b` values for each LED.
+fn serialize(effect: &str, frames: &[Vec<RGB>]) -> String {
+    let mut s = String::new();
+    writeln!(s, "# Golden frames for `{effect}` with {LED_COUNT} LEDs.").unwrap();
+    writeln!(s, "# Regenerate with {UPDATE_ENV}=1 cargo test --test golden").unwrap();
+    for frame in frames {
+        let pixels: Vec<String> = frame
+            .iter()
+            .map(|rgb| format!("{:.3},{:.3},{:.3}", rgb.r, rgb.g, rgb.b))
+            .collect();
+        writeln!(s, "{}", pixels.join(" ")).unwrap();
+    }
+    s
+}
+
+fn parse(s: &str) -> Vec<Vec<[f32; 3]>> {
+    s.lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            line.split_whitespace()
+                .map(|pixel| {
+                    let mut rgb = [0.0; 3];
+                    let mut parts = pixel.split(',');
+                    for component in rgb.iter_mut() {
+                        *component = parts.next().and_then(|x| x.parse().ok()).expect("malformed golden file");
+                    }
+                    rgb
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Compare the output of an effect against its golden file, or regenerate the file.
+fn check_golden(effect: &str) -> Result<(), String> {
+    let frames = render(effect);
+    let path = golden_path(effect);
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, serialize(effect, &frames)).unwrap();
+        return Ok(());
+    }
+
+    let golden = std::fs::read_to_string(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let golden = parse(&golden);
+
+    if golden.len() != frames.len() {
+        return Err(format!("{effect}: expected {} frames, got {}", golden.len(), frames.len()));
+    }
+
+    for (index, (expected, actual)) in golden.iter().zip(frames.iter()).enumerate() {
+        if expected.len() != actual.len() {
+            return Err(format!("{effect}: frame {index}: expected {} LEDs, got {}", expected.len(), actual.len()));
+        }
+        for (led, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            let actual = [actual.r, actual.g, actual.b];
+            let within_tolerance = expected
+                .iter()
+                .zip(actual.iter())
+                .all(|(e, a)| (e - a).abs() <= TOLERANCE);
+            if !within_tolerance {
+                return Err(format!("{effect}: frame {index}, LED {led}: expected {expected:?}, got {actual:?}"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn golden_frames() {
+    let failures: Vec<String> = EFFECTS
+        .iter()
+        .filter_map(|effect| check_golden(effect).err())
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "effect output differs from golden files:\n{}\n\
+        If this change is intentional, regenerate with {UPDATE_ENV}=1 cargo test --test golden",
+        failures.join("\n"),
+    );
+}
@@ -0,0 +1,128 @@
+/// HTTP/JSON control API.
+///
+/// Served on port 80 of the WiFi station interface, next to MQTT:
+///
+/// * `GET /api/state` returns the current state, in the same format as the MQTT `state` topic.
+/// * `POST /api/state` applies a JSON command, in the same format as the MQTT `set` topic,
+///   and returns the new state.
+/// * `GET /api/effects` returns the names of all effects.
+/// * `GET /api/info` returns the firmware version, LED count and uptime in seconds.
+
+use core::ptr::addr_of_mut;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::Stack;
+use embassy_time::{Duration, Instant};
+use esp_wifi::wifi::{WifiDevice, WifiStaDevice};
+use serde::Serialize;
+use crate::config::LED_COUNT;
+use crate::control;
+use crate::http::{self, Request};
+use crate::identity::Identity;
+use crate::persist;
+use crate::state::{Effect, JsonCommand, JsonState};
+use crate::settings::Settings;
+
+const PORT: u16 = 80;
+
+type StaStack = Stack<WifiDevice<'static, WifiStaDevice>>;
+
+static mut RX_BUFFER: [u8; 1536] = [0; 1536];
+static mut TX_BUFFER: [u8; 1536] = [0; 1536];
+static mut REQUEST: [u8; 1024] = [0; 1024];
+static mut RESPONSE: [u8; 1024] = [0; 1024];
+
+#[derive(Serialize)]
+struct Info<'a> {
+    version: &'static str,
+    device_id: &'a str,
+    led_count: usize,
+    uptime: u64,
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: &'static str,
+}
+
+#[embassy_executor::task]
+pub async fn api_task(stack: &'static StaStack, identity: &'static Identity, settings: &'static Settings) {
+    let request_buf = unsafe { &mut *addr_of_mut!(REQUEST) };
+    let response_buf = unsafe { &mut *addr_of_mut!(RESPONSE) };
+
+    loop {
+        let mut socket = TcpSocket::new(
+            stack,
+            unsafe { &mut *addr_of_mut!(RX_BUFFER) },
+            unsafe { &mut *addr_of_mut!(TX_BUFFER) },
+        );
+        socket.set_timeout(Some(Duration::from_secs(10)));
+
+        if let Err(err) = socket.accept(PORT).await {
+            warn!("API accept failed: {:?}", err);
+            continue;
+        }
+
+        let response_buf = &mut *response_buf;
+        let result = http::serve(&mut socket, request_buf, "application/json", move |request| {
+            debug!("API request: {} {}", request.method, request.path);
+            let out = response_buf;
+            let (status, len) = respond(request, identity, settings, out).unwrap_or_else(|| {
+                error!("API response serialization failed");
+                (500, 0)
+            });
+            (status, &out[..len])
+        }).await;
+
+        match result {
+            Ok(()) => socket.close(),
+            Err(err) => {
+                warn!("API request failed: {:?}", err);
+                socket.abort();
+            }
+        }
+    }
+}
+
+/// Handle a request, and write the response body into `out`.
+/// Returns the status code and the length of the body.
+fn respond(request: &Request, identity: &Identity, settings: &Settings, out: &mut [u8]) -> Option<(u16, usize)> {
+    match (request.method, request.path) {
+        ("GET", "/api/state") => json(200, &JsonState::from(&control::state()), out),
+        ("POST", "/api/state") => {
+            let Ok((command, _)) = serde_json_core::from_slice::<JsonCommand>(request.body) else {
+                return json(400, &ApiError { error: "invalid JSON command" }, out);
+            };
+            match control::update(|state| command.apply(state)) {
+                Ok(state) => json(200, &JsonState::from(&state), out),
+                Err(_) => json(400, &ApiError { error: "invalid value" }, out),
+            }
+        }
+        ("POST", "/api/preset") => {
+            let state = control::state();
+            match persist::save_preset(&state) {
+                Ok(()) => json(200, &JsonState::from(&state), out),
+                Err(err) => {
+                    error!("Unable to save preset: {:?}", err);
+                    json(500, &ApiError { error: "unable to save preset" }, out)
+                }
+            }
+        }
+        ("GET", "/api/effects") => json(200, &Effect::ALL, out),
+        ("GET", "/api/info") => {
+            let info = Info {
+                version: env!("CARGO_PKG_VERSION"),
+                device_id: &identity.device_id,
+                led_count: LED_COUNT.min(settings.led_count as usize),
+                uptime: Instant::now().as_secs(),
+            };
+            json(200, &info, out)
+        }
+        (_, "/api/state" | "/api/preset" | "/api/effects" | "/api/info") => json(405, &ApiError { error: "method not allowed" }, out),
+        _ => json(404, &ApiError { error: "not found" }, out),
+    }
+}
+
+fn json(status: u16, value: &impl Serialize, out: &mut [u8]) -> Option<(u16, usize)> {
+    let len = serde_json_core::to_slice(value, out).ok()?;
+    Some((status, len))
+}
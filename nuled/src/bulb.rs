@@ -0,0 +1,163 @@
+/// LIFX bulb emulation on the WiFi station interface, see `lifx`.
+///
+/// The LIFX apps and integrations find the strip through discovery, and see it as a single
+/// color bulb. Setting a color switches to the solid effect. The label starts out as the
+/// device id, and changes to it are kept until the next restart.
+
+use core::convert::Infallible;
+use core::ptr::addr_of_mut;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Stack};
+use esp_wifi::wifi::{WifiDevice, WifiStaDevice};
+use heapless::String;
+use crate::color::{Kelvin, HCL, RGB};
+use crate::control;
+use crate::identity::Identity;
+use crate::lifx::{self, Header, Hsbk, Request, Response, LABEL_SIZE};
+use crate::state::Effect;
+
+/// Color temperature reported until a color is set.
+const DEFAULT_KELVIN: u16 = 3500;
+
+type StaStack = Stack<WifiDevice<'static, WifiStaDevice>>;
+
+static mut RX_META: [PacketMetadata; 4] = [PacketMetadata::EMPTY; 4];
+static mut RX_BUFFER: [u8; 512] = [0; 512];
+static mut TX_META: [PacketMetadata; 4] = [PacketMetadata::EMPTY; 4];
+static mut TX_BUFFER: [u8; 512] = [0; 512];
+static mut PACKET: [u8; 128] = [0; 128];
+static mut RESPONSE: [u8; 128] = [0; 128];
+
+/// State that only exists for LIFX clients.
+struct Bulb {
+    label: String<LABEL_SIZE>,
+    /// Color temperature of the last color set, as colors are stored without it.
+    kelvin: u16,
+}
+
+#[embassy_executor::task]
+pub async fn bulb_task(stack: &'static StaStack, identity: &'static Identity, mac: [u8; 6]) {
+    let packet = unsafe { &mut *addr_of_mut!(PACKET) };
+    let response_buf = unsafe { &mut *addr_of_mut!(RESPONSE) };
+
+    let mut socket = UdpSocket::new(
+        stack,
+        unsafe { &mut *addr_of_mut!(RX_META) },
+        unsafe { &mut *addr_of_mut!(RX_BUFFER) },
+        unsafe { &mut *addr_of_mut!(TX_META) },
+        unsafe { &mut *addr_of_mut!(TX_BUFFER) },
+    );
+
+    if let Err(err) = socket.bind(lifx::PORT) {
+        error!("Unable to bind LIFX emulation to port {}: {:?}", lifx::PORT, err);
+        return;
+    }
+
+    info!("Emulating a LIFX bulb on port {}.", lifx::PORT);
+
+    let mut bulb = Bulb {
+        label: identity.device_id.clone(),
+        kelvin: DEFAULT_KELVIN,
+    };
+
+    loop {
+        let Ok((len, remote)) = socket.recv_from(packet).await else {
+            continue;
+        };
+        let Some((header, payload)) = lifx::parse_header(&packet[..len]) else {
+            continue;
+        };
+        if !header.is_for(mac) {
+            continue;
+        }
+
+        if header.ack_required {
+            send(&mut socket, remote, &header, mac, &Response::Acknowledgement, response_buf).await;
+        }
+
+        let Some(request) = Request::parse(header.message_type, payload) else {
+            debug!("Ignoring LIFX message type {}", header.message_type);
+            continue;
+        };
+
+        debug!("LIFX request: {:?}", request);
+
+        if let Some(response) = handle(request, &header, &mut bulb) {
+            send(&mut socket, remote, &header, mac, &response, response_buf).await;
+        }
+    }
+}
+
+/// Apply a request. Returns the response, if one is due.
+fn handle<'a>(request: Request, header: &Header, bulb: &'a mut Bulb) -> Option<Response<'a>> {
+    let response = match request {
+        Request::GetService => return Some(Response::StateService),
+        Request::GetPower => return Some(Response::StatePower { on: control::state().output_params.on }),
+        Request::GetLightPower => return Some(Response::StateLightPower { on: control::state().output_params.on }),
+        Request::GetLabel => return Some(Response::StateLabel(&bulb.label)),
+        Request::GetVersion => return Some(Response::StateVersion),
+        Request::GetColor => return Some(light_state(bulb)),
+        Request::SetPower { on } => {
+            set_power(on);
+            Response::StatePower { on }
+        }
+        Request::SetLightPower { on } => {
+            set_power(on);
+            Response::StateLightPower { on }
+        }
+        Request::SetLabel(label) => {
+            bulb.label.clear();
+            // Labels are at most `LABEL_SIZE` bytes.
+            let _ = bulb.label.push_str(label);
+            Response::StateLabel(&bulb.label)
+        }
+        Request::SetColor(color) => {
+            bulb.kelvin = color.kelvin;
+            let _ = control::update(|state| {
+                state.effect = Effect::Solid;
+                state.led_effect_params.color1 = to_rgb(color);
+                Ok::<_, Infallible>(())
+            });
+            light_state(bulb)
+        }
+    };
+
+    // Set messages are only answered with the new state if the client asks for it.
+    header.res_required.then_some(response)
+}
+
+fn set_power(on: bool) {
+    let _ = control::update(|state| {
+        state.output_params.on = on;
+        Ok::<_, Infallible>(())
+    });
+}
+
+fn light_state(bulb: &Bulb) -> Response<'_> {
+    let state = control::state();
+    Response::LightState {
+        color: Hsbk::from_hcl(HCL::from(state.led_effect_params.color1), bulb.kelvin),
+        on: state.output_params.on,
+        label: &bulb.label,
+    }
+}
+
+/// Whites, without saturation, use the color temperature like the MQTT `temperature` topic.
+fn to_rgb(color: Hsbk) -> RGB {
+    if color.saturation > 0 {
+        return color.to_hcl().into();
+    }
+    let white = RGB::from(Kelvin(color.kelvin as f32));
+    let brightness = color.brightness as f32 / 65535.0;
+    RGB { r: white.r * brightness, g: white.g * brightness, b: white.b * brightness }
+}
+
+async fn send(socket: &mut UdpSocket<'_>, remote: IpEndpoint, header: &Header, mac: [u8; 6], response: &Response<'_>, out: &mut [u8]) {
+    let Some(len) = lifx::encode(header, mac, response, out) else {
+        error!("LIFX response does not fit: {:?}", response);
+        return;
+    };
+    if let Err(err) = socket.send_to(&out[..len], remote).await {
+        warn!("LIFX response failed: {:?}", err);
+    }
+}
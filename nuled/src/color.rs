@@ -1,6 +1,7 @@
 /// Color manipulation library.
 ///
 /// Allows conversion between RGB, XYZ and CIELUV color spaces,
+/// conversion from color temperature to any of them,
 /// as well as creation of gradients through the CIELUV color space.
 
 use num_traits::Float;
@@ -12,7 +13,7 @@ use num_traits::Float;
 /// * `r` is the amount of red,
 /// * `g` is the amount of green,
 /// * `b` is the amount of blue.
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct RGB {
     pub r: f32,
     pub g: f32,
@@ -21,11 +22,7 @@ pub struct RGB {
 
 impl From<XYZ> for RGB {
     fn from(xyz: XYZ) -> Self {
-        // sYCC: Amendment 1 to IEC 61966-2-1:1999.
-        // Higher conversion precision with seven decimals.
-        let r = 3.2406255 * xyz.x - 1.5372080 * xyz.y - 0.4986286 * xyz.z;
-        let g = -0.9689307 * xyz.x + 1.8758561 * xyz.y + 0.0415175 * xyz.z;
-        let b = 0.0557101 * xyz.x - 0.2040211 * xyz.y + 1.0570959 * xyz.z;
+        let (r, g, b) = xyz.to_linear_rgb();
 
         Self {
             r: (linear_to_srgb(r) * 255.0).clamp(0.0, 255.0),
@@ -42,6 +39,13 @@ impl From<CIELUV> for RGB {
     }
 }
 
+/// Conversions from color temperature to RGB is done through the XYZ color space.
+impl From<Kelvin> for RGB {
+    fn from(kelvin: Kelvin) -> Self {
+        XYZ::from(kelvin).into()
+    }
+}
+
 /// Conversions to and from HCL/RGB is done via the CIELUV color space.
 impl From<HCL> for RGB {
     fn from(hcl: HCL) -> Self {
@@ -59,15 +63,57 @@ impl Into<smart_leds::RGB8> for RGB {
     }
 }
 
-type RGBW = smart_leds::RGBW<u8,u8>;
+pub type RGBW = smart_leds::RGBW<u8,u8>;
+
+/// Strategy for driving the white LED on RGBW strips.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum WhiteMode {
+    /// The white LED is never used.
+    Off,
+    /// The gray component common to all three channels is moved to the white LED.
+    /// Assumes that the white LED has the same color as full RGB white.
+    #[default]
+    MinChannel,
+    /// The white LED is assumed to have the given color when driven at full power.
+    /// As much of that color as possible is moved from the RGB channels to the white LED,
+    /// which keeps the hue of warm or cool white LEDs from shifting the output color.
+    Calibrated(RGB),
+}
+
+impl RGB {
+    /// Split into RGB and white channels, extracting the white channel according to `mode`.
+    /// The white channel is returned in the range `0.0..255.0`.
+    pub fn extract_white(self, mode: WhiteMode) -> (RGB, f32) {
+        let white = match mode {
+            WhiteMode::Off => return (self, 0.0),
+            WhiteMode::MinChannel => RGB { r: 255.0, g: 255.0, b: 255.0 },
+            WhiteMode::Calibrated(white) => white,
+        };
+
+        // Fraction of full white LED power that fits inside the requested color.
+        let w = (self.r / white.r)
+            .min(self.g / white.g)
+            .min(self.b / white.b)
+            .clamp(0.0, 1.0);
+
+        let rgb = RGB {
+            r: (self.r - w * white.r).clamp(0.0, 255.0),
+            g: (self.g - w * white.g).clamp(0.0, 255.0),
+            b: (self.b - w * white.b).clamp(0.0, 255.0),
+        };
 
-impl Into<RGBW> for RGB {
-    fn into(self) -> RGBW {
+        (rgb, w * 255.0)
+    }
+
+    /// Convert to RGBW, extracting the white channel according to `mode`.
+    #[allow(dead_code)]
+    pub fn to_rgbw(self, mode: WhiteMode) -> RGBW {
+        let (rgb, white) = self.extract_white(mode);
         RGBW {
-            r: self.r.round() as u8,
-            g: self.g.round() as u8,
-            b: self.b.round() as u8,
-            a: smart_leds::White(0),
+            r: rgb.r.round() as u8,
+            g: rgb.g.round() as u8,
+            b: rgb.b.round() as u8,
+            a: smart_leds::White(white.round() as u8),
         }
     }
 }
@@ -88,6 +134,18 @@ pub struct XYZ {
     z: f32,
 }
 
+impl XYZ {
+    /// Convert to linear RGB, without companding or clamping.
+    fn to_linear_rgb(self) -> (f32, f32, f32) {
+        // sYCC: Amendment 1 to IEC 61966-2-1:1999.
+        // Higher conversion precision with seven decimals.
+        let r = 3.2406255 * self.x - 1.537208 * self.y - 0.4986286 * self.z;
+        let g = -0.9689307 * self.x + 1.875856 * self.y + 0.0415175 * self.z;
+        let b = 0.0557101 * self.x - 0.2040211 * self.y + 1.0570959 * self.z;
+        (r, g, b)
+    }
+}
+
 // Constants for D65 white point
 const X_REF: f32 = 95.047;
 const Y_REF: f32 = 100.0;
@@ -110,7 +168,29 @@ impl From<RGB> for XYZ {
         Self {
             x: r * 0.4124564 + g * 0.3575761 + b * 0.1804375,
             y: r * 0.2126729 + g * 0.7151522 + b * 0.0721750,
-            z: r * 0.0193339 + g * 0.1191920 + b * 0.9503041,
+            z: r * 0.0193339 + g * 0.119192 + b * 0.9503041,
+        }
+    }
+}
+
+impl From<Kelvin> for XYZ {
+    /// Returns the brightest color with the chromaticity of the given temperature
+    /// that can be displayed without clipping any of the RGB channels.
+    fn from(kelvin: Kelvin) -> Self {
+        let (x, y) = kelvin.chromaticity();
+        let xyz = XYZ {
+            x: x / y,
+            y: 1.0,
+            z: (1.0 - x - y) / y,
+        };
+
+        let (r, g, b) = xyz.to_linear_rgb();
+        let scale = 1.0 / r.max(g).max(b);
+
+        XYZ {
+            x: xyz.x * scale,
+            y: xyz.y * scale,
+            z: xyz.z * scale,
         }
     }
 }
@@ -121,8 +201,8 @@ impl From<CIELUV> for XYZ {
             return XYZ { x: 0.0, y: 0.0, z: 0.0 };
         }
 
-        let u_prime = cieluv.u / (13.0 * cieluv.l) + 0.19783000664283;
-        let v_prime = cieluv.v / (13.0 * cieluv.l) + 0.46831999493879;
+        let u_prime = cieluv.u / (13.0 * cieluv.l) + 0.19783;
+        let v_prime = cieluv.v / (13.0 * cieluv.l) + 0.46831998;
 
         let y = if cieluv.l > 8.0 {
             Y_REF * ((cieluv.l + 16.0) / 116.0).powi(3)
@@ -195,6 +275,13 @@ impl From<RGB> for CIELUV {
     }
 }
 
+/// Conversions from color temperature to CIELUV is done through the XYZ color space.
+impl From<Kelvin> for CIELUV {
+    fn from(kelvin: Kelvin) -> Self {
+        XYZ::from(kelvin).into()
+    }
+}
+
 impl From<HCL> for CIELUV {
     fn from(hcl: HCL) -> Self {
         let h_rad = hcl.h.to_radians(); // Convert hue to radians
@@ -204,6 +291,24 @@ impl From<HCL> for CIELUV {
     }
 }
 
+impl From<CIELUV> for HCL {
+    fn from(cieluv: CIELUV) -> Self {
+        let h = cieluv.v.atan2(cieluv.u).to_degrees();
+        HCL {
+            h: if h < 0.0 { h + 360.0 } else { h },
+            c: (cieluv.u * cieluv.u + cieluv.v * cieluv.v).sqrt(),
+            l: cieluv.l,
+        }
+    }
+}
+
+/// Conversions to and from HCL/RGB is done via the CIELUV color space.
+impl From<RGB> for HCL {
+    fn from(rgb: RGB) -> Self {
+        CIELUV::from(rgb).into()
+    }
+}
+
 /// CIELCh, also known as HCL, is a cylindrical representation of the CIELUV color space.
 ///
 /// * `h` is the hue, ranging from `0.0..360.0`,
@@ -217,6 +322,35 @@ pub struct HCL {
     pub l: f32,
 }
 
+/// Color temperature of a black body radiator, in Kelvin.
+///
+/// Values are clamped to the range `1000.0..10000.0`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Kelvin(pub f32);
+
+impl Kelvin {
+    pub const MIN: f32 = 1000.0;
+    pub const MAX: f32 = 10000.0;
+
+    /// CIE 1931 chromaticity coordinates `(x, y)` of the Planckian locus.
+    ///
+    /// Uses the rational approximation in the CIE 1960 UCS from
+    /// Krystek, M. (1985), "An algorithm to calculate correlated colour temperature",
+    /// which is accurate to within 1e-4 for temperatures between 1000K and 15000K.
+    fn chromaticity(&self) -> (f32, f32) {
+        let t = self.0.clamp(Self::MIN, Self::MAX);
+        let t2 = t * t;
+
+        let u = (0.86011773 + 1.5411826e-4 * t + 1.2864122e-7 * t2)
+            / (1.0 + 8.424202e-4 * t + 7.0814514e-7 * t2);
+        let v = (0.31739873 + 4.2280626e-5 * t + 4.2048168e-8 * t2)
+            / (1.0 - 2.8974182e-5 * t + 1.6145606e-7 * t2);
+
+        let d = 2.0 * u - 8.0 * v + 4.0;
+        (3.0 * u / d, 2.0 * v / d)
+    }
+}
+
 /// Helper function to perform linear interpolation
 #[inline]
 pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
@@ -243,4 +377,322 @@ fn linear_to_srgb(c: f32) -> f32 {
     } else {
         1.055 * c.powf(1.0 / 2.4) - 0.055
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tolerance for values on the 0.0..255.0 RGB scale.
+    /// The XYZ to RGB matrix is not an exact inverse of the RGB to XYZ matrix,
+    /// and dark channels amplify the error, but it must stay within half a step of 8-bit output.
+    const RGB_TOLERANCE: f32 = 0.5;
+
+    /// Going through CIELUV adds the error from its rounded constants,
+    /// which must still stay within one step of 8-bit output.
+    const RGB_LUV_TOLERANCE: f32 = 1.0;
+
+    /// Tolerance for XYZ values on the 0.0..1.0 scale.
+    const XYZ_TOLERANCE: f32 = 0.0001;
+
+    /// Tolerance for CIELUV values when checked against published references.
+    const LUV_TOLERANCE: f32 = 0.01;
+
+    /// The CIELUV to XYZ conversion uses rounded constants,
+    /// so the reverse direction is slightly less precise.
+    const LUV_TO_XYZ_TOLERANCE: f32 = 0.05;
+
+    /// The eight corners of the sRGB gamut.
+    const GAMUT_CORNERS: [(f32, f32, f32); 8] = [
+        (0.0, 0.0, 0.0),
+        (255.0, 0.0, 0.0),
+        (0.0, 255.0, 0.0),
+        (0.0, 0.0, 255.0),
+        (255.0, 255.0, 0.0),
+        (0.0, 255.0, 255.0),
+        (255.0, 0.0, 255.0),
+        (255.0, 255.0, 255.0),
+    ];
+
+    fn rgb(r: f32, g: f32, b: f32) -> RGB {
+        RGB { r, g, b }
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32, what: &str) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{what}: expected {expected}, got {actual} (tolerance {tolerance})",
+        );
+    }
+
+    fn assert_rgb_close(actual: RGB, expected: RGB, tolerance: f32) {
+        let what = format!("{expected:?} became {actual:?}");
+        assert_close(actual.r, expected.r, tolerance, &what);
+        assert_close(actual.g, expected.g, tolerance, &what);
+        assert_close(actual.b, expected.b, tolerance, &what);
+    }
+
+    fn assert_xyz_close(actual: XYZ, expected: XYZ, tolerance: f32) {
+        let what = format!("expected {expected:?}, got {actual:?}");
+        assert_close(actual.x, expected.x, tolerance, &what);
+        assert_close(actual.y, expected.y, tolerance, &what);
+        assert_close(actual.z, expected.z, tolerance, &what);
+    }
+
+    fn assert_luv_close(actual: CIELUV, expected: CIELUV, tolerance: f32) {
+        let what = format!("expected {expected:?}, got {actual:?}");
+        assert_close(actual.l, expected.l, tolerance, &what);
+        assert_close(actual.u, expected.u, tolerance, &what);
+        assert_close(actual.v, expected.v, tolerance, &what);
+    }
+
+    /// Deterministic sRGB colors, generated with a linear congruential generator.
+    fn random_colors(count: usize) -> impl Iterator<Item=RGB> {
+        let mut seed: u32 = 0x6e75_6c65;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 255.0
+        };
+        (0..count).map(move |_| rgb(next(), next(), next()))
+    }
+
+    #[test]
+    fn srgb_companding_is_reversible() {
+        for i in 0..=1000 {
+            let c = i as f32 / 1000.0;
+            assert_close(linear_to_srgb(srgb_to_linear(c)), c, 0.00001, "companding");
+        }
+    }
+
+    #[test]
+    fn srgb_companding_reference_values() {
+        // Middle gray, as tabulated for the sRGB transfer function.
+        assert_close(srgb_to_linear(128.0 / 255.0), 0.2158605, 0.000001, "sRGB 128");
+        assert_close(linear_to_srgb(0.0031308), 0.04045, 0.00001, "linear threshold");
+        assert_close(srgb_to_linear(0.04045), 0.0031308, 0.00001, "sRGB threshold");
+    }
+
+    #[test]
+    fn rgb_to_xyz_primaries() {
+        // Columns of the sRGB working space matrix.
+        // http://www.brucelindbloom.com/Eqn_RGB_XYZ_Matrix.html
+        let primaries = [
+            (rgb(255.0, 0.0, 0.0), XYZ { x: 0.4124564, y: 0.2126729, z: 0.0193339 }),
+            (rgb(0.0, 255.0, 0.0), XYZ { x: 0.3575761, y: 0.7151522, z: 0.119192 }),
+            (rgb(0.0, 0.0, 255.0), XYZ { x: 0.1804375, y: 0.0721750, z: 0.9503041 }),
+        ];
+        for (rgb, expected) in primaries {
+            assert_xyz_close(rgb.into(), expected, XYZ_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rgb_to_xyz_white_is_d65() {
+        let white = XYZ::from(rgb(255.0, 255.0, 255.0));
+        let expected = XYZ { x: X_REF / 100.0, y: Y_REF / 100.0, z: Z_REF / 100.0 };
+        assert_xyz_close(white, expected, XYZ_TOLERANCE);
+    }
+
+    #[test]
+    fn xyz_to_cieluv_reference_values() {
+        // CIELUV is computed relative to the white point with Y = 100.0.
+        // Reference values for the sRGB primaries and D65 white,
+        // http://www.brucelindbloom.com/index.html?ColorCalculator.html
+        let references = [
+            (XYZ { x: 41.24564, y: 21.26729, z: 1.93339 }, CIELUV { l: 53.2408, u: 175.0151, v: 37.7564 }),
+            (XYZ { x: 35.75761, y: 71.51522, z: 11.9192 }, CIELUV { l: 87.7347, u: -83.0776, v: 107.3985 }),
+            (XYZ { x: 18.04375, y: 7.21750, z: 95.03041 }, CIELUV { l: 32.2970, u: -9.4054, v: -130.3423 }),
+            (XYZ { x: X_REF, y: Y_REF, z: Z_REF }, CIELUV { l: 100.0, u: 0.0, v: 0.0 }),
+        ];
+        for (xyz, expected) in references {
+            assert_luv_close(xyz.into(), expected, LUV_TOLERANCE);
+            assert_xyz_close(XYZ::from(expected), xyz, LUV_TO_XYZ_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rgb_round_trip_through_xyz() {
+        for color in random_colors(1000).chain(GAMUT_CORNERS.map(|(r, g, b)| rgb(r, g, b))) {
+            assert_rgb_close(XYZ::from(color).into(), color, RGB_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rgb_round_trip_through_cieluv() {
+        for color in random_colors(1000).chain(GAMUT_CORNERS.map(|(r, g, b)| rgb(r, g, b))) {
+            assert_rgb_close(CIELUV::from(color).into(), color, RGB_LUV_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn black_is_zero_in_all_color_spaces() {
+        let black = rgb(0.0, 0.0, 0.0);
+        let xyz = XYZ::from(black);
+        assert_xyz_close(xyz, XYZ { x: 0.0, y: 0.0, z: 0.0 }, 0.0);
+        let luv = CIELUV::from(xyz);
+        assert_luv_close(luv, CIELUV { l: 0.0, u: 0.0, v: 0.0 }, 0.0);
+        assert_rgb_close(luv.into(), black, RGB_TOLERANCE);
+    }
+
+    #[test]
+    fn white_has_no_chromaticity() {
+        let luv = CIELUV::from(rgb(255.0, 255.0, 255.0));
+        assert_close(luv.u, 0.0, LUV_TOLERANCE, "u");
+        assert_close(luv.v, 0.0, LUV_TOLERANCE, "v");
+    }
+
+    #[test]
+    fn zero_luminance_is_black_regardless_of_chromaticity() {
+        // The `l == 0.0` branch must not divide by zero.
+        for (u, v) in [(0.0, 0.0), (1.0, -1.0), (-100.0, 100.0)] {
+            let xyz = XYZ::from(CIELUV { l: 0.0, u, v });
+            assert_xyz_close(xyz, XYZ { x: 0.0, y: 0.0, z: 0.0 }, 0.0);
+            assert_rgb_close(xyz.into(), rgb(0.0, 0.0, 0.0), RGB_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn luminance_is_continuous_at_threshold() {
+        // http://www.brucelindbloom.com/LContinuity.html
+        let below = XYZ::from(CIELUV { l: 7.9999, u: 0.0, v: 0.0 });
+        let above = XYZ::from(CIELUV { l: 8.0001, u: 0.0, v: 0.0 });
+        assert_close(below.y, above.y, 0.001, "Y around L = 8");
+    }
+
+    #[test]
+    fn out_of_gamut_colors_are_clamped() {
+        let rgb = RGB::from(XYZ { x: 2.0, y: 2.0, z: 2.0 });
+        assert_rgb_close(rgb, self::rgb(255.0, 255.0, 255.0), RGB_TOLERANCE);
+        let rgb = RGB::from(XYZ { x: 0.0, y: 0.0, z: 1.0 });
+        assert_eq!(rgb.r, 0.0);
+        assert_eq!(rgb.b, 255.0);
+    }
+
+    #[test]
+    fn hcl_to_cieluv() {
+        let luv = CIELUV::from(HCL { h: 0.0, c: 1.0, l: 0.5 });
+        assert_luv_close(luv, CIELUV { l: 0.5, u: 1.0, v: 0.0 }, 0.00001);
+        let luv = CIELUV::from(HCL { h: 90.0, c: 2.0, l: 0.5 });
+        assert_luv_close(luv, CIELUV { l: 0.5, u: 0.0, v: 2.0 }, 0.00001);
+        let luv = CIELUV::from(HCL { h: 225.0, c: 2.0_f32.sqrt(), l: 0.5 });
+        assert_luv_close(luv, CIELUV { l: 0.5, u: -1.0, v: -1.0 }, 0.00001);
+    }
+
+    #[test]
+    fn cieluv_to_hcl() {
+        let hcl = HCL::from(CIELUV { l: 0.5, u: 0.0, v: 2.0 });
+        assert_close(hcl.h, 90.0, 0.001, "hue");
+        assert_close(hcl.c, 2.0, 0.00001, "chroma");
+        assert_close(hcl.l, 0.5, 0.0, "luminance");
+        let hcl = HCL::from(CIELUV { l: 0.5, u: -1.0, v: -1.0 });
+        assert_close(hcl.h, 225.0, 0.001, "hue in the third quadrant");
+        assert_close(hcl.c, 2.0_f32.sqrt(), 0.00001, "chroma");
+    }
+
+    #[test]
+    fn interpolate_endpoints() {
+        let start = CIELUV::from(rgb(255.0, 0.0, 0.0));
+        let end = CIELUV::from(rgb(0.0, 0.0, 255.0));
+        assert_luv_close(start.interpolate(&end, 0.0), start, 0.0);
+        assert_luv_close(start.interpolate(&end, 1.0), end, 0.0001);
+        let middle = start.interpolate(&end, 0.5);
+        assert_close(middle.l, (start.l + end.l) / 2.0, 0.0001, "middle luminance");
+    }
+
+    fn xy(xyz: XYZ) -> (f32, f32) {
+        let sum = xyz.x + xyz.y + xyz.z;
+        (xyz.x / sum, xyz.y / sum)
+    }
+
+    #[test]
+    fn kelvin_chromaticity_reference_values() {
+        // Blackbody chromaticity, as tabulated by Mitchell Charity and used in `blinken`.
+        // http://www.vendian.org/mncharity/dir3/blackbody/
+        // The table is computed for the CIE 1964 10° observer,
+        // which is why it differs slightly from the CIE 1931 locus.
+        let references = [
+            (1000.0, (0.6499, 0.3474)),
+            (2000.0, (0.5267, 0.4173)),
+            (2700.0, (0.4614, 0.4158)),
+            (4000.0, (0.3827, 0.3820)),
+            (5000.0, (0.3473, 0.3561)),
+            (6500.0, (0.3155, 0.3270)),
+        ];
+        for (kelvin, (x, y)) in references {
+            let (actual_x, actual_y) = xy(Kelvin(kelvin).into());
+            let what = format!("{kelvin}K");
+            assert_close(actual_x, x, 0.01, &what);
+            assert_close(actual_y, y, 0.01, &what);
+        }
+    }
+
+    #[test]
+    fn kelvin_is_clamped() {
+        assert_eq!(xy(Kelvin(500.0).into()), xy(Kelvin(Kelvin::MIN).into()));
+        assert_eq!(xy(Kelvin(20000.0).into()), xy(Kelvin(Kelvin::MAX).into()));
+    }
+
+    #[test]
+    fn kelvin_to_rgb() {
+        // Warm white is dominated by red, and uses the full brightness of the red channel.
+        let warm = RGB::from(Kelvin(2700.0));
+        assert_close(warm.r, 255.0, RGB_TOLERANCE, "2700K red");
+        assert!(warm.r > warm.g && warm.g > warm.b, "2700K is not warm: {warm:?}");
+
+        // Daylight is close to the D65 white point.
+        let daylight = RGB::from(Kelvin(6500.0));
+        assert!(daylight.r > 240.0 && daylight.g > 240.0 && daylight.b > 240.0, "6500K is not white: {daylight:?}");
+
+        // Cold white is dominated by blue.
+        let cold = RGB::from(Kelvin(10000.0));
+        assert_close(cold.b, 255.0, RGB_TOLERANCE, "10000K blue");
+        assert!(cold.b > cold.g && cold.g > cold.r, "10000K is not cold: {cold:?}");
+    }
+
+    #[test]
+    fn kelvin_to_cieluv_has_full_luminance() {
+        for kelvin in [1000.0, 2700.0, 6500.0, 10000.0] {
+            let luv = CIELUV::from(Kelvin(kelvin));
+            assert!(luv.l > 0.0, "{kelvin}K: {luv:?}");
+        }
+    }
+
+    fn rgbw(rgbw: RGBW) -> (u8, u8, u8, u8) {
+        (rgbw.r, rgbw.g, rgbw.b, rgbw.a.0)
+    }
+
+    #[test]
+    fn rgbw_without_white_extraction() {
+        let color = rgb(200.0, 150.0, 100.0);
+        assert_eq!(rgbw(color.to_rgbw(WhiteMode::Off)), (200, 150, 100, 0));
+    }
+
+    #[test]
+    fn rgbw_min_channel_extraction() {
+        assert_eq!(rgbw(rgb(255.0, 255.0, 255.0).to_rgbw(WhiteMode::MinChannel)), (0, 0, 0, 255));
+        assert_eq!(rgbw(rgb(200.0, 150.0, 100.0).to_rgbw(WhiteMode::MinChannel)), (100, 50, 0, 100));
+        assert_eq!(rgbw(rgb(255.0, 0.0, 0.0).to_rgbw(WhiteMode::MinChannel)), (255, 0, 0, 0));
+        assert_eq!(rgbw(rgb(0.0, 0.0, 0.0).to_rgbw(WhiteMode::MinChannel)), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn rgbw_calibrated_extraction() {
+        let warm_white = WhiteMode::Calibrated(rgb(255.0, 180.0, 100.0));
+
+        // The exact color of the white LED is produced by the white LED alone.
+        assert_eq!(rgbw(rgb(255.0, 180.0, 100.0).to_rgbw(warm_white)), (0, 0, 0, 255));
+        assert_eq!(rgbw(rgb(127.5, 90.0, 50.0).to_rgbw(warm_white)), (0, 0, 0, 128));
+
+        // Cooler colors than the white LED keep the blue remainder in the RGB channels.
+        assert_eq!(rgbw(rgb(255.0, 255.0, 255.0).to_rgbw(warm_white)), (0, 75, 155, 255));
+
+        // Saturated colors do not use the white LED.
+        assert_eq!(rgbw(rgb(0.0, 0.0, 255.0).to_rgbw(warm_white)), (0, 0, 255, 0));
+    }
+
+    #[test]
+    fn rgb_to_rgb8_rounds() {
+        let rgb8: smart_leds::RGB8 = rgb(0.4, 127.5, 254.6).into();
+        assert_eq!((rgb8.r, rgb8.g, rgb8.b), (0, 128, 255));
+    }
+}
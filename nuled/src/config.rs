@@ -1,13 +1,80 @@
 /// NULED configuration.
 /// All variables must be known at compile time.
+/// WiFi, MQTT and LED count can be overridden at runtime, see `Settings`.
 
-pub const WIFI_SSID: &'static str = env!("NULED_WIFI_SSID");
-pub const WIFI_PASSWORD: &'static str = env!("NULED_WIFI_PASSWORD");
+use crate::color::{WhiteMode, RGB};
+use crate::rust_mqtt::client::client_config::MqttVersion;
+use crate::settings::{PowerOn, Settings};
+
+/// WiFi settings are optional, and can be entered in the provisioning portal instead.
+pub const WIFI_SSID: &'static str = match option_env!("NULED_WIFI_SSID") {
+    Some(ssid) => ssid,
+    None => "",
+};
+pub const WIFI_PASSWORD: &'static str = match option_env!("NULED_WIFI_PASSWORD") {
+    Some(password) => password,
+    None => "",
+};
 pub const MQTT_SERVER: &'static str = env!("NULED_MQTT_SERVER");
 pub const MQTT_PORT: u16 = must_parse_u16(env!("NULED_MQTT_PORT"));
 pub const MQTT_USERNAME: &'static str = env!("NULED_MQTT_USERNAME");
 pub const MQTT_PASSWORD: &'static str = env!("NULED_MQTT_PASSWORD");
+pub const MQTT_VERSION: MqttVersion = must_parse_mqtt_version(option_env!("NULED_MQTT_VERSION"));
+/// PEM certificate the MQTT server certificate is verified against, NUL terminated for mbedTLS.
+/// The path is relative to the crate directory.
+#[cfg(feature = "tls")]
+pub const MQTT_CA_CERT: &'static str = concat!(
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", env!("NULED_MQTT_CA_CERT"))),
+    "\0",
+);
+/// Device id, MQTT topic prefix and MQTT client id.
+/// Derived from the MAC address when not set, see `Identity`.
+pub const DEVICE_ID: Option<&'static str> = option_env!("NULED_DEVICE_ID");
+pub const MQTT_TOPIC_PREFIX: Option<&'static str> = option_env!("NULED_MQTT_TOPIC_PREFIX");
+pub const MQTT_CLIENT_ID: Option<&'static str> = option_env!("NULED_MQTT_CLIENT_ID");
+/// Maximum number of LEDs. A lower number can be configured at runtime.
 pub const LED_COUNT: usize = must_parse_led_count(env!("NULED_LED_COUNT")) as usize;
+pub const WHITE_MODE: WhiteMode = must_parse_white_mode(option_env!("NULED_WHITE_MODE"), option_env!("NULED_WHITE_COLOR"));
+pub const POWER_ON: PowerOn = must_parse_power_on(option_env!("NULED_POWER_ON"));
+pub const POWER_BUDGET_MA: u16 = match option_env!("NULED_POWER_BUDGET_MA") {
+    Some(s) => must_parse_u16(s),
+    None => 4000,
+};
+/// Placement of the LED strip among DMX universes received over E1.31 and Art-Net, see `dmx`.
+pub const REALTIME_UNIVERSE: u16 = match option_env!("NULED_REALTIME_UNIVERSE") {
+    Some(s) => must_parse_u16(s),
+    None => 1,
+};
+pub const REALTIME_CHANNEL: u16 = match option_env!("NULED_REALTIME_CHANNEL") {
+    Some(s) => must_parse_dmx_channel(s),
+    None => 1,
+};
+/// How long to show realtime pixel data after the last packet, before returning to the effect.
+pub const REALTIME_TIMEOUT_MS: u16 = match option_env!("NULED_REALTIME_TIMEOUT_MS") {
+    Some(s) => must_parse_u16(s),
+    None => 2500,
+};
+
+/// Flash offset of the runtime settings: the start of the `nvs` partition in the default partition table.
+pub const SETTINGS_FLASH_OFFSET: u32 = 0x9000;
+/// Flash offset of the persisted LED state, in the next sector of the `nvs` partition.
+pub const STATE_FLASH_OFFSET: u32 = 0xA000;
+/// Flash offset of the LED state to start with when `power_on` is `preset`, in the sector after the state.
+pub const PRESET_FLASH_OFFSET: u32 = 0xB000;
+
+/// Runtime settings to use when none are stored in flash.
+pub fn default_settings() -> Settings {
+    Settings {
+        wifi_ssid: WIFI_SSID.try_into().expect("WiFi SSID too long"),
+        wifi_password: WIFI_PASSWORD.try_into().expect("WiFi password too long"),
+        mqtt_server: MQTT_SERVER.try_into().expect("MQTT server name too long"),
+        mqtt_port: MQTT_PORT,
+        mqtt_username: MQTT_USERNAME.try_into().expect("MQTT username too long"),
+        mqtt_password: MQTT_PASSWORD.try_into().expect("MQTT password too long"),
+        led_count: LED_COUNT as u16,
+        power_on: POWER_ON,
+    }
+}
 
 const fn must_parse_u16(s: &str) -> u16 {
     match u16::from_str_radix(s, 10) {
@@ -21,4 +88,97 @@ const fn must_parse_led_count(s: &str) -> usize {
         0 => panic!("LED count must be greater than zero"),
         x => x as usize,
     }
-}
\ No newline at end of file
+}
+
+/// DMX channels are numbered from 1, and a pixel needs three channels.
+const fn must_parse_dmx_channel(s: &str) -> u16 {
+    match must_parse_u16(s) {
+        x @ 1..=510 => x,
+        _ => panic!("DMX channel must be in the range 1..510"),
+    }
+}
+
+/// White mode is one of `off`, `min` or `calibrated`, and defaults to `min`.
+/// Calibrated mode requires the color of the white LED.
+const fn must_parse_white_mode(mode: Option<&str>, color: Option<&str>) -> WhiteMode {
+    let mode = match mode {
+        Some(mode) => mode,
+        None => "",
+    };
+    if str_eq(mode, "off") {
+        WhiteMode::Off
+    } else if str_eq(mode, "min") || str_eq(mode, "") {
+        WhiteMode::MinChannel
+    } else if str_eq(mode, "calibrated") {
+        match color {
+            Some(color) => WhiteMode::Calibrated(must_parse_rgb(color)),
+            None => panic!("calibrated white mode requires the white LED color"),
+        }
+    } else {
+        panic!("white mode must be one of off, min or calibrated")
+    }
+}
+
+/// MQTT protocol version is one of `5` or `3.1.1`, and defaults to `5`.
+const fn must_parse_mqtt_version(s: Option<&str>) -> MqttVersion {
+    match s {
+        None => MqttVersion::MQTTv5,
+        Some(s) if str_eq(s, "5") => MqttVersion::MQTTv5,
+        Some(s) if str_eq(s, "3.1.1") => MqttVersion::MQTTv3,
+        Some(_) => panic!("MQTT version must be one of 5 or 3.1.1"),
+    }
+}
+
+/// Power-on behavior is one of `last`, `preset` or `off`, and defaults to `last`.
+const fn must_parse_power_on(s: Option<&str>) -> PowerOn {
+    match s {
+        None => PowerOn::Last,
+        Some(s) if str_eq(s, "last") => PowerOn::Last,
+        Some(s) if str_eq(s, "preset") => PowerOn::Preset,
+        Some(s) if str_eq(s, "off") => PowerOn::Off,
+        Some(_) => panic!("power-on behavior must be one of last, preset or off"),
+    }
+}
+
+/// Parse a color on the form `r,g,b`, with each component in the range 1..255.
+const fn must_parse_rgb(s: &str) -> RGB {
+    let bytes = s.as_bytes();
+    let mut components = [0_u32; 3];
+    let mut index = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'0'..=b'9' => components[index] = components[index] * 10 + (bytes[i] - b'0') as u32,
+            b',' if index < 2 => index += 1,
+            _ => panic!("color must be on the form r,g,b"),
+        }
+        if components[index] > 255 {
+            panic!("color components must be at most 255");
+        }
+        i += 1;
+    }
+    if index != 2 || components[0] == 0 || components[1] == 0 || components[2] == 0 {
+        panic!("color must be on the form r,g,b, with each component greater than zero");
+    }
+    RGB {
+        r: components[0] as f32,
+        g: components[1] as f32,
+        b: components[2] as f32,
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
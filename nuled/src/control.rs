@@ -0,0 +1,55 @@
+/// LED state shared by the MQTT, HTTP and LIFX interfaces.
+///
+/// Changes are forwarded to the LED task through `LED_STATE`, saved to flash,
+/// and announced through `CHANGED`, so that every interface can report the new state.
+
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use crate::state::ServerState;
+use crate::persist;
+
+static STATE: Mutex<CriticalSectionRawMutex, RefCell<Option<ServerState>>> = Mutex::new(RefCell::new(None));
+
+/// Latest state for the LED task. Updates that arrive between two frames are merged,
+/// and the LED task applies the parts that differ from the state it has shown.
+pub static LED_STATE: Signal<CriticalSectionRawMutex, ServerState> = Signal::new();
+
+/// Signalled after every change to the state.
+pub static CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Set the initial state, and configure the LED task accordingly.
+pub fn init(state: ServerState) {
+    STATE.lock(|shared| shared.replace(Some(state)));
+    LED_STATE.signal(state);
+}
+
+pub fn state() -> ServerState {
+    STATE.lock(|shared| shared.borrow().expect("LED state not initialized"))
+}
+
+/// Change the state through `f`. If `f` fails, the state is left unchanged.
+/// An update that changes nothing is not forwarded, saved or announced.
+pub fn update<E>(f: impl FnOnce(&mut ServerState) -> Result<(), E>) -> Result<ServerState, E> {
+    let (state, changed) = STATE.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        let shared = shared.as_mut().expect("LED state not initialized");
+
+        let mut state = *shared;
+        f(&mut state)?;
+
+        let changed = state != *shared;
+        *shared = state;
+        Ok((state, changed))
+    })?;
+
+    if changed {
+        LED_STATE.signal(state);
+        persist::store(&state);
+        CHANGED.signal(());
+        info!("Update: {:?}", state);
+    }
+
+    Ok(state)
+}
@@ -0,0 +1,147 @@
+/// Distributed Display Protocol (DDP), as sent by xLights, LedFx and Hyperion.
+///
+/// Each packet carries channel data for the strip at a byte offset, so that frames larger
+/// than one packet can be split up. The last packet of a frame has the push flag set.
+/// Queries, and packets for the status and configuration destinations, are ignored.
+
+use crate::color::RGB;
+use crate::pixels::{self, Layout};
+
+pub const PORT: u16 = 4048;
+
+const HEADER_SIZE: usize = 10;
+/// A timecode adds four bytes to the header.
+const TIMECODE_SIZE: usize = 4;
+
+const VERSION_MASK: u8 = 0xc0;
+const VERSION_1: u8 = 0x40;
+const FLAG_TIMECODE: u8 = 0x10;
+const FLAG_QUERY: u8 = 0x02;
+const FLAG_PUSH: u8 = 0x01;
+
+/// Data type for RGBW with 8 bits per channel. Any other type is treated as RGB with 8 bits per channel.
+const TYPE_RGBW8: u8 = 0x1b;
+
+const DESTINATION_DISPLAY: u8 = 1;
+const DESTINATION_ALL: u8 = 255;
+
+#[derive(Debug, PartialEq)]
+pub struct Packet<'a> {
+    /// Offset of the data, in bytes from the start of the strip.
+    pub offset: usize,
+    pub layout: Layout,
+    /// Whether this is the last packet of a frame.
+    pub push: bool,
+    pub data: &'a [u8],
+}
+
+pub fn parse(packet: &[u8]) -> Option<Packet<'_>> {
+    let header = packet.get(..HEADER_SIZE)?;
+    let flags = header[0];
+    if flags & VERSION_MASK != VERSION_1 || flags & FLAG_QUERY != 0 {
+        return None;
+    }
+    if !matches!(header[3], DESTINATION_DISPLAY | DESTINATION_ALL) {
+        return None;
+    }
+
+    let start = match flags & FLAG_TIMECODE {
+        0 => HEADER_SIZE,
+        _ => HEADER_SIZE + TIMECODE_SIZE,
+    };
+    let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+
+    Some(Packet {
+        offset: u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+        layout: match header[2] {
+            TYPE_RGBW8 => Layout::Rgbw,
+            _ => Layout::Rgb,
+        },
+        push: flags & FLAG_PUSH != 0,
+        data: packet.get(start..start + len)?,
+    })
+}
+
+impl Packet<'_> {
+    /// Copy the pixels in the packet onto the strip.
+    /// Returns whether any pixels were changed.
+    pub fn apply(&self, pixels: &mut [RGB]) -> bool {
+        pixels::copy(pixels, self.offset / self.layout.channels(), self.data, self.layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(pixel: RGB) -> (f32, f32, f32) {
+        (pixel.r, pixel.g, pixel.b)
+    }
+
+    /// Two pixels from xLights: push, sequence 5, RGB, display, offset 0, length 6.
+    const XLIGHTS: [u8; 16] = [
+        0x41, 0x05, 0x0b, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06,
+        0xff, 0x00, 0x00, 0x00, 0x80, 0xff,
+    ];
+
+    /// Second half of a split frame from LedFx: no push, undefined type, offset 1440, length 3.
+    const LEDFX_SPLIT: [u8; 13] = [
+        0x40, 0x00, 0x00, 0x01, 0x00, 0x00, 0x05, 0xa0, 0x00, 0x03,
+        0x10, 0x20, 0x30,
+    ];
+
+    #[test]
+    fn parse_xlights() {
+        let packet = parse(&XLIGHTS).unwrap();
+        assert_eq!(packet, Packet { offset: 0, layout: Layout::Rgb, push: true, data: &XLIGHTS[10..] });
+
+        let mut pixels = [RGB::default(); 3];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[0]), (255.0, 0.0, 0.0));
+        assert_eq!(rgb(pixels[1]), (0.0, 128.0, 255.0));
+        assert_eq!(rgb(pixels[2]), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn parse_split_frame() {
+        let packet = parse(&LEDFX_SPLIT).unwrap();
+        assert_eq!(packet.offset, 1440);
+        assert!(!packet.push);
+
+        let mut pixels = [RGB::default(); 481];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[480]), (16.0, 32.0, 48.0));
+    }
+
+    #[test]
+    fn parse_timecode_and_rgbw() {
+        let packet = [
+            0x51, 0x00, 0x1b, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04,
+            0x12, 0x34, 0x56, 0x78,
+            0x01, 0x02, 0x03, 0x04,
+        ];
+        let packet = parse(&packet).unwrap();
+        assert_eq!(packet.layout, Layout::Rgbw);
+        assert_eq!(packet.data, &[1, 2, 3, 4]);
+
+        let mut pixels = [RGB::default(); 2];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[1]), (5.0, 6.0, 7.0));
+    }
+
+    #[test]
+    fn other_packets_are_ignored() {
+        let mut query = XLIGHTS;
+        query[0] |= FLAG_QUERY;
+        let mut version_2 = XLIGHTS;
+        version_2[0] = 0x81;
+        let mut config = XLIGHTS;
+        config[3] = 250;
+
+        assert_eq!(parse(&query), None);
+        assert_eq!(parse(&version_2), None);
+        assert_eq!(parse(&config), None);
+        assert_eq!(parse(&XLIGHTS[..15]), None);
+        assert_eq!(parse(&XLIGHTS[..9]), None);
+    }
+}
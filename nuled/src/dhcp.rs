@@ -0,0 +1,203 @@
+/// Minimal DHCP server for the provisioning access point.
+///
+/// Hands out addresses without keeping track of leases: each client gets an address
+/// derived from its MAC address, so a client asking twice gets the same address.
+/// Two clients may get the same address, which is acceptable while provisioning.
+
+const BOOTP_SIZE: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Replies are padded to the minimum BOOTP message size, as some clients drop shorter ones.
+const MIN_REPLY_SIZE: usize = 300;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVER: u8 = 6;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+
+pub struct DhcpServer {
+    /// Address of the server, which also acts as router and DNS server for clients.
+    pub address: [u8; 4],
+    /// Lease time, in seconds.
+    pub lease_time: u32,
+}
+
+impl DhcpServer {
+    /// Address handed out to the client with the given MAC address, in the range `.100` to `.199`.
+    pub fn client_address(&self, mac: &[u8]) -> [u8; 4] {
+        let hash = mac.iter().fold(0_u32, |hash, byte| hash.wrapping_mul(31).wrapping_add(*byte as u32));
+        let [a, b, c, _] = self.address;
+        [a, b, c, 100 + (hash % 100) as u8]
+    }
+
+    /// Write the reply to `request` into `out`, and return its length.
+    /// Returns `None` for messages that need no reply.
+    pub fn reply(&self, request: &[u8], out: &mut [u8]) -> Option<usize> {
+        // Boot request from an Ethernet client.
+        if request.len() < BOOTP_SIZE + 4 || request[0] != 1 || request[1] != 1 || request[2] != 6 {
+            return None;
+        }
+        if request[BOOTP_SIZE..BOOTP_SIZE + 4] != MAGIC_COOKIE {
+            return None;
+        }
+
+        let reply_type = match message_type(&request[BOOTP_SIZE + 4..])? {
+            DISCOVER => OFFER,
+            REQUEST => ACK,
+            _ => return None,
+        };
+
+        let out = out.get_mut(..MIN_REPLY_SIZE)?;
+        out.fill(0);
+
+        let mac = &request[28..34];
+        out[0] = 2; // Boot reply
+        out[1..3].copy_from_slice(&request[1..3]);
+        out[4..8].copy_from_slice(&request[4..8]); // Transaction id
+        out[10..12].copy_from_slice(&request[10..12]); // Flags
+        out[16..20].copy_from_slice(&self.client_address(mac));
+        out[20..24].copy_from_slice(&self.address);
+        out[24..28].copy_from_slice(&request[24..28]); // Relay agent
+        out[28..44].copy_from_slice(&request[28..44]); // Client hardware address
+        out[BOOTP_SIZE..BOOTP_SIZE + 4].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut pos = BOOTP_SIZE + 4;
+        let mut option = |code: u8, data: &[u8]| {
+            out[pos] = code;
+            out[pos + 1] = data.len() as u8;
+            out[pos + 2..pos + 2 + data.len()].copy_from_slice(data);
+            pos += 2 + data.len();
+        };
+        option(OPTION_MESSAGE_TYPE, &[reply_type]);
+        option(OPTION_SERVER_ID, &self.address);
+        option(OPTION_LEASE_TIME, &self.lease_time.to_be_bytes());
+        option(OPTION_SUBNET_MASK, &[255, 255, 255, 0]);
+        option(OPTION_ROUTER, &self.address);
+        option(OPTION_DNS_SERVER, &self.address);
+        out[pos] = OPTION_END;
+
+        Some(MIN_REPLY_SIZE)
+    }
+}
+
+/// Find the DHCP message type among the options.
+fn message_type(mut options: &[u8]) -> Option<u8> {
+    loop {
+        match *options.first()? {
+            OPTION_PAD => options = &options[1..],
+            OPTION_END => return None,
+            code => {
+                let len = *options.get(1)? as usize;
+                let data = options.get(2..2 + len)?;
+                if code == OPTION_MESSAGE_TYPE {
+                    return data.first().copied();
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: DhcpServer = DhcpServer {
+        address: [192, 168, 4, 1],
+        lease_time: 3600,
+    };
+
+    const MAC: [u8; 6] = [0x02, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+    fn request(message_type: u8) -> Vec<u8> {
+        let mut request = vec![0_u8; BOOTP_SIZE];
+        request[0..3].copy_from_slice(&[1, 1, 6]);
+        request[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        request[10] = 0x80;
+        request[28..34].copy_from_slice(&MAC);
+        request.extend_from_slice(&MAGIC_COOKIE);
+        request.extend_from_slice(&[OPTION_PAD, 61, 7, 1, 2, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        request.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type, OPTION_END]);
+        request
+    }
+
+    /// Options of a reply, as (code, data) pairs.
+    fn options(reply: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut options = Vec::new();
+        let mut pos = BOOTP_SIZE + 4;
+        while reply[pos] != OPTION_END {
+            let len = reply[pos + 1] as usize;
+            options.push((reply[pos], reply[pos + 2..pos + 2 + len].to_vec()));
+            pos += 2 + len;
+        }
+        options
+    }
+
+    #[test]
+    fn discover_gets_offer() {
+        let mut out = [0_u8; 576];
+        let len = SERVER.reply(&request(DISCOVER), &mut out).unwrap();
+        let reply = &out[..len];
+        let address = SERVER.client_address(&MAC);
+
+        assert_eq!(len, MIN_REPLY_SIZE);
+        assert_eq!(reply[0], 2);
+        assert_eq!(&reply[4..8], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(reply[10], 0x80);
+        assert_eq!(&reply[16..20], &address);
+        assert_eq!(&reply[28..34], &MAC);
+        assert_eq!(&reply[BOOTP_SIZE..BOOTP_SIZE + 4], &MAGIC_COOKIE);
+        assert_eq!(options(reply), [
+            (OPTION_MESSAGE_TYPE, vec![OFFER]),
+            (OPTION_SERVER_ID, vec![192, 168, 4, 1]),
+            (OPTION_LEASE_TIME, vec![0, 0, 0x0e, 0x10]),
+            (OPTION_SUBNET_MASK, vec![255, 255, 255, 0]),
+            (OPTION_ROUTER, vec![192, 168, 4, 1]),
+            (OPTION_DNS_SERVER, vec![192, 168, 4, 1]),
+        ]);
+    }
+
+    #[test]
+    fn request_gets_ack_with_same_address() {
+        let mut offer = [0_u8; 576];
+        let mut ack = [0_u8; 576];
+        SERVER.reply(&request(DISCOVER), &mut offer).unwrap();
+        SERVER.reply(&request(REQUEST), &mut ack).unwrap();
+
+        assert_eq!(options(&ack)[0], (OPTION_MESSAGE_TYPE, vec![ACK]));
+        assert_eq!(&ack[16..20], &offer[16..20]);
+    }
+
+    #[test]
+    fn client_address_is_in_pool() {
+        for last in 0..=255 {
+            let address = SERVER.client_address(&[0, 0, 0, 0, 0, last]);
+            assert_eq!(&address[..3], &[192, 168, 4]);
+            assert!((100..200).contains(&address[3]));
+        }
+    }
+
+    #[test]
+    fn other_messages_are_ignored() {
+        let mut out = [0_u8; 576];
+        let release = request(7);
+        let mut reply = request(DISCOVER);
+        reply[0] = 2;
+        let mut no_cookie = request(DISCOVER);
+        no_cookie[BOOTP_SIZE] = 0;
+
+        assert_eq!(SERVER.reply(&release, &mut out), None);
+        assert_eq!(SERVER.reply(&reply, &mut out), None);
+        assert_eq!(SERVER.reply(&no_cookie, &mut out), None);
+        assert_eq!(SERVER.reply(&request(DISCOVER)[..BOOTP_SIZE], &mut out), None);
+    }
+}
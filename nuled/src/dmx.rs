@@ -0,0 +1,249 @@
+/// DMX universes over E1.31 (sACN) and Art-Net.
+///
+/// Lighting desks and show software send one packet per universe of 512 channels.
+/// Pixels use three channels each, for red, green and blue, and never span two universes.
+/// The strip starts at a configurable channel of the first universe, and continues at
+/// the first channel of each following universe, 170 pixels at a time.
+
+use crate::color::RGB;
+use crate::pixels::{self, Layout};
+
+pub const E131_PORT: u16 = 5568;
+pub const ARTNET_PORT: u16 = 6454;
+
+/// Channels in a DMX universe.
+pub const CHANNELS: usize = 512;
+const PIXELS_PER_UNIVERSE: usize = CHANNELS / Layout::Rgb.channels();
+
+const E131_ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const E131_VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+const E131_VECTOR_FRAMING_DATA: u32 = 0x0000_0002;
+const E131_VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const E131_HEADER_SIZE: usize = 126;
+/// Option bit for preview data, which is meant for visualizers and not for output.
+const E131_OPTION_PREVIEW: u8 = 0x80;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const ARTNET_PROTOCOL_VERSION: u16 = 14;
+const ARTNET_HEADER_SIZE: usize = 18;
+
+/// Channel values for one universe.
+#[derive(Debug, PartialEq)]
+pub struct Universe<'a> {
+    pub universe: u16,
+    pub data: &'a [u8],
+}
+
+/// Parse an E1.31 data packet. Other packets, such as synchronization and discovery, are ignored.
+pub fn parse_e131(packet: &[u8]) -> Option<Universe<'_>> {
+    let header = packet.get(..E131_HEADER_SIZE)?;
+    let u16_at = |pos: usize| u16::from_be_bytes([header[pos], header[pos + 1]]);
+    let u32_at = |pos: usize| u32::from_be_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]);
+
+    if u16_at(0) != 0x0010 || &header[4..16] != E131_ACN_ID {
+        return None;
+    }
+    if u32_at(18) != E131_VECTOR_ROOT_DATA || u32_at(40) != E131_VECTOR_FRAMING_DATA {
+        return None;
+    }
+    if header[112] & E131_OPTION_PREVIEW != 0 {
+        return None;
+    }
+    // Set property, with one byte per channel, starting at the DMX start code.
+    if header[117] != E131_VECTOR_DMP_SET_PROPERTY || header[118] != 0xa1 || u16_at(119) != 0 || u16_at(121) != 1 {
+        return None;
+    }
+
+    // Only the null start code carries channel values.
+    let count = (u16_at(123) as usize).checked_sub(1)?;
+    if header[125] != 0 || count > CHANNELS {
+        return None;
+    }
+
+    Some(Universe {
+        universe: u16_at(113),
+        data: packet.get(E131_HEADER_SIZE..E131_HEADER_SIZE + count)?,
+    })
+}
+
+/// Parse an Art-Net `ArtDmx` packet. Other operations, such as `ArtPoll`, are ignored.
+pub fn parse_artnet(packet: &[u8]) -> Option<Universe<'_>> {
+    let header = packet.get(..ARTNET_HEADER_SIZE)?;
+    if &header[0..8] != ARTNET_ID || u16::from_le_bytes([header[8], header[9]]) != ARTNET_OP_DMX {
+        return None;
+    }
+    if u16::from_be_bytes([header[10], header[11]]) < ARTNET_PROTOCOL_VERSION {
+        return None;
+    }
+
+    let count = u16::from_be_bytes([header[16], header[17]]) as usize;
+    if count > CHANNELS {
+        return None;
+    }
+
+    Some(Universe {
+        // Port address, from the net and sub-net/universe fields.
+        universe: u16::from_le_bytes([header[14], header[15] & 0x7f]),
+        data: packet.get(ARTNET_HEADER_SIZE..ARTNET_HEADER_SIZE + count)?,
+    })
+}
+
+/// Placement of the LED strip among the DMX universes.
+#[derive(Debug, Clone, Copy)]
+pub struct Mapping {
+    /// Universe holding the first pixel.
+    pub start_universe: u16,
+    /// Channel of the first pixel in the first universe, starting at 1.
+    pub start_channel: u16,
+}
+
+impl Mapping {
+    /// Number of pixels in the first universe.
+    fn first_universe_pixels(&self) -> usize {
+        let offset = self.start_channel.max(1) as usize - 1;
+        CHANNELS.saturating_sub(offset) / Layout::Rgb.channels()
+    }
+
+    /// Number of universes needed for a strip of `pixels` pixels.
+    pub fn universes(&self, pixels: usize) -> u16 {
+        let remaining = pixels.saturating_sub(self.first_universe_pixels());
+        (1 + remaining.div_ceil(PIXELS_PER_UNIVERSE)) as u16
+    }
+
+    /// Copy the channel values of a universe onto the pixels they are mapped to.
+    /// Returns whether any pixels were changed.
+    pub fn apply(&self, universe: &Universe, pixels: &mut [RGB]) -> bool {
+        let Some(index) = universe.universe.checked_sub(self.start_universe) else {
+            return false;
+        };
+
+        let (first_pixel, data) = match index {
+            0 => (0, universe.data.get(self.start_channel.max(1) as usize - 1..).unwrap_or_default()),
+            _ => (self.first_universe_pixels() + (index as usize - 1) * PIXELS_PER_UNIVERSE, universe.data),
+        };
+        pixels::copy(pixels, first_pixel, data, Layout::Rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn e131(universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0_u8; E131_HEADER_SIZE];
+        packet[0..2].copy_from_slice(&0x0010_u16.to_be_bytes());
+        packet[4..16].copy_from_slice(E131_ACN_ID);
+        packet[18..22].copy_from_slice(&E131_VECTOR_ROOT_DATA.to_be_bytes());
+        packet[40..44].copy_from_slice(&E131_VECTOR_FRAMING_DATA.to_be_bytes());
+        packet[44..52].copy_from_slice(b"lightdsk");
+        packet[108] = 100;
+        packet[113..115].copy_from_slice(&universe.to_be_bytes());
+        packet[117] = E131_VECTOR_DMP_SET_PROPERTY;
+        packet[118] = 0xa1;
+        packet[121..123].copy_from_slice(&1_u16.to_be_bytes());
+        packet[123..125].copy_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn artnet(net: u8, subuni: u8, data: &[u8]) -> Vec<u8> {
+        let mut packet = ARTNET_ID.to_vec();
+        packet.extend_from_slice(&ARTNET_OP_DMX.to_le_bytes());
+        packet.extend_from_slice(&ARTNET_PROTOCOL_VERSION.to_be_bytes());
+        packet.extend_from_slice(&[1, 0, subuni, net]);
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn rgb(pixel: RGB) -> (f32, f32, f32) {
+        (pixel.r, pixel.g, pixel.b)
+    }
+
+    #[test]
+    fn e131_data_packet() {
+        let packet = e131(7, &[1, 2, 3, 4]);
+        assert_eq!(parse_e131(&packet), Some(Universe { universe: 7, data: &[1, 2, 3, 4] }));
+    }
+
+    #[test]
+    fn e131_other_packets_are_ignored() {
+        let mut preview = e131(1, &[255; 3]);
+        preview[112] = E131_OPTION_PREVIEW;
+        let mut sync = e131(1, &[255; 3]);
+        sync[40..44].copy_from_slice(&1_u32.to_be_bytes());
+        let mut start_code = e131(1, &[255; 3]);
+        start_code[125] = 0xdd;
+        let mut truncated = e131(1, &[255; 3]);
+        truncated.pop();
+
+        assert_eq!(parse_e131(&preview), None);
+        assert_eq!(parse_e131(&sync), None);
+        assert_eq!(parse_e131(&start_code), None);
+        assert_eq!(parse_e131(&truncated), None);
+        assert_eq!(parse_e131(&artnet(0, 1, &[255; 3])), None);
+    }
+
+    #[test]
+    fn artnet_dmx_packet() {
+        let packet = artnet(2, 0x13, &[9, 8, 7]);
+        assert_eq!(parse_artnet(&packet), Some(Universe { universe: 0x0213, data: &[9, 8, 7] }));
+    }
+
+    #[test]
+    fn artnet_other_packets_are_ignored() {
+        let mut poll = artnet(0, 1, &[]);
+        poll[8..10].copy_from_slice(&0x2000_u16.to_le_bytes());
+        let mut old_version = artnet(0, 1, &[255; 3]);
+        old_version[11] = 13;
+        let mut truncated = artnet(0, 1, &[255; 3]);
+        truncated.pop();
+
+        assert_eq!(parse_artnet(&poll), None);
+        assert_eq!(parse_artnet(&old_version), None);
+        assert_eq!(parse_artnet(&truncated), None);
+        assert_eq!(parse_artnet(&e131(1, &[255; 3])), None);
+    }
+
+    #[test]
+    fn mapping_with_channel_offset() {
+        let mapping = Mapping { start_universe: 1, start_channel: 4 };
+        let mut pixels = [RGB::default(); 200];
+
+        let mut first = [0_u8; CHANNELS];
+        first[3..6].copy_from_slice(&[10, 20, 30]);
+        first[507..510].copy_from_slice(&[40, 50, 60]);
+        assert!(mapping.apply(&Universe { universe: 1, data: &first }, &mut pixels));
+        assert_eq!(rgb(pixels[0]), (10.0, 20.0, 30.0));
+        assert_eq!(rgb(pixels[168]), (40.0, 50.0, 60.0));
+
+        // The first universe holds 169 pixels, and the rest continue in the next universe.
+        assert!(mapping.apply(&Universe { universe: 2, data: &[70, 80, 90, 1, 2, 3] }, &mut pixels));
+        assert_eq!(rgb(pixels[169]), (70.0, 80.0, 90.0));
+        assert_eq!(rgb(pixels[170]), (1.0, 2.0, 3.0));
+        assert_eq!(rgb(pixels[171]), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mapping_ignores_other_universes() {
+        let mapping = Mapping { start_universe: 5, start_channel: 1 };
+        let mut pixels = [RGB::default(); 10];
+
+        assert!(!mapping.apply(&Universe { universe: 4, data: &[255; 30] }, &mut pixels));
+        assert!(!mapping.apply(&Universe { universe: 6, data: &[255; 30] }, &mut pixels));
+        assert!(pixels.iter().all(|pixel| rgb(*pixel) == (0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn universes_needed() {
+        let mapping = Mapping { start_universe: 1, start_channel: 1 };
+        assert_eq!(mapping.universes(30), 1);
+        assert_eq!(mapping.universes(170), 1);
+        assert_eq!(mapping.universes(171), 2);
+
+        let mapping = Mapping { start_universe: 1, start_channel: 10 };
+        assert_eq!(mapping.universes(167), 1);
+        assert_eq!(mapping.universes(168), 2);
+    }
+}
@@ -0,0 +1,121 @@
+/// Captive portal DNS responder.
+///
+/// Answers every A query with the same address, so that clients connected to the
+/// provisioning access point end up on the portal whatever host name they look up.
+/// Other query types get an empty answer, so that clients fall back to IPv4.
+
+const HEADER_SIZE: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+
+/// Time to live of answers, in seconds. Kept short, so that clients
+/// do not hold on to the portal address after provisioning.
+const TTL: u32 = 60;
+
+/// Write the response to `query` into `out`, and return its length.
+/// Returns `None` for packets that are not standard queries.
+pub fn answer(query: &[u8], address: [u8; 4], out: &mut [u8]) -> Option<usize> {
+    let header = query.get(..HEADER_SIZE)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0x0f;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    if is_response || opcode != 0 || questions == 0 {
+        return None;
+    }
+
+    // Only the first question is answered. Names in queries are never compressed.
+    let mut pos = HEADER_SIZE;
+    loop {
+        let len = *query.get(pos)? as usize;
+        pos += 1;
+        match len {
+            0 => break,
+            1..=63 => pos += len,
+            _ => return None,
+        }
+    }
+    let question_end = pos + 4;
+    let question = query.get(HEADER_SIZE..question_end)?;
+    let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let answers = matches!(qtype, TYPE_A | TYPE_ANY) as u16;
+
+    let answer_size = 16 * answers as usize;
+    let out = out.get_mut(..question_end + answer_size)?;
+
+    // Response, authoritative, recursion desired is copied, recursion available.
+    let flags = 0x8000 | 0x0400 | (flags & 0x0100) | 0x0080;
+    out[0..2].copy_from_slice(&header[0..2]);
+    out[2..4].copy_from_slice(&flags.to_be_bytes());
+    out[4..6].copy_from_slice(&1_u16.to_be_bytes());
+    out[6..8].copy_from_slice(&answers.to_be_bytes());
+    out[8..12].fill(0);
+    out[HEADER_SIZE..question_end].copy_from_slice(question);
+
+    if answers > 0 {
+        let answer = &mut out[question_end..];
+        // Pointer to the name in the question.
+        answer[0..2].copy_from_slice(&(0xc000_u16 | HEADER_SIZE as u16).to_be_bytes());
+        answer[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer[4..6].copy_from_slice(&1_u16.to_be_bytes());
+        answer[6..10].copy_from_slice(&TTL.to_be_bytes());
+        answer[10..12].copy_from_slice(&4_u16.to_be_bytes());
+        answer[12..16].copy_from_slice(&address);
+    }
+
+    Some(out.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Query for `nuled.lan` with the given type, and recursion desired.
+    fn query(qtype: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend_from_slice(b"\x05nuled\x03lan\x00");
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&[0, 1]);
+        query
+    }
+
+    #[test]
+    fn a_query_is_answered() {
+        let query = query(TYPE_A);
+        let mut out = [0_u8; 512];
+        let len = answer(&query, [192, 168, 4, 1], &mut out).unwrap();
+
+        assert_eq!(len, query.len() + 16);
+        assert_eq!(&out[0..2], &[0x12, 0x34]);
+        assert_eq!(&out[2..4], &[0x85, 0x80]);
+        assert_eq!(&out[4..8], &[0, 1, 0, 1]);
+        assert_eq!(&out[12..query.len()], &query[12..]);
+        assert_eq!(&out[query.len()..len], &[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 168, 4, 1]);
+    }
+
+    #[test]
+    fn aaaa_query_has_no_answers() {
+        let query = query(28);
+        let mut out = [0_u8; 512];
+        let len = answer(&query, [192, 168, 4, 1], &mut out).unwrap();
+
+        assert_eq!(len, query.len());
+        assert_eq!(&out[6..8], &[0, 0]);
+    }
+
+    #[test]
+    fn invalid_packets_are_ignored() {
+        let mut out = [0_u8; 512];
+        let mut response = query(TYPE_A);
+        response[2] |= 0x80;
+        let mut truncated = query(TYPE_A);
+        truncated.truncate(20);
+        let mut compressed = query(TYPE_A);
+        compressed[12] = 0xc0;
+
+        assert_eq!(answer(&response, [192, 168, 4, 1], &mut out), None);
+        assert_eq!(answer(&truncated, [192, 168, 4, 1], &mut out), None);
+        assert_eq!(answer(&compressed, [192, 168, 4, 1], &mut out), None);
+        assert_eq!(answer(&query(TYPE_A), [192, 168, 4, 1], &mut out[..20]), None);
+    }
+}
@@ -7,7 +7,7 @@ use crate::color::{lerp, CIELUV, HCL, RGB};
 /// To make the API extremely simple, we define a set of common parameters.
 /// Effects may use as many as these as they need, but are encouraged to
 /// use all of them.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Params {
     pub color1: RGB,
     pub color2: RGB,
@@ -15,6 +15,8 @@ pub struct Params {
     pub luminance: f32,
     pub size: f32,
     pub speed: f32,
+    /// Duration of the crossfade when changing effects or parameters, in seconds.
+    pub transition: f32,
 }
 
 impl Default for Params {
@@ -26,16 +28,24 @@ impl Default for Params {
             luminance: 0.6,
             size: 0.5,
             speed: 0.5,
+            transition: 0.5,
         }
     }
 }
 
+impl Params {
+    pub fn transition_ms(&self) -> u64 {
+        (self.transition * 1000.0) as u64
+    }
+}
+
 /// Implement the Effect trait to create new LED effects.
 pub trait Effect<const N: usize>: Iterator<Item=RgbArray<N>> {
     fn configure(&mut self, params: Params);
 }
 
 /// This type holds a string of RGB values and is the return type of all effect iterators.
+#[derive(Clone, Copy)]
 pub struct RgbArray<const N: usize>(pub [RGB; N]);
 
 impl<const N: usize> RgbArray<N> {
@@ -43,10 +53,6 @@ impl<const N: usize> RgbArray<N> {
     pub fn to_rgb8(self) -> [smart_leds::RGB8; N] {
         self.0.map(|x| x.into())
     }
-
-    pub fn to_rgbw(self) -> [smart_leds::RGBW<u8, u8>; N] {
-        self.0.map(|x| x.into())
-    }
 }
 
 impl<const N: usize> Default for RgbArray<N> {
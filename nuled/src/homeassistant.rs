@@ -0,0 +1,79 @@
+/// Home Assistant MQTT discovery.
+///
+/// Retained configuration messages under the discovery prefix make NULED show up
+/// in Home Assistant as a light, with number entities for the effect parameters.
+/// https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
+///
+/// Payloads use the abbreviated key names to fit within the MQTT packet size.
+
+use core::fmt::Write;
+use heapless::String;
+use crate::state::Effect;
+
+pub const DISCOVERY_PREFIX: &str = "homeassistant";
+
+pub type Topic = String<128>;
+pub type Payload = String<1024>;
+
+/// Entities are unavailable while the device is offline. Uses the default `online` and `offline` payloads.
+const AVAILABILITY: &str = r#""avty_t":"~/availability","#;
+
+/// Effect parameters exposed as number entities, as topic name and display name.
+pub const NUMBERS: [(&str, &str); 4] = [
+    ("speed", "Speed"),
+    ("size", "Size"),
+    ("chroma", "Chroma"),
+    ("luminance", "Luminance"),
+];
+
+/// Discovery message for the light entity, which controls on/off, brightness, color and effect.
+pub fn light_config(topic_prefix: &str, device_id: &str) -> Option<(Topic, Payload)> {
+    let mut topic = Topic::new();
+    write!(topic, "{DISCOVERY_PREFIX}/light/nuled_{device_id}/light/config").ok()?;
+
+    let mut s = Payload::new();
+    write!(s, r#"{{"~":"{topic_prefix}","name":null,"uniq_id":"nuled_{device_id}_light","#).ok()?;
+    s.push_str(r#""cmd_t":"~/on/set","stat_t":"~/on","pl_on":"on","pl_off":"off","#).ok()?;
+    s.push_str(r#""bri_cmd_t":"~/brightness/set","bri_stat_t":"~/brightness","#).ok()?;
+    s.push_str(r#""bri_cmd_tpl":"{{ value / 255 }}","#).ok()?;
+    s.push_str(r#""bri_val_tpl":"{{ (value | float * 255) | round(0) | int }}","#).ok()?;
+    s.push_str(r#""rgb_cmd_t":"~/color1/set","rgb_stat_t":"~/color1","#).ok()?;
+    s.push_str(r#""rgb_val_tpl":"{{ value.split(',') | map('float') | map('round') | map('int') | join(',') }}","#).ok()?;
+    s.push_str(r#""fx_cmd_t":"~/effect/set","fx_stat_t":"~/effect","fx_list":["#).ok()?;
+    for (i, effect) in Effect::ALL.iter().enumerate() {
+        if i > 0 {
+            s.push(',').ok()?;
+        }
+        write!(s, r#""{}""#, effect.name()).ok()?;
+    }
+    s.push_str("],").ok()?;
+    s.push_str(AVAILABILITY).ok()?;
+    write_device(&mut s, device_id).ok()?;
+    s.push('}').ok()?;
+
+    Some((topic, s))
+}
+
+/// Discovery message for a number entity controlling one of the effect parameters.
+pub fn number_config(topic_prefix: &str, device_id: &str, key: &str, name: &str) -> Option<(Topic, Payload)> {
+    let mut topic = Topic::new();
+    write!(topic, "{DISCOVERY_PREFIX}/number/nuled_{device_id}/{key}/config").ok()?;
+
+    let mut s = Payload::new();
+    write!(s, r#"{{"~":"{topic_prefix}","name":"{name}","uniq_id":"nuled_{device_id}_{key}","#).ok()?;
+    write!(s, r#""cmd_t":"~/{key}/set","stat_t":"~/{key}","#).ok()?;
+    s.push_str(r#""min":0,"max":1,"step":0.01,"mode":"slider","#).ok()?;
+    s.push_str(AVAILABILITY).ok()?;
+    write_device(&mut s, device_id).ok()?;
+    s.push('}').ok()?;
+
+    Some((topic, s))
+}
+
+fn write_device(s: &mut Payload, device_id: &str) -> core::fmt::Result {
+    write!(
+        s,
+        r#""dev":{{"ids":["nuled_{device_id}"],"name":"NULED {device_id}","mf":"NULED","mdl":"ESP32-C3","sw":"{}"}}"#,
+        env!("CARGO_PKG_VERSION"),
+    )
+}
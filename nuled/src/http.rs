@@ -0,0 +1,260 @@
+/// Minimal HTTP/1.1 server support.
+///
+/// Only what the provisioning portal and the HTTP API need: one request per connection,
+/// requests and bodies that fit in a single buffer, and no chunked transfer encoding.
+
+use core::fmt::Write as _;
+use embedded_io_async::{Read, Write};
+use heapless::String;
+
+#[derive(Debug, PartialEq)]
+pub enum HttpError {
+    /// More data is needed to parse the request.
+    Incomplete,
+    Malformed,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    pub body: &'a [u8],
+}
+
+#[derive(Debug)]
+pub enum ServeError<E> {
+    /// No valid request was received.
+    Request(HttpError),
+    /// The status line and headers did not fit.
+    Response,
+    Io(E),
+}
+
+/// Serve the single request of an accepted connection.
+///
+/// The request is read into `buf`, and `respond` returns the status code and body of the response.
+/// The connection is left open, so that the caller can close it, or abort it on errors.
+pub async fn serve<S: Read + Write, B: AsRef<[u8]>>(
+    socket: &mut S,
+    buf: &mut [u8],
+    content_type: &str,
+    respond: impl FnOnce(&Request) -> (u16, B),
+) -> Result<(), ServeError<S::Error>> {
+    let mut len = 0;
+    while len < buf.len() {
+        match socket.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+        if parse_request(&buf[..len]) != Err(HttpError::Incomplete) {
+            break;
+        }
+    }
+
+    let request = parse_request(&buf[..len]).map_err(ServeError::Request)?;
+    let (status, body) = respond(&request);
+    let body = body.as_ref();
+    let head = response_head(status, content_type, body.len()).ok_or(ServeError::Response)?;
+
+    socket.write_all(head.as_bytes()).await.map_err(ServeError::Io)?;
+    socket.write_all(body).await.map_err(ServeError::Io)?;
+    socket.flush().await.map_err(ServeError::Io)
+}
+
+/// Parse a request, including the body if a `Content-Length` header is present.
+pub fn parse_request(buf: &[u8]) -> Result<Request<'_>, HttpError> {
+    use HttpError::*;
+
+    let header_end = buf.windows(4).position(|w| w == b"\r\n\r\n").ok_or(Incomplete)?;
+    let head = core::str::from_utf8(&buf[..header_end]).map_err(|_| Malformed)?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().ok_or(Malformed)?.split(' ');
+    let method = request_line.next().filter(|s| !s.is_empty()).ok_or(Malformed)?;
+    let target = request_line.next().filter(|s| s.starts_with('/')).ok_or(Malformed)?;
+    match request_line.next() {
+        Some(version) if version.starts_with("HTTP/1.") => {}
+        _ => return Err(Malformed),
+    }
+
+    let mut content_length = 0;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(Malformed)?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().map_err(|_| Malformed)?;
+        }
+    }
+
+    let body_start = header_end + 4;
+    let body = buf.get(body_start..body_start + content_length).ok_or(Incomplete)?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+
+    Ok(Request { method, path, query, body })
+}
+
+/// Status line and headers of a response. The body is written separately.
+pub fn response_head(status: u16, content_type: &str, content_length: usize) -> Option<String<160>> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+
+    let mut s = String::new();
+    write!(
+        s,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {content_length}\r\nConnection: close\r\n\r\n",
+    ).ok()?;
+    Some(s)
+}
+
+/// Iterate over the fields of an `application/x-www-form-urlencoded` body, or a query string.
+/// Names and values are returned as-is, and must be decoded with `url_decode`.
+pub fn form_fields(form: &str) -> impl Iterator<Item = (&str, &str)> {
+    form.split('&')
+        .filter(|field| !field.is_empty())
+        .map(|field| field.split_once('=').unwrap_or((field, "")))
+}
+
+/// Decode percent-encoding, and `+` as space.
+pub fn url_decode<const N: usize>(s: &str) -> Option<String<N>> {
+    let mut bytes = heapless::Vec::<u8, N>::new();
+    let mut input = s.bytes();
+
+    while let Some(byte) = input.next() {
+        let decoded = match byte {
+            b'+' => b' ',
+            b'%' => {
+                let hi = (input.next()? as char).to_digit(16)?;
+                let lo = (input.next()? as char).to_digit(16)?;
+                (hi * 16 + lo) as u8
+            }
+            byte => byte,
+        };
+        bytes.push(decoded).ok()?;
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_request() {
+        let request = parse_request(b"GET /api/state?pretty=1 HTTP/1.1\r\nHost: nuled\r\n\r\n").unwrap();
+        assert_eq!(request, Request {
+            method: "GET",
+            path: "/api/state",
+            query: Some("pretty=1"),
+            body: b"",
+        });
+    }
+
+    #[test]
+    fn post_request_with_body() {
+        let buf = b"POST /save HTTP/1.1\r\ncontent-length: 7\r\n\r\na=1&b=2";
+        let request = parse_request(buf).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/save");
+        assert_eq!(request.body, b"a=1&b=2");
+    }
+
+    #[test]
+    fn incomplete_request() {
+        assert_eq!(parse_request(b"GET / HTTP/1.1\r\nHost: nu"), Err(HttpError::Incomplete));
+        assert_eq!(parse_request(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"), Err(HttpError::Incomplete));
+    }
+
+    #[test]
+    fn malformed_request() {
+        assert_eq!(parse_request(b"GET\r\n\r\n"), Err(HttpError::Malformed));
+        assert_eq!(parse_request(b"GET / SPDY/3\r\n\r\n"), Err(HttpError::Malformed));
+        assert_eq!(parse_request(b"GET / HTTP/1.1\r\nno colon\r\n\r\n"), Err(HttpError::Malformed));
+        assert_eq!(parse_request(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"), Err(HttpError::Malformed));
+    }
+
+    #[test]
+    fn response_head_format() {
+        assert_eq!(
+            response_head(404, "text/plain", 9).unwrap().as_str(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 9\r\nConnection: close\r\n\r\n",
+        );
+    }
+
+    #[test]
+    fn form_decoding() {
+        let fields: Vec<_> = form_fields("ssid=My+Home%21&password=&flag&").collect();
+        assert_eq!(fields, [("ssid", "My+Home%21"), ("password", ""), ("flag", "")]);
+        assert_eq!(url_decode::<32>("My+Home%21").unwrap().as_str(), "My Home!");
+        assert_eq!(url_decode::<32>("%C3%A6%c3%b8").unwrap().as_str(), "æø");
+        assert_eq!(url_decode::<32>("100%"), None);
+        assert_eq!(url_decode::<32>("%zz"), None);
+        assert_eq!(url_decode::<32>("%ff"), None);
+        assert_eq!(url_decode::<4>("12345"), None);
+    }
+
+    /// Connection that receives `input` in chunks of `chunk` bytes, and records the response.
+    struct TestSocket<'a> {
+        input: &'a [u8],
+        chunk: usize,
+        output: Vec<u8>,
+    }
+
+    impl embedded_io_async::ErrorType for TestSocket<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for TestSocket<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.chunk).min(self.input.len());
+            buf[..len].copy_from_slice(&self.input[..len]);
+            self.input = &self.input[len..];
+            Ok(len)
+        }
+    }
+
+    impl Write for TestSocket<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn serve_test(input: &[u8], chunk: usize) -> (Result<(), ServeError<core::convert::Infallible>>, Vec<u8>) {
+        let mut socket = TestSocket { input, chunk, output: Vec::new() };
+        let mut buf = [0; 256];
+        let result = tokio_test::block_on(serve(&mut socket, &mut buf, "text/plain", |request| {
+            (200, [request.path.as_bytes(), request.body].concat())
+        }));
+        (result, socket.output)
+    }
+
+    #[test]
+    fn serve_request_in_pieces() {
+        let (result, output) = serve_test(b"POST /echo HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody", 5);
+        assert!(result.is_ok());
+        let expected = [response_head(200, "text/plain", 9).unwrap().as_bytes(), b"/echobody"].concat();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn serve_invalid_request() {
+        let (result, output) = serve_test(b"GET / SPDY/3\r\n\r\n", 64);
+        assert!(matches!(result, Err(ServeError::Request(HttpError::Malformed))));
+        assert!(output.is_empty());
+
+        // The connection was closed before the request was complete.
+        let (result, output) = serve_test(b"GET / HTTP/1.1\r\n", 64);
+        assert!(matches!(result, Err(ServeError::Request(HttpError::Incomplete))));
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+/// Names that identify this device on the network.
+///
+/// The device id, MQTT topic prefix and MQTT client id can be set in the configuration.
+/// Otherwise they are derived from the WiFi MAC address, so that several
+/// boards can share the same MQTT broker without any configuration.
+
+use core::fmt::Write;
+use heapless::String;
+
+pub type Topic = String<128>;
+
+/// Longest device id, so that `nuled-<device id>` fits in a client id, and `NULED-<device id>`
+/// in a WiFi SSID.
+pub const MAX_DEVICE_ID_LEN: usize = 26;
+
+#[derive(Debug, PartialEq)]
+pub enum IdentityError {
+    /// The device id is empty, too long, or has other characters than ASCII letters,
+    /// digits, `_` and `-`. It is used in MQTT topics and Home Assistant ids.
+    InvalidDeviceId,
+    /// The topic prefix is empty, too long, or has the MQTT wildcards `+` or `#`.
+    InvalidTopicPrefix,
+    ClientIdTooLong,
+}
+
+pub struct Identity {
+    /// Short name of the device, e.g. `pallet`, or `a1b2c3` if derived from the MAC address.
+    pub device_id: String<32>,
+    /// Prefix of all MQTT topics for this device, e.g. `led/pallet`.
+    pub topic_prefix: String<64>,
+    pub client_id: String<32>,
+}
+
+impl Identity {
+    /// Use the configured names, and derive the others from `mac`.
+    pub fn new(
+        mac: [u8; 6],
+        device_id: Option<&str>,
+        topic_prefix: Option<&str>,
+        client_id: Option<&str>,
+    ) -> Result<Self, IdentityError> {
+        let device_id = match device_id {
+            Some(id) if valid_device_id(id) => String::try_from(id).map_err(|_| IdentityError::InvalidDeviceId)?,
+            Some(_) => return Err(IdentityError::InvalidDeviceId),
+            None => {
+                let mut id = String::new();
+                write!(id, "{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]).unwrap();
+                id
+            }
+        };
+
+        let mut prefix = String::new();
+        match topic_prefix.map(|prefix| prefix.trim_end_matches('/')) {
+            Some(p) if !p.is_empty() && !p.contains(['+', '#']) => {
+                prefix.push_str(p).map_err(|_| IdentityError::InvalidTopicPrefix)?
+            }
+            Some(_) => return Err(IdentityError::InvalidTopicPrefix),
+            None => write!(prefix, "led/{device_id}").unwrap(),
+        }
+
+        let mut id = String::new();
+        match client_id {
+            Some(client_id) => id.push_str(client_id).map_err(|_| IdentityError::ClientIdTooLong)?,
+            None => write!(id, "nuled-{device_id}").unwrap(),
+        }
+
+        Ok(Self {
+            device_id,
+            topic_prefix: prefix,
+            client_id: id,
+        })
+    }
+
+    /// Full MQTT topic for `name`, e.g. `led/pallet/speed`.
+    pub fn topic(&self, name: &str) -> Option<Topic> {
+        let mut topic = Topic::new();
+        write!(topic, "{}/{}", self.topic_prefix, name).ok()?;
+        Some(topic)
+    }
+
+    /// Topic relative to the prefix, e.g. `speed/set` for `led/pallet/speed/set`.
+    /// Returns `None` for topics that belong to other devices.
+    pub fn strip_prefix<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic.strip_prefix(self.topic_prefix.as_str())?.strip_prefix('/')
+    }
+}
+
+fn valid_device_id(id: &str) -> bool {
+    (1..=MAX_DEVICE_ID_LEN).contains(&id.len())
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x00, 0x11, 0x22, 0xa1, 0xb2, 0xc3];
+
+    #[test]
+    fn derived_from_mac() {
+        let identity = Identity::new(MAC, None, None, None).unwrap();
+        assert_eq!(identity.device_id, "a1b2c3");
+        assert_eq!(identity.topic_prefix, "led/a1b2c3");
+        assert_eq!(identity.client_id, "nuled-a1b2c3");
+    }
+
+    #[test]
+    fn configured_names() {
+        let identity = Identity::new(MAC, Some("pallet"), Some("home/led/"), Some("pallet-client")).unwrap();
+        assert_eq!(identity.device_id, "pallet");
+        assert_eq!(identity.topic_prefix, "home/led");
+        assert_eq!(identity.client_id, "pallet-client");
+        assert_eq!(identity.topic("speed/set").unwrap(), "home/led/speed/set");
+        assert_eq!(identity.strip_prefix("home/led/speed/set"), Some("speed/set"));
+        assert_eq!(identity.strip_prefix("home/ledx/speed/set"), None);
+    }
+
+    #[test]
+    fn longest_device_id_fits_client_id() {
+        let id = "a".repeat(MAX_DEVICE_ID_LEN);
+        let identity = Identity::new(MAC, Some(&id), None, None).unwrap();
+        assert_eq!(identity.client_id.len(), "nuled-".len() + MAX_DEVICE_ID_LEN);
+
+        let id = "a".repeat(MAX_DEVICE_ID_LEN + 1);
+        assert_eq!(Identity::new(MAC, Some(&id), None, None).err(), Some(IdentityError::InvalidDeviceId));
+    }
+
+    #[test]
+    fn invalid_names() {
+        for id in ["", "living room", "a/b", "a+", "#", "pallé"] {
+            assert_eq!(Identity::new(MAC, Some(id), None, None).err(), Some(IdentityError::InvalidDeviceId), "{id}");
+        }
+        for prefix in ["", "/", "led/+", "led/#"] {
+            let err = Identity::new(MAC, None, Some(prefix), None).err();
+            assert_eq!(err, Some(IdentityError::InvalidTopicPrefix), "{prefix}");
+        }
+        let client_id = "c".repeat(33);
+        assert_eq!(Identity::new(MAC, None, None, Some(&client_id)).err(), Some(IdentityError::ClientIdTooLong));
+    }
+}
@@ -0,0 +1,152 @@
+/// LEDServer protocol, as spoken by `blinken` and the original `ledserver` in this repository.
+///
+/// Each `LED` message from `pb.proto` sets a single pixel, and messages with `render` set show the frame.
+/// Messages are published over ZeroMQ, see `zmtp`, and decoded here without a protobuf library.
+
+use crate::color::RGB;
+use crate::pixels::{self, Layout};
+
+/// Port that `ledserver` listens on.
+pub const PORT: u16 = 1230;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+/// The `LED` message.
+#[derive(Debug, Default, PartialEq)]
+pub struct Led {
+    /// Increased by one for every message sent.
+    pub serial: u64,
+    /// Position of the pixel on the strip.
+    pub index: u32,
+    /// Color as `0xAARRGGBB`. The alpha channel is ignored.
+    pub rgb: u32,
+    /// Whether to show the frame after setting this pixel.
+    pub render: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// The message ends in the middle of a field.
+    Truncated,
+    /// A field has an unsupported wire type, or a different wire type than in `pb.proto`.
+    WireType,
+}
+
+/// Decode an `LED` message. Unknown fields are skipped, as in any protobuf decoder.
+pub fn decode(mut buf: &[u8]) -> Result<Led, DecodeError> {
+    let mut led = Led::default();
+
+    while !buf.is_empty() {
+        let key = varint(&mut buf)?;
+        let (field, wire_type) = (key >> 3, (key & 0x07) as u8);
+
+        match (field, wire_type) {
+            (1, WIRE_VARINT) => led.serial = varint(&mut buf)?,
+            (2, WIRE_VARINT) => led.index = varint(&mut buf)? as u32,
+            (3, WIRE_FIXED32) => led.rgb = u32::from_le_bytes(take(&mut buf, 4)?.try_into().unwrap()),
+            (4, WIRE_VARINT) => led.render = varint(&mut buf)? != 0,
+            (1..=4, _) => return Err(DecodeError::WireType),
+            (_, WIRE_VARINT) => {
+                varint(&mut buf)?;
+            }
+            (_, WIRE_FIXED64) => {
+                take(&mut buf, 8)?;
+            }
+            (_, WIRE_LENGTH_DELIMITED) => {
+                let len = varint(&mut buf)? as usize;
+                take(&mut buf, len)?;
+            }
+            (_, WIRE_FIXED32) => {
+                take(&mut buf, 4)?;
+            }
+            _ => return Err(DecodeError::WireType),
+        }
+    }
+
+    Ok(led)
+}
+
+impl Led {
+    /// Set the pixel on the strip. Returns whether the pixel is on the strip.
+    pub fn apply(&self, pixels: &mut [RGB]) -> bool {
+        let [_, r, g, b] = self.rgb.to_be_bytes();
+        pixels::copy(pixels, self.index as usize, &[r, g, b], Layout::Rgb)
+    }
+}
+
+fn varint(buf: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0_u64;
+    for i in 0..10 {
+        let (&byte, rest) = buf.split_first().ok_or(DecodeError::Truncated)?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Truncated)
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if buf.len() < len {
+        return Err(DecodeError::Truncated);
+    }
+    let (data, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Encoded by `proto.Marshal` in blinken, using `pb.LED` from `blinken/pb`.
+
+    /// `LED{Serial: 15, Index: 7, Rgb: lib.RGBA(colorful.Color{R: 1, G: 0.5, B: 0}), Render: true}`
+    const RENDER: [u8; 11] = [0x08, 0x0f, 0x10, 0x07, 0x1d, 0x00, 0x80, 0xff, 0xff, 0x20, 0x01];
+
+    /// `LED{Serial: 300, Index: 0, Rgb: lib.RGBA(colorful.Color{})}`: zero values are left out.
+    const ZERO_INDEX: [u8; 8] = [0x08, 0xac, 0x02, 0x1d, 0x00, 0x00, 0x00, 0xff];
+
+    /// `LED{Serial: 1, Index: 239, Rgb: 0xff0000ff}`, where the index needs two bytes.
+    const LAST_LED: [u8; 10] = [0x08, 0x01, 0x10, 0xef, 0x01, 0x1d, 0xff, 0x00, 0x00, 0xff];
+
+    #[test]
+    fn decode_messages() {
+        assert_eq!(decode(&RENDER), Ok(Led { serial: 15, index: 7, rgb: 0xffff8000, render: true }));
+        assert_eq!(decode(&ZERO_INDEX), Ok(Led { serial: 300, index: 0, rgb: 0xff000000, render: false }));
+        assert_eq!(decode(&LAST_LED), Ok(Led { serial: 1, index: 239, rgb: 0xff0000ff, render: false }));
+        assert_eq!(decode(&[]), Ok(Led::default()));
+    }
+
+    #[test]
+    fn apply_sets_pixel() {
+        let mut pixels = [RGB::default(); 8];
+        assert!(decode(&RENDER).unwrap().apply(&mut pixels));
+        assert_eq!((pixels[7].r, pixels[7].g, pixels[7].b), (255.0, 128.0, 0.0));
+        assert!(!decode(&LAST_LED).unwrap().apply(&mut pixels));
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        // Field 5 as a string, and field 6 as fixed64, before the known fields.
+        let mut message = vec![0x2a, 0x02, b'h', b'i', 0x31, 1, 2, 3, 4, 5, 6, 7, 8];
+        message.extend_from_slice(&RENDER);
+        assert_eq!(decode(&message), decode(&RENDER));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert_eq!(decode(&RENDER[..8]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&[0x08, 0x80]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&[0x08; 12]), Ok(Led { serial: 8, ..Default::default() }));
+        assert_eq!(decode(&[0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), Err(DecodeError::Truncated));
+        // Index as fixed32 instead of varint.
+        assert_eq!(decode(&[0x15, 0x07, 0x00, 0x00, 0x00]), Err(DecodeError::WireType));
+        // Deprecated group wire type.
+        assert_eq!(decode(&[0x2b]), Err(DecodeError::WireType));
+    }
+}
@@ -0,0 +1,435 @@
+/// LIFX LAN protocol, so that the LIFX apps and integrations can discover and control the strip.
+///
+/// Every packet starts with a 36 byte header: the frame, the frame address with the target
+/// MAC address, and the protocol header with the message type. All fields are little endian.
+/// Only the messages needed to appear as a color bulb are supported, others are ignored.
+
+use num_traits::Float;
+use crate::color::HCL;
+
+pub const PORT: u16 = 56700;
+
+pub const HEADER_SIZE: usize = 36;
+pub const LABEL_SIZE: usize = 32;
+
+/// Protocol number, which must be 1024.
+const PROTOCOL: u16 = 1024;
+const PROTOCOL_MASK: u16 = 0x0fff;
+const FLAG_ADDRESSABLE: u16 = 0x1000;
+const FLAG_TAGGED: u16 = 0x2000;
+
+const FLAG_RES_REQUIRED: u8 = 0x01;
+const FLAG_ACK_REQUIRED: u8 = 0x02;
+
+const SERVICE_UDP: u8 = 1;
+const VENDOR_LIFX: u32 = 1;
+/// LIFX Color 1000, the same product as in the ESPHome emulation.
+const PRODUCT: u32 = 22;
+
+const POWER_ON: u16 = 0xffff;
+
+const GET_SERVICE: u16 = 2;
+const STATE_SERVICE: u16 = 3;
+const GET_POWER: u16 = 20;
+const SET_POWER: u16 = 21;
+const STATE_POWER: u16 = 22;
+const GET_LABEL: u16 = 23;
+const SET_LABEL: u16 = 24;
+const STATE_LABEL: u16 = 25;
+const GET_VERSION: u16 = 32;
+const STATE_VERSION: u16 = 33;
+const ACKNOWLEDGEMENT: u16 = 45;
+const GET_COLOR: u16 = 101;
+const SET_COLOR: u16 = 102;
+const LIGHT_STATE: u16 = 107;
+const GET_LIGHT_POWER: u16 = 116;
+const SET_LIGHT_POWER: u16 = 117;
+const STATE_LIGHT_POWER: u16 = 118;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    /// Whether the packet is sent to all bulbs.
+    pub tagged: bool,
+    /// Chosen by the client, and repeated in the replies.
+    pub source: u32,
+    /// MAC address of the bulb, followed by two zero bytes. All zeros for all bulbs.
+    pub target: [u8; 8],
+    pub ack_required: bool,
+    pub res_required: bool,
+    /// Chosen by the client, and repeated in the replies.
+    pub sequence: u8,
+    pub message_type: u16,
+}
+
+/// Color as hue, saturation, brightness and color temperature in Kelvin.
+/// Hue, saturation and brightness use the full range of `u16`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hsbk {
+    pub hue: u16,
+    pub saturation: u16,
+    pub brightness: u16,
+    pub kelvin: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Request<'a> {
+    GetService,
+    GetPower,
+    SetPower { on: bool },
+    GetLabel,
+    SetLabel(&'a str),
+    GetVersion,
+    GetColor,
+    /// The transition duration is ignored.
+    SetColor(Hsbk),
+    GetLightPower,
+    /// The transition duration is ignored.
+    SetLightPower { on: bool },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Response<'a> {
+    StateService,
+    StatePower { on: bool },
+    StateLabel(&'a str),
+    StateVersion,
+    Acknowledgement,
+    LightState { color: Hsbk, on: bool, label: &'a str },
+    StateLightPower { on: bool },
+}
+
+/// Parse the header, and return it along with the payload.
+pub fn parse_header(packet: &[u8]) -> Option<(Header, &[u8])> {
+    let header = packet.get(..HEADER_SIZE)?;
+    let size = u16_at(header, 0) as usize;
+    let protocol = u16_at(header, 2);
+    if protocol & PROTOCOL_MASK != PROTOCOL || protocol & FLAG_ADDRESSABLE == 0 {
+        return None;
+    }
+
+    let header = Header {
+        tagged: protocol & FLAG_TAGGED != 0,
+        source: u32::from_le_bytes(header[4..8].try_into().unwrap()),
+        target: header[8..16].try_into().unwrap(),
+        res_required: header[22] & FLAG_RES_REQUIRED != 0,
+        ack_required: header[22] & FLAG_ACK_REQUIRED != 0,
+        sequence: header[23],
+        message_type: u16_at(header, 32),
+    };
+    Some((header, packet.get(HEADER_SIZE..size)?))
+}
+
+impl Header {
+    /// Whether the packet is meant for the bulb with the given MAC address.
+    pub fn is_for(&self, mac: [u8; 6]) -> bool {
+        self.tagged || self.target == [0; 8] || self.target[..6] == mac
+    }
+}
+
+impl<'a> Request<'a> {
+    /// Parse the payload of a request. Returns `None` for unsupported messages.
+    pub fn parse(message_type: u16, payload: &'a [u8]) -> Option<Self> {
+        Some(match message_type {
+            GET_SERVICE => Request::GetService,
+            GET_POWER => Request::GetPower,
+            SET_POWER => Request::SetPower { on: u16_at(payload.get(..2)?, 0) != 0 },
+            GET_LABEL => Request::GetLabel,
+            SET_LABEL => Request::SetLabel(parse_label(payload.get(..LABEL_SIZE)?)?),
+            GET_VERSION => Request::GetVersion,
+            GET_COLOR => Request::GetColor,
+            // A reserved byte precedes the color.
+            SET_COLOR => Request::SetColor(Hsbk::parse(payload.get(1..9)?)),
+            GET_LIGHT_POWER => Request::GetLightPower,
+            SET_LIGHT_POWER => Request::SetLightPower { on: u16_at(payload.get(..2)?, 0) != 0 },
+            _ => return None,
+        })
+    }
+}
+
+impl Response<'_> {
+    fn message_type(&self) -> u16 {
+        match self {
+            Response::StateService => STATE_SERVICE,
+            Response::StatePower { .. } => STATE_POWER,
+            Response::StateLabel(_) => STATE_LABEL,
+            Response::StateVersion => STATE_VERSION,
+            Response::Acknowledgement => ACKNOWLEDGEMENT,
+            Response::LightState { .. } => LIGHT_STATE,
+            Response::StateLightPower { .. } => STATE_LIGHT_POWER,
+        }
+    }
+
+    /// Write the payload, and return its size.
+    fn write_payload(&self, out: &mut [u8]) -> Option<usize> {
+        let mut writer = Writer { out, pos: 0 };
+        match self {
+            Response::StateService => {
+                writer.put(&[SERVICE_UDP])?;
+                writer.put(&(PORT as u32).to_le_bytes())?;
+            }
+            Response::StatePower { on } | Response::StateLightPower { on } => {
+                writer.put(&power_level(*on).to_le_bytes())?;
+            }
+            Response::StateLabel(label) => writer.put_label(label)?,
+            Response::StateVersion => {
+                writer.put(&VENDOR_LIFX.to_le_bytes())?;
+                writer.put(&PRODUCT.to_le_bytes())?;
+                writer.put(&[0; 4])?;
+            }
+            Response::Acknowledgement => {}
+            Response::LightState { color, on, label } => {
+                color.write(&mut writer)?;
+                writer.put(&[0; 2])?;
+                writer.put(&power_level(*on).to_le_bytes())?;
+                writer.put_label(label)?;
+                writer.put(&[0; 8])?;
+            }
+        }
+        Some(writer.pos)
+    }
+}
+
+/// Encode a reply to `request` from the bulb with the given MAC address, and return its size.
+pub fn encode(request: &Header, mac: [u8; 6], response: &Response, out: &mut [u8]) -> Option<usize> {
+    if out.len() < HEADER_SIZE {
+        return None;
+    }
+    let (header, payload) = out.split_at_mut(HEADER_SIZE);
+    let size = HEADER_SIZE + response.write_payload(payload)?;
+
+    header.fill(0);
+    header[0..2].copy_from_slice(&(size as u16).to_le_bytes());
+    header[2..4].copy_from_slice(&(PROTOCOL | FLAG_ADDRESSABLE).to_le_bytes());
+    header[4..8].copy_from_slice(&request.source.to_le_bytes());
+    header[8..14].copy_from_slice(&mac);
+    header[23] = request.sequence;
+    header[32..34].copy_from_slice(&response.message_type().to_le_bytes());
+
+    Some(size)
+}
+
+impl Hsbk {
+    fn parse(data: &[u8]) -> Self {
+        Hsbk {
+            hue: u16_at(data, 0),
+            saturation: u16_at(data, 2),
+            brightness: u16_at(data, 4),
+            kelvin: u16_at(data, 6),
+        }
+    }
+
+    fn write(&self, writer: &mut Writer) -> Option<()> {
+        for value in [self.hue, self.saturation, self.brightness, self.kelvin] {
+            writer.put(&value.to_le_bytes())?;
+        }
+        Some(())
+    }
+
+    /// Saturation maps to chroma, and brightness to luminance. The color temperature is not used.
+    pub fn to_hcl(self) -> HCL {
+        HCL {
+            h: self.hue as f32 * 360.0 / 65536.0,
+            c: self.saturation as f32 / 65535.0,
+            l: self.brightness as f32 / 65535.0,
+        }
+    }
+
+    /// Inverse of `to_hcl`, with chroma and luminance clamped to `0.0..1.0`.
+    pub fn from_hcl(hcl: HCL, kelvin: u16) -> Self {
+        let h = hcl.h % 360.0;
+        let h = if h < 0.0 { h + 360.0 } else { h };
+        Hsbk {
+            hue: ((h * 65536.0 / 360.0).round() as u32 % 65536) as u16,
+            saturation: (hcl.c.clamp(0.0, 1.0) * 65535.0).round() as u16,
+            brightness: (hcl.l.clamp(0.0, 1.0) * 65535.0).round() as u16,
+            kelvin,
+        }
+    }
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, data: &[u8]) -> Option<()> {
+        self.out.get_mut(self.pos..self.pos + data.len())?.copy_from_slice(data);
+        self.pos += data.len();
+        Some(())
+    }
+
+    /// Labels are padded with zeros, and cut off at `LABEL_SIZE` bytes.
+    fn put_label(&mut self, label: &str) -> Option<()> {
+        let mut padded = [0; LABEL_SIZE];
+        let len = label.len().min(LABEL_SIZE);
+        padded[..len].copy_from_slice(&label.as_bytes()[..len]);
+        self.put(&padded)
+    }
+}
+
+/// Labels are UTF-8, padded with zeros.
+fn parse_label(data: &[u8]) -> Option<&str> {
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    core::str::from_utf8(&data[..len]).ok()
+}
+
+fn power_level(on: bool) -> u16 {
+    if on { POWER_ON } else { 0 }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0xd0, 0x73, 0xd5, 0x01, 0x02, 0x03];
+
+    /// GetService broadcast by the LIFX app: tagged, source 0x5e1fcd3a, sequence 0.
+    const GET_SERVICE_PACKET: [u8; 36] = [
+        0x24, 0x00, 0x00, 0x34, 0x3a, 0xcd, 0x1f, 0x5e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x02, 0x00, 0x00, 0x00,
+    ];
+
+    /// SetColor to green at full brightness, 3500K, over 1024ms,
+    /// addressed to `MAC`, with ack and response required, sequence 7.
+    const SET_COLOR_PACKET: [u8; 49] = [
+        0x31, 0x00, 0x00, 0x14, 0x02, 0x00, 0x00, 0x00, 0xd0, 0x73, 0xd5, 0x01, 0x02, 0x03, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x66, 0x00, 0x00, 0x00,
+        0x00, 0x55, 0x55, 0xff, 0xff, 0xff, 0xff, 0xac, 0x0d, 0x00, 0x04, 0x00, 0x00,
+    ];
+
+    fn request(packet: &[u8]) -> (Header, Request<'_>) {
+        let (header, payload) = parse_header(packet).unwrap();
+        (header, Request::parse(header.message_type, payload).unwrap())
+    }
+
+    #[test]
+    fn parse_discovery() {
+        let (header, request) = request(&GET_SERVICE_PACKET);
+        assert_eq!(request, Request::GetService);
+        assert!(header.tagged);
+        assert!(header.is_for(MAC));
+        assert_eq!(header.source, 0x5e1fcd3a);
+        assert!(!header.ack_required && !header.res_required);
+    }
+
+    #[test]
+    fn parse_set_color() {
+        let (header, request) = request(&SET_COLOR_PACKET);
+        let green = Hsbk { hue: 0x5555, saturation: 0xffff, brightness: 0xffff, kelvin: 3500 };
+        assert_eq!(request, Request::SetColor(green));
+        assert!(!header.tagged);
+        assert!(header.ack_required && header.res_required);
+        assert_eq!(header.sequence, 7);
+        assert!(header.is_for(MAC));
+        assert!(!header.is_for([0xd0, 0x73, 0xd5, 0x01, 0x02, 0x04]));
+    }
+
+    #[test]
+    fn parse_power_and_label() {
+        let mut packet = [0; HEADER_SIZE + LABEL_SIZE];
+        packet[..HEADER_SIZE].copy_from_slice(&GET_SERVICE_PACKET);
+        packet[0] = packet.len() as u8;
+
+        packet[32] = SET_POWER as u8;
+        packet[36..38].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(request(&packet).1, Request::SetPower { on: true });
+
+        packet[32] = SET_LIGHT_POWER as u8;
+        packet[36..38].copy_from_slice(&[0x00, 0x00]);
+        assert_eq!(request(&packet).1, Request::SetLightPower { on: false });
+
+        packet[32] = SET_LABEL as u8;
+        packet[36..43].copy_from_slice(b"Kitchen");
+        assert_eq!(request(&packet).1, Request::SetLabel("Kitchen"));
+    }
+
+    #[test]
+    fn invalid_and_unsupported_packets() {
+        // Truncated header, and a size beyond the end of the packet.
+        assert_eq!(parse_header(&GET_SERVICE_PACKET[..35]), None);
+        assert_eq!(parse_header(&SET_COLOR_PACKET[..48]), None);
+
+        let mut protocol = GET_SERVICE_PACKET;
+        protocol[3] = 0x24;
+        assert_eq!(parse_header(&protocol), None);
+
+        // Missing payload, and the unsupported SetWaveform.
+        assert_eq!(Request::parse(SET_COLOR, &SET_COLOR_PACKET[36..44]), None);
+        assert_eq!(Request::parse(103, &[0; 21]), None);
+    }
+
+    #[test]
+    fn encode_state_service() {
+        let (header, _) = request(&GET_SERVICE_PACKET);
+        let mut out = [0; 64];
+        let len = encode(&header, MAC, &Response::StateService, &mut out).unwrap();
+        assert_eq!(&out[..len], &[
+            0x29, 0x00, 0x00, 0x14, 0x3a, 0xcd, 0x1f, 0x5e, 0xd0, 0x73, 0xd5, 0x01, 0x02, 0x03, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00,
+            0x01, 0x7c, 0xdd, 0x00, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn encode_light_state() {
+        let (header, _) = request(&SET_COLOR_PACKET);
+        let color = Hsbk { hue: 0x5555, saturation: 0xffff, brightness: 0x8000, kelvin: 3500 };
+        let response = Response::LightState { color, on: true, label: "Kitchen" };
+        let mut out = [0; 128];
+        let len = encode(&header, MAC, &response, &mut out).unwrap();
+
+        assert_eq!(len, HEADER_SIZE + 52);
+        let (reply, payload) = parse_header(&out[..len]).unwrap();
+        assert_eq!(reply.message_type, LIGHT_STATE);
+        assert_eq!((reply.source, reply.sequence), (2, 7));
+        assert_eq!(&reply.target[..6], &MAC);
+        assert_eq!(Hsbk::parse(&payload[..8]), color);
+        assert_eq!(&payload[10..12], &[0xff, 0xff]);
+        assert_eq!(parse_label(&payload[12..44]), Some("Kitchen"));
+
+        assert_eq!(encode(&header, MAC, &response, &mut out[..87]), None);
+    }
+
+    #[test]
+    fn encode_version_and_ack() {
+        let (header, _) = request(&SET_COLOR_PACKET);
+        let mut out = [0; 64];
+        let len = encode(&header, MAC, &Response::StateVersion, &mut out).unwrap();
+        assert_eq!(&out[36..len], &[1, 0, 0, 0, 22, 0, 0, 0, 0, 0, 0, 0]);
+
+        let len = encode(&header, MAC, &Response::Acknowledgement, &mut out).unwrap();
+        assert_eq!(len, HEADER_SIZE);
+        assert_eq!(out[32], 45);
+    }
+
+    #[test]
+    fn long_labels_are_cut_off() {
+        let (header, _) = request(&GET_SERVICE_PACKET);
+        let label = "A label that is longer than thirty-two bytes";
+        let mut out = [0; 128];
+        let len = encode(&header, MAC, &Response::StateLabel(label), &mut out).unwrap();
+        assert_eq!(&out[36..len], &label.as_bytes()[..32]);
+    }
+
+    #[test]
+    fn hsbk_to_hcl() {
+        let hcl = Hsbk { hue: 0x4000, saturation: 0xffff, brightness: 0x8000, kelvin: 3500 }.to_hcl();
+        assert_eq!((hcl.h, hcl.c), (90.0, 1.0));
+        assert!((hcl.l - 0.5).abs() < 0.0001);
+
+        let hsbk = Hsbk::from_hcl(hcl, 2700);
+        assert_eq!(hsbk, Hsbk { hue: 0x4000, saturation: 0xffff, brightness: 0x8000, kelvin: 2700 });
+
+        let hsbk = Hsbk::from_hcl(HCL { h: -90.0, c: 1.5, l: -0.1 }, 2700);
+        assert_eq!((hsbk.hue, hsbk.saturation, hsbk.brightness), (0xc000, 0xffff, 0));
+        assert_eq!(Hsbk::from_hcl(HCL { h: 359.999, c: 0.0, l: 0.0 }, 2700).hue, 0);
+    }
+}
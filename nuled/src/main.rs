@@ -6,10 +6,37 @@ mod effect;
 mod color;
 mod mqtt;
 mod config;
+mod output;
+mod power;
+mod transition;
+mod homeassistant;
+mod identity;
+mod settings;
+mod persist;
+mod state;
+mod http;
+mod dns;
+mod dhcp;
+mod portal;
+mod provision;
+mod control;
+mod api;
+mod pixels;
+mod dmx;
+mod ddp;
+mod wled;
+mod zmtp;
+mod ledserver;
+mod realtime;
+mod lifx;
+mod bulb;
+#[cfg(feature = "tls")]
+mod tls;
 
 use core::str::FromStr;
-use crate::effect::{Effect, Params};
+use crate::effect::{Effect, Params, RgbArray};
 use crate::config::*;
+use crate::settings::{Settings, SettingsError};
 use embassy_executor::Spawner;
 use esp_backtrace as _;
 use esp_hal::clock::{ClockControl, Clocks};
@@ -22,9 +49,9 @@ use esp_hal::rng::Rng;
 use esp_hal::spi::SpiMode;
 use esp_hal::system::SystemControl;
 use esp_hal::timer::timg::TimerGroup;
+use esp_storage::FlashStorage;
 use esp_wifi::current_millis;
 use esp_wifi::wifi::WifiController;
-use heapless::{spsc};
 use smart_leds::SmartLedsWrite;
 use static_cell::StaticCell;
 use ws2812_spi::prerendered::Ws2812;
@@ -40,8 +67,11 @@ async fn main(spawner: Spawner) {
 
     static CLOCKS: StaticCell<Clocks> = StaticCell::new();
     static NETWORK_STACK: StaticCell<embassy_net::Stack<esp_wifi::wifi::WifiDevice<'_, esp_wifi::wifi::WifiStaDevice>>> = StaticCell::new();
-    static NETWORK_STACK_MEMORY: StaticCell<embassy_net::StackResources<3>> = StaticCell::new();
-    static COMMAND_QUEUE: StaticCell<spsc::Queue::<mqtt::EffectCommand, 16>> = StaticCell::new();
+    static NETWORK_STACK_MEMORY: StaticCell<embassy_net::StackResources<12>> = StaticCell::new();
+    static AP_NETWORK_STACK: StaticCell<embassy_net::Stack<esp_wifi::wifi::WifiDevice<'_, esp_wifi::wifi::WifiApDevice>>> = StaticCell::new();
+    static AP_NETWORK_STACK_MEMORY: StaticCell<embassy_net::StackResources<3>> = StaticCell::new();
+    static IDENTITY: StaticCell<identity::Identity> = StaticCell::new();
+    static SETTINGS: StaticCell<Settings> = StaticCell::new();
 
     let peripherals = Peripherals::take();
     let system = SystemControl::new(peripherals.SYSTEM);
@@ -52,6 +82,22 @@ async fn main(spawner: Spawner) {
 
     let embassy_timer = TimerGroup::new(peripherals.TIMG0, clocks);
 
+    let settings = match Settings::load(&mut FlashStorage::new(), SETTINGS_FLASH_OFFSET) {
+        Ok(settings) => {
+            info!("Loaded settings from flash.");
+            settings
+        }
+        Err(SettingsError::Missing) => {
+            info!("No settings stored in flash, using compile-time configuration.");
+            default_settings()
+        }
+        Err(err) => {
+            warn!("Unable to load settings from flash, using compile-time configuration: {:?}", err);
+            default_settings()
+        }
+    };
+    let settings: &'static Settings = SETTINGS.init(settings);
+
     info!("Initializing embassy...");
     esp_hal_embassy::init(clocks, embassy_timer.timer0);
 
@@ -66,20 +112,20 @@ async fn main(spawner: Spawner) {
         &clocks,
     ).unwrap();
 
-    debug!("Configuring WiFi for station mode.");
+    // The access point is only started if the device fails to connect to WiFi, see `provision`.
+    debug!("Configuring WiFi for station and access point mode.");
 
-    let (wifi_interface, wifi_controller) =
-        esp_wifi::wifi::new_with_mode(
+    let (ap_interface, wifi_interface, wifi_controller) =
+        esp_wifi::wifi::new_ap_sta(
             &wifi_init,
             peripherals.WIFI,
-            esp_wifi::wifi::WifiStaDevice,
         ).unwrap();
 
     let network_config = embassy_net::Config::dhcpv4(Default::default());
 
     let seed = 1234; // very random, very secure seed
 
-    let stack_resources: &'static mut _ = NETWORK_STACK_MEMORY.init(embassy_net::StackResources::<3>::new());
+    let stack_resources: &'static mut _ = NETWORK_STACK_MEMORY.init(embassy_net::StackResources::<12>::new());
 
     let network_stack: &'static mut _ = NETWORK_STACK.init(
         embassy_net::Stack::new(
@@ -90,18 +136,36 @@ async fn main(spawner: Spawner) {
         )
     );
 
-    let command_queue: &'static mut _ = COMMAND_QUEUE.init(
-        spsc::Queue::<mqtt::EffectCommand, 16>::new()
+    let ap_stack_resources: &'static mut _ = AP_NETWORK_STACK_MEMORY.init(embassy_net::StackResources::<3>::new());
+
+    let ap_network_stack: &'static mut _ = AP_NETWORK_STACK.init(
+        embassy_net::Stack::new(
+            ap_interface,
+            provision::network_config(),
+            ap_stack_resources,
+            seed,
+        )
     );
 
-    let (mut producer, consumer) = command_queue.split();
+    let mac = esp_hal::efuse::Efuse::get_mac_address();
+    let identity = identity::Identity::new(mac, DEVICE_ID, MQTT_TOPIC_PREFIX, MQTT_CLIENT_ID)
+        .unwrap_or_else(|err| panic!("Invalid device id, MQTT topic prefix or client id: {:?}", err));
+    let identity: &'static _ = IDENTITY.init(identity);
+
+    info!("Device id is {}, using MQTT topic prefix {}.", identity.device_id, identity.topic_prefix);
 
-    let _ = producer.enqueue(mqtt::EffectCommand::ChangeEffect(mqtt::Effect::Rainbow));
+    control::init(persist::load(settings.power_on));
 
-    spawner.must_spawn(wifi_task(wifi_controller));
+    spawner.must_spawn(wifi_task(wifi_controller, identity, settings));
     spawner.must_spawn(net_task(network_stack));
-    spawner.must_spawn(mqtt::mqtt_task(network_stack, producer));
-    spawner.must_spawn(led_task(peripherals.SPI2, io.pins.gpio8, peripherals.DMA, clocks, consumer));
+    spawner.must_spawn(ap_net_task(ap_network_stack));
+    spawner.must_spawn(provision::portal_task(ap_network_stack, identity, settings));
+    spawner.must_spawn(persist::persist_task());
+    spawner.must_spawn(mqtt::mqtt_task(network_stack, identity, settings));
+    spawner.must_spawn(api::api_task(network_stack, identity, settings));
+    spawner.must_spawn(realtime::realtime_task(network_stack));
+    spawner.must_spawn(bulb::bulb_task(network_stack, identity, esp_hal::efuse::Efuse::get_mac_address()));
+    spawner.must_spawn(led_task(peripherals.SPI2, io.pins.gpio8, peripherals.DMA, clocks, settings));
 
     loop {
         embassy_time::Timer::after_secs(1).await;
@@ -113,30 +177,53 @@ async fn net_task(stack: &'static embassy_net::Stack<esp_wifi::wifi::WifiDevice<
     stack.run().await
 }
 
+#[embassy_executor::task]
+async fn ap_net_task(stack: &'static embassy_net::Stack<esp_wifi::wifi::WifiDevice<'static, esp_wifi::wifi::WifiApDevice>>) {
+    stack.run().await
+}
+
 #[embassy_executor::task]
 async fn wifi_task(
     mut wifi_controller: WifiController<'static>,
+    identity: &'static identity::Identity,
+    settings: &'static Settings,
 ) {
     use esp_wifi::wifi::*;
     use embassy_time::Duration;
     use embassy_time::Timer;
+    use core::fmt::Write;
+
+    /// Number of failed connection attempts before starting the provisioning portal.
+    const CONNECT_ATTEMPTS: u32 = 5;
+
+    /// How long to keep the provisioning portal running before restarting and trying
+    /// the stored WiFi settings again, e.g. if the WiFi router took long to start after a power cut.
+    const PORTAL_TIMEOUT: Duration = Duration::from_secs(600);
 
     info!("WiFi task started.");
 
+    let client_config = ClientConfiguration {
+        ssid: settings.wifi_ssid.as_str().try_into().unwrap(),
+        password: settings.wifi_password.as_str().try_into().unwrap(),
+        auth_method: AuthMethod::WPA2Personal,
+        ..Default::default()
+    };
+
+    let mut failures = 0;
+
     loop {
         if let WifiState::StaConnected = get_wifi_state() {
+            failures = 0;
             wifi_controller.wait_for_event(WifiEvent::StaDisconnected).await;
             Timer::after(Duration::from_millis(5000)).await
         }
 
+        if settings.wifi_ssid.is_empty() || failures >= CONNECT_ATTEMPTS {
+            break;
+        }
+
         if !matches!(wifi_controller.is_started(), Ok(true)) {
-            let client_config = Configuration::Client(ClientConfiguration {
-                ssid: WIFI_SSID.try_into().unwrap(),
-                password: WIFI_PASSWORD.try_into().unwrap(),
-                auth_method: AuthMethod::WPA2Personal,
-                ..Default::default()
-            });
-            wifi_controller.set_configuration(&client_config).unwrap();
+            wifi_controller.set_configuration(&Configuration::Client(client_config.clone())).unwrap();
             info!("Starting WiFi controller...");
             wifi_controller.start().await.unwrap();
             info!("WiFi started.");
@@ -156,10 +243,36 @@ async fn wifi_task(
                     WifiError::UnknownWifiMode => "unknown wifi mode",
                 };
                 error!("WiFi connect error: {}", msg);
+                failures += 1;
                 Timer::after(Duration::from_millis(5000)).await;
             }
         }
     }
+
+    let mut ap_ssid = heapless::String::<32>::new();
+    let _ = write!(ap_ssid, "NULED-{}", identity.device_id);
+    warn!("Unable to connect to WiFi, starting provisioning access point {}.", ap_ssid);
+
+    let ap_config = AccessPointConfiguration {
+        ssid: ap_ssid.as_str().try_into().unwrap(),
+        auth_method: AuthMethod::None,
+        ..Default::default()
+    };
+
+    if matches!(wifi_controller.is_started(), Ok(true)) {
+        let _ = wifi_controller.stop().await;
+    }
+    wifi_controller.set_configuration(&Configuration::Mixed(client_config, ap_config)).unwrap();
+    wifi_controller.start().await.unwrap();
+    provision::START.signal(());
+
+    if settings.wifi_ssid.is_empty() {
+        return;
+    }
+
+    Timer::after(PORTAL_TIMEOUT).await;
+    info!("Provisioning portal timed out, restarting.");
+    esp_hal::reset::software_reset();
 }
 
 /// The LED task is responsible for displaying data on the LED strip in a timely manner.
@@ -169,7 +282,7 @@ async fn led_task(
     pin: GpioPin<8>,
     dma: esp_hal::peripherals::DMA,
     clocks: &'static Clocks<'static>,
-    mut queue: spsc::Consumer<'static, mqtt::EffectCommand, 16>,
+    settings: &'static Settings,
 ) {
     info!("LED task started.");
     info!("Setting up DMA buffers.");
@@ -208,58 +321,106 @@ async fn led_task(
 
     info!("WS2812 driver started on SPI2 and GPIO8.");
 
+    // Effects are always rendered for the maximum number of LEDs, and cut off at the configured count.
+    let led_count = LED_COUNT.min(settings.led_count as usize);
+
     use static_box::Box;
     let mut mem = [0_u8; 4096];
     let mut effect: Box<dyn Effect<LED_COUNT>> = Box::new(&mut mem, effect::Polyrhythm::<LED_COUNT>::default());
     let mut state = Params::default();
+    let mut output = output::OutputStage::<LED_COUNT>::new(WHITE_MODE);
+    let mut transition = transition::Transition::<LED_COUNT>::default();
+
+    // The frame currently shown on the LED strip, and the last frame produced by the effect.
+    let mut shown = RgbArray::<LED_COUNT>::default();
+    let mut target = RgbArray::<LED_COUNT>::default();
+
+    // Whether realtime pixel data has been shown since the effect last ran.
+    let mut realtime_shown = false;
+
+    // The state last applied, to tell which parts of a new state have changed.
+    let mut applied: Option<state::ServerState> = None;
 
     loop {
-        let Some(command) = queue.dequeue() else {
+        // Realtime pixel data bypasses the effect, and is written as soon as it arrives.
+        if realtime::is_active() {
+            if let Some(frame) = realtime::take_frame() {
+                shown = frame;
+                let rgb_values = output.render(shown, led_count);
+                power::store(output.power());
+                critical_section::with(|_| {
+                    ws.write(rgb_values.into_iter().take(led_count)).expect("failed LED update")
+                });
+            }
+            realtime_shown = true;
             embassy_time::Timer::after_millis(1).await;
             continue;
-        };
+        }
+
+        if !control::LED_STATE.signaled() && !realtime_shown {
+            embassy_time::Timer::after_millis(1).await;
+            continue;
+        }
 
-        match command {
-            mqtt::EffectCommand::ChangeEffect(eff) => {
+        // Apply the latest state before rendering the next frame.
+        // A new effect or new effect parameters fade in, output parameters apply at once.
+        // After realtime pixel data, the effect starts over, and fades in from the last frame received.
+        let mut changed = realtime_shown;
+        if realtime_shown {
+            effect.configure(state.clone());
+            realtime_shown = false;
+        }
+        if let Some(new) = control::LED_STATE.try_take() {
+            let effect_changed = applied.map_or(true, |applied| applied.effect != new.effect);
+            if effect_changed {
                 drop(effect);
-                effect = match eff {
-                    mqtt::Effect::Solid => Box::new(&mut mem, effect::Solid::<LED_COUNT>::default()),
-                    mqtt::Effect::Rainbow => Box::new(&mut mem, effect::Rainbow::<LED_COUNT>::default()),
-                    mqtt::Effect::Gradient => Box::new(&mut mem, effect::Gradient::<LED_COUNT>::default()),
-                    mqtt::Effect::Polyrhythm => Box::new(&mut mem, effect::Polyrhythm::<LED_COUNT>::default()),
+                effect = match new.effect {
+                    state::Effect::Solid => Box::new(&mut mem, effect::Solid::<LED_COUNT>::default()),
+                    state::Effect::Rainbow => Box::new(&mut mem, effect::Rainbow::<LED_COUNT>::default()),
+                    state::Effect::Gradient => Box::new(&mut mem, effect::Gradient::<LED_COUNT>::default()),
+                    state::Effect::Polyrhythm => Box::new(&mut mem, effect::Polyrhythm::<LED_COUNT>::default()),
                 };
-                effect.configure(state.clone());
             }
-            mqtt::EffectCommand::ConfigureParams(new_state) => {
-                state = new_state;
+            if effect_changed || applied.map_or(true, |applied| applied.led_effect_params != new.led_effect_params) {
+                state = new.led_effect_params;
                 effect.configure(state.clone());
+                changed = true;
+            }
+            if applied.map_or(true, |applied| applied.output_params != new.output_params) {
+                output.configure(new.output_params);
             }
+            applied = Some(new);
+        }
+
+        if changed {
+            transition.start(&shown, state.transition_ms(), current_millis());
         }
 
         let mut last_effect_millis = current_millis();
 
         // Run the current effect until it is exhausted, or the user has requested a new effect.
-        while let Some(strip) = effect.next() {
+        loop {
             /// Maximum amount of time budget for one frame of animation.
             /// 42ms corresponds to just below 24 frames per second, which is sufficient
             /// for the eye to not notice individual frames, while at the same time
             /// allowing for effects that do expensive float computation.
             const EFFECT_RUNTIME_NOMINAL_MS: i64 = 42;
 
-            // Maximum LED brightness regardless of other parameters.
-            //const BRIGHTNESS: u8 = 127;
+            let strip = match effect.next() {
+                Some(strip) => strip,
+                // Exhausted effects keep their last frame until the transition is complete.
+                None if transition.is_active(current_millis()) => target,
+                None => break,
+            };
+            target = strip;
+            shown = transition.apply(strip, current_millis());
 
-            // let data = smart_leds::brightness(
-            //     smart_leds::gamma(data.iter().cloned()),
-            //     BRIGHTNESS,
-            // );
-            //let rgb_values = strip.to_rgb8();
-            let rgb_values = strip.to_rgbw();
-            //let gamma_corrected = smart_leds::gamma(rgb_values.iter().cloned());
+            let rgb_values = output.render(shown, led_count);
+            power::store(output.power());
 
             let pre_write_ms = current_millis();
             critical_section::with(|_| {
-                ws.write(rgb_values).expect("failed LED update")
+                ws.write(rgb_values.into_iter().take(led_count)).expect("failed LED update")
             });
             debug!("LED critical section in {} ms", current_millis() - pre_write_ms);
 
@@ -276,7 +437,7 @@ async fn led_task(
 
             embassy_time::Timer::after_micros(sleep_time).await;
 
-            if let Some(_) = queue.peek() {
+            if control::LED_STATE.signaled() || realtime::is_active() {
                 break;
             }
         }
@@ -1,4 +1,4 @@
-use crate::color::RGB;
+use crate::color::{Kelvin, RGB};
 use crate::rust_mqtt::{
     client::client_config::ClientConfig,
     utils::rng_generator::CountingRng,
@@ -8,16 +8,41 @@ use crate::config::*;
 use embassy_net::dns;
 use embassy_net::tcp::TcpSocket;
 use embassy_time::Duration;
+use embassy_time::Instant;
 use embassy_time::Timer;
 use embedded_io_async::{Read, Write};
-use heapless::{spsc, String};
+use heapless::String;
 use rand_core::RngCore;
 use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
 use rust_mqtt::packet::v5::publish_packet::QualityOfService::*;
-use crate::rust_mqtt::client::client_config::MqttVersion;
 use core::fmt::Write as _;
+use core::future::Future;
 use core::str::FromStr;
-use crate::effect::Params;
+use crate::output;
+use crate::persist;
+use crate::power;
+use crate::control;
+use crate::homeassistant;
+use crate::identity::Identity;
+use crate::settings::{Settings, SettingsError};
+use crate::state::{self, Effect, JsonCommand, JsonState, ServerState};
+use esp_storage::FlashStorage;
+use embassy_futures::select::{select, select3, Either, Either3};
+use serde::Serialize;
+
+/// How often to report the estimated power usage of the LED strip.
+const POWER_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Keep alive requested from the broker, in seconds. Pings are sent twice per interval,
+/// so a dead broker is noticed within one interval. The broker publishes the last will
+/// after one and a half intervals without a packet from the device.
+const KEEP_ALIVE_SECS: u16 = 15;
+
+/// Retained on the `availability` topic after connecting. The broker replaces it with
+/// `OFFLINE`, the last will, when the connection is lost.
+const ONLINE: &[u8] = b"online";
+const OFFLINE: &[u8] = b"offline";
 
 const RX_BUFFER_SIZE: usize = 16384;
 const TX_BUFFER_SIZE: usize = 16384;
@@ -43,41 +68,74 @@ impl MqttMessage<'_> {
 
     fn parse_effect(&self) -> Option<Effect> {
         let effect_str = core::str::from_utf8(self.0).ok()?;
-        match effect_str {
-            "rainbow" => Some(Effect::Rainbow),
-            "solid" => Some(Effect::Solid),
-            "polyrhythm" => Some(Effect::Polyrhythm),
-            "gradient" => Some(Effect::Gradient),
-            _ => None,
-        }
+        Effect::ALL.into_iter().find(|effect| effect.name() == effect_str)
     }
 
     fn parse_float(&self) -> Option<f32> {
         let s = core::str::from_utf8(self.0).ok()?;
         f32::from_str(s).ok()
     }
+
+    fn parse_bool(&self) -> Option<bool> {
+        let s = core::str::from_utf8(self.0).ok()?;
+        state::parse_on_off(s)
+    }
+
+    /// Parse gamma as either a single value for all channels, or one value each for `r,g,b,w`.
+    fn parse_gamma(&self) -> Option<[f32; 4]> {
+        let s = core::str::from_utf8(self.0).ok()?;
+        let gamma = match f32::from_str(s) {
+            Ok(gamma) => [gamma; 4],
+            Err(_) => {
+                let mut parts = s.split(",");
+                let mut gamma = [0.0; 4];
+                for channel in gamma.iter_mut() {
+                    *channel = f32::from_str(parts.next()?).ok()?;
+                }
+                if parts.next().is_some() {
+                    return None;
+                }
+                gamma
+            }
+        };
+        output::valid_gamma(&gamma).then_some(gamma)
+    }
+
+    fn parse_json(&self) -> Option<JsonCommand<'_>> {
+        serde_json_core::from_slice(self.0).ok().map(|(command, _)| command)
+    }
+
+    /// Parse a color temperature such as `2700` or `2700K`.
+    fn parse_temperature(&self) -> Option<Kelvin> {
+        let s = core::str::from_utf8(self.0).ok()?;
+        let s = s.trim_end_matches(['K', 'k']);
+        f32::from_str(s).ok().map(Kelvin)
+    }
 }
 
 enum MqttResponse {
     RGB(RGB),
     Effect(Effect),
     Number(f32),
+    Bool(bool),
+    Gamma([f32; 4]),
 }
 
 impl MqttResponse {
-    fn serialize<'a>(self) -> Option<String<32>> {
+    fn serialize<'a>(self) -> Option<String<64>> {
         let mut s = String::new();
         match self {
             MqttResponse::RGB(rgb) => {
                 write!(s, "{},{},{}", rgb.r, rgb.g, rgb.b).ok()?;
             }
+            MqttResponse::Bool(value) => {
+                s.write_str(if value { "on" } else { "off" }).ok()?;
+            }
+            MqttResponse::Gamma(gamma) => {
+                write!(s, "{},{},{},{}", gamma[0], gamma[1], gamma[2], gamma[3]).ok()?;
+            }
             MqttResponse::Effect(effect) => {
-                s.write_str(match effect {
-                    Effect::Solid => "solid",
-                    Effect::Rainbow => "rainbow",
-                    Effect::Polyrhythm => "polyrhythm",
-                    Effect::Gradient => "gradient",
-                }).ok()?;
+                s.write_str(effect.name()).ok()?;
             }
             MqttResponse::Number(num) => {
                 let mut buf = ryu::Buffer::new();
@@ -88,40 +146,30 @@ impl MqttResponse {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone)]
-struct ServerState {
-    effect: Effect,
-    led_effect_params: Params,
-}
-
-#[derive(Debug, Default, Copy, Clone)]
-pub enum Effect {
-    Solid,
-    #[default]
-    Rainbow,
-    Gradient,
-    Polyrhythm,
-}
-
 #[derive(Debug)]
-pub enum EffectCommand {
-    ChangeEffect(Effect),
-    ConfigureParams(Params),
-}
-
 enum Error {
     MqttReceive(rust_mqtt::packet::v5::reason_codes::ReasonCode),
     MqttPublish(rust_mqtt::packet::v5::reason_codes::ReasonCode),
+    MqttKeepAlive(rust_mqtt::packet::v5::reason_codes::ReasonCode),
     InvalidTopic,
     ParseParameter,
     Serialize,
+    Settings(SettingsError),
 }
 
 #[embassy_executor::task]
 pub async fn mqtt_task(
     stack: &'static embassy_net::Stack<esp_wifi::wifi::WifiDevice<'static, esp_wifi::wifi::WifiStaDevice>>,
-    mut queue: spsc::Producer<'static, EffectCommand, 16>,
+    identity: &'static Identity,
+    settings: &'static Settings,
 ) {
+    // Settings changed over MQTT are saved to flash, and take effect after a restart.
+    let mut pending_settings = settings.clone();
+
+    let Some(availability_topic) = identity.topic("availability") else {
+        panic!("MQTT topic prefix too long");
+    };
+
     loop {
         if !stack.is_link_up() {
             warn!("Waiting for network...");
@@ -138,9 +186,9 @@ pub async fn mqtt_task(
 
         info!("Acquired IPv4 address {}", config.address);
 
-        let mqtt_server_ip = match stack.dns_query(MQTT_SERVER, dns::DnsQueryType::A).await {
+        let mqtt_server_ip = match stack.dns_query(&settings.mqtt_server, dns::DnsQueryType::A).await {
             Err(err) => {
-                warn!("DNS query failed for {}, retrying in 30s: {:?}", MQTT_SERVER, err);
+                warn!("DNS query failed for {}, retrying in 30s: {:?}", settings.mqtt_server, err);
                 Timer::after_secs(30).await;
                 continue;
             }
@@ -156,35 +204,57 @@ pub async fn mqtt_task(
         sock.set_timeout(Some(Duration::from_secs(30)));
         sock.set_keep_alive(Some(Duration::from_secs(15)));
 
-        info!("Connecting to {} ({}) port {}...", MQTT_SERVER, mqtt_server_ip, MQTT_PORT);
+        info!("Connecting to {} ({}) port {}...", settings.mqtt_server, mqtt_server_ip, settings.mqtt_port);
 
-        if let Err(err) = sock.connect((mqtt_server_ip, MQTT_PORT)).await {
-            error!("Unable to connect to MQTT at {}:{}: {:?}", MQTT_SERVER, MQTT_PORT, err);
+        if let Err(err) = sock.connect((mqtt_server_ip, settings.mqtt_port)).await {
+            error!("Unable to connect to MQTT at {}:{}: {:?}", settings.mqtt_server, settings.mqtt_port, err);
             Timer::after_secs(5).await;
             continue;
         };
 
+        #[cfg(feature = "tls")]
+        let tcp = core::cell::RefCell::new(sock);
+        #[cfg(feature = "tls")]
+        let sock = match crate::tls::connect(&tcp, &settings.mqtt_server).await {
+            Ok(sock) => sock,
+            Err(err) => {
+                error!("TLS handshake with {} failed: {:?}", settings.mqtt_server, err);
+                Timer::after_secs(5).await;
+                continue;
+            }
+        };
+
         info!("Connected to MQTT, authenticating...");
 
         const MQTT_BUFFER_SIZE: usize = 1024;
 
         let mut config = ClientConfig::new(
-            MqttVersion::MQTTv5,
+            MQTT_VERSION,
             CountingRng(20000),
         );
 
-        config.add_username(MQTT_USERNAME);
-        config.add_password(MQTT_PASSWORD);
+        config.add_username(&settings.mqtt_username);
+        config.add_password(&settings.mqtt_password);
         config.add_max_subscribe_qos(QoS1);
-        config.add_client_id("ruled");
+        config.add_client_id(&identity.client_id);
         config.max_packet_size = MQTT_BUFFER_SIZE as u32;
-        config.keep_alive = 3600;
+        config.keep_alive = KEEP_ALIVE_SECS;
+        config.add_will(&availability_topic, OFFLINE, true);
 
         let mut recv_buffer = [0; MQTT_BUFFER_SIZE];
         let mut write_buffer = [0; MQTT_BUFFER_SIZE];
-
-        let mut client =
-            MqttClient::<_, 5, _>::new(sock, &mut write_buffer, MQTT_BUFFER_SIZE, &mut recv_buffer, MQTT_BUFFER_SIZE, config);
+        // Commands received while waiting for the broker to acknowledge a subscription or message.
+        let mut queue_buffer = [0; MQTT_BUFFER_SIZE];
+
+        let mut client = MqttClient::<_, 5, _>::new_with_queue(
+            sock,
+            &mut write_buffer,
+            MQTT_BUFFER_SIZE,
+            &mut recv_buffer,
+            MQTT_BUFFER_SIZE,
+            &mut queue_buffer,
+            config,
+        );
 
         if let Err(err) = client.connect_to_broker().await {
             error!("MQTT authentication failed: {:?}", err);
@@ -194,18 +264,74 @@ pub async fn mqtt_task(
 
         info!("MQTT authenticated.");
 
-        if let Err(err) = client.subscribe_to_topic("led/pallet/+/set").await {
-            error!("Unable to subscribe to {}: {:?}", "topic", err);
+        let (Some(set_topic), Some(set_any_topic), Some(config_topic)) =
+            (identity.topic("set"), identity.topic("+/set"), identity.topic("config/+/set")) else {
+            panic!("MQTT topic prefix too long");
+        };
+        let mut topics = heapless::Vec::<&str, 3>::new();
+        let _ = topics.push(&set_topic);
+        let _ = topics.push(&set_any_topic);
+        let _ = topics.push(&config_topic);
+
+        let subscribed = loop {
+            match client.subscribe_to_topics(&topics).await {
+                // Commands of the resumed session filled the queue, handle them and try again.
+                Err(ReasonCode::QuotaExceeded) if client.has_queued_message() => {
+                    while client.has_queued_message() {
+                        let Ok((topic, data)) = client.receive_message().await else {
+                            break;
+                        };
+                        if let Err(err) = mqtt_handle_message(identity, &mut pending_settings, topic, data) {
+                            warn!("Unable to handle queued MQTT message: {:?}", err);
+                        }
+                    }
+                }
+                result => break result,
+            }
+        };
+
+        if let Err(err) = subscribed {
+            error!("Unable to subscribe to {:?}: {:?}", topics, err);
             Timer::after_secs(5).await;
             continue;
         };
 
         info!("MQTT subscribed.");
 
-        let mut state = ServerState::default();
+        if let Err(err) = mqtt_publish_discovery(&mut client, identity).await {
+            error!("Unable to publish Home Assistant discovery config: {:?}", err);
+            Timer::after_secs(5).await;
+            continue;
+        }
+
+        if let Err(err) = mqtt_publish_all(&mut client, identity, &control::state()).await {
+            error!("Unable to publish initial state: {:?}", err);
+            Timer::after_secs(5).await;
+            continue;
+        }
+
+        if let Err(err) = client.send_message(&availability_topic, ONLINE, QoS0, true).await {
+            error!("Unable to publish availability: {:?}", err);
+            Timer::after_secs(5).await;
+            continue;
+        }
+
+        // The broker may assign a different keep alive in CONNACK, or disable it with 0.
+        let ping_interval = match client.keep_alive() {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64) / 2),
+        };
+        let mut next_ping = ping_interval.map(|interval| Instant::now() + interval);
+        let mut next_power_report = Instant::now() + POWER_REPORT_INTERVAL;
 
         loop {
-            let Err(err) = mqtt_process_message(&mut client, &mut state, &mut queue).await else {
+            // Reading TLS is not known to be cancel-safe, see `tls`.
+            #[cfg(feature = "tls")]
+            let readable = Some(crate::tls::readable(&tcp));
+            #[cfg(not(feature = "tls"))]
+            let readable = None::<core::future::Pending<()>>;
+
+            let Err(err) = mqtt_process_message(&mut client, identity, &mut pending_settings, readable, ping_interval, &mut next_ping, &mut next_power_report).await else {
                 continue;
             };
 
@@ -218,6 +344,10 @@ pub async fn mqtt_task(
                     error!("MQTT publish packet error: {:?}", err);
                     break;
                 }
+                Error::MqttKeepAlive(err) => {
+                    error!("MQTT broker did not answer ping: {:?}", err);
+                    break;
+                }
                 Error::InvalidTopic => {
                     debug!("MQTT received data on unrecognized topic");
                 }
@@ -225,19 +355,31 @@ pub async fn mqtt_task(
                     error!("Unable to parse color or effect parameter from MQTT");
                 }
                 Error::Serialize => {
-                    error!("RGB serialization failed");
+                    error!("State serialization failed");
+                }
+                Error::Settings(err) => {
+                    error!("Unable to change settings: {:?}", err);
                 }
             }
         }
     }
 }
 
-/// Receive a valid message over any of the configured MQTT topics, configure LEDs based on that,
-/// and report back the current state.
+/// Receive a valid message over any of the configured MQTT topics, and configure LEDs based on that.
+/// Whenever the state changes, from MQTT or any other interface, the new state is reported back.
+/// A ping is sent at `next_ping`, and if the previous one was not answered, the connection is dead.
+/// The power usage is reported at `next_power_report`.
+///
+/// `readable` is `None` when reading the socket is cancel-safe. Otherwise, it resolves when
+/// data arrives, and only then is the message received.
 async fn mqtt_process_message<'a, T, const MAX_PROPERTIES: usize, R>(
     client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
-    state: &mut ServerState,
-    queue: &mut spsc::Producer<'static, EffectCommand, 16>,
+    identity: &Identity,
+    settings: &mut Settings,
+    readable: Option<impl Future<Output = ()>>,
+    ping_interval: Option<Duration>,
+    next_ping: &mut Option<Instant>,
+    next_power_report: &mut Instant,
 ) -> Result<(), Error>
 where
     T: Read + Write,
@@ -245,61 +387,238 @@ where
 {
     use Error::*;
 
-    let (topic, data) = client.receive_message().await.map_err(MqttReceive)?;
+    // Report state changes and power usage while waiting for messages.
+    let changed = control::CHANGED.wait();
+    let ping_at = *next_ping;
+    let ping = async move {
+        match ping_at {
+            Some(at) => Timer::at(at).await,
+            None => core::future::pending().await,
+        }
+    };
+    let power_report = Timer::at(*next_power_report);
+    let events = select3(changed, power_report, ping);
+    let (topic, data) = match readable {
+        // Cancelling the receive is safe, the client keeps a partially received packet and
+        // resumes it on the next call, as long as reading the socket itself is cancel-safe.
+        None => match select(client.receive_message(), events).await {
+            Either::First(received) => received.map_err(MqttReceive)?,
+            Either::Second(event) => {
+                return mqtt_handle_event(client, identity, event, ping_interval, next_ping, next_power_report).await;
+            }
+        },
+        Some(readable) => match select(readable, events).await {
+            Either::First(()) => client.receive_message().await.map_err(MqttReceive)?,
+            Either::Second(event) => {
+                return mqtt_handle_event(client, identity, event, ping_interval, next_ping, next_power_report).await;
+            }
+        },
+    };
+
+    mqtt_handle_message(identity, settings, topic, data)
+}
+
+/// Handle a command received on `topic`.
+fn mqtt_handle_message(identity: &Identity, settings: &mut Settings, topic: &str, data: &[u8]) -> Result<(), Error> {
+    use Error::*;
 
     debug!("MQTT receive on {}: {:?}", topic, data);
 
     let message = MqttMessage(data);
 
+    match identity.strip_prefix(topic).ok_or(InvalidTopic)? {
+        "restart/set" => {
+            info!("Restart requested over MQTT.");
+            esp_hal::reset::software_reset();
+            Ok(())
+        }
+        "preset/set" => {
+            persist::save_preset(&control::state()).map_err(Error::Settings)?;
+            info!("Saved the current state as the power-on preset.");
+            Ok(())
+        }
+        topic if topic.starts_with("config/") => {
+            let key = topic.strip_prefix("config/").and_then(|t| t.strip_suffix("/set")).ok_or(InvalidTopic)?;
+            let value = core::str::from_utf8(data).map_err(|_| ParseParameter)?;
+            settings.set(key, value).map_err(Error::Settings)?;
+            settings.save(&mut FlashStorage::new(), SETTINGS_FLASH_OFFSET).map_err(Error::Settings)?;
+            info!("Saved setting {} to flash, restart to apply.", key);
+            Ok(())
+        }
+        topic => control::update(|state| mqtt_apply_message(state, topic, &message)).map(|_| ()),
+    }
+}
+
+/// Report the state after a change, report the power usage, or send a ping.
+async fn mqtt_handle_event<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    identity: &Identity,
+    event: Either3<(), (), ()>,
+    ping_interval: Option<Duration>,
+    next_ping: &mut Option<Instant>,
+    next_power_report: &mut Instant,
+) -> Result<(), Error>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    match event {
+        Either3::First(()) => mqtt_publish_all(client, identity, &control::state()).await,
+        Either3::Second(()) => {
+            *next_power_report = Instant::now() + POWER_REPORT_INTERVAL;
+            mqtt_publish_power(client, identity).await
+        }
+        Either3::Third(()) => {
+            *next_ping = ping_interval.map(|interval| Instant::now() + interval);
+            client.send_keep_alive().await.map_err(Error::MqttKeepAlive)
+        }
+    }
+}
+
+/// Apply a message on one of the state topics.
+fn mqtt_apply_message(state: &mut ServerState, topic: &str, message: &MqttMessage) -> Result<(), Error> {
+    use Error::*;
+
     match topic {
-        "led/pallet/color1/set" => {
+        "set" => {
+            let command: JsonCommand = message.parse_json().ok_or(ParseParameter)?;
+            command.apply(state).map_err(|_| ParseParameter)?;
+        }
+        "on/set" => {
+            state.output_params.on = message.parse_bool().ok_or(ParseParameter)?;
+        }
+        "color1/set" => {
             state.led_effect_params.color1 = message.parse_rgb().ok_or(ParseParameter)?;
         }
-        "led/pallet/color2/set" => {
+        "temperature/set" => {
+            state.led_effect_params.color1 = message.parse_temperature().ok_or(ParseParameter)?.into();
+        }
+        "color2/set" => {
             state.led_effect_params.color2 = message.parse_rgb().ok_or(ParseParameter)?;
         }
-        "led/pallet/chroma/set" => {
+        "chroma/set" => {
             state.led_effect_params.chroma = message.parse_float().ok_or(ParseParameter)?;
         }
-        "led/pallet/luminance/set" => {
+        "luminance/set" => {
             state.led_effect_params.luminance = message.parse_float().ok_or(ParseParameter)?;
         }
-        "led/pallet/speed/set" => {
+        "speed/set" => {
             state.led_effect_params.speed = message.parse_float().ok_or(ParseParameter)?;
         }
-        "led/pallet/size/set" => {
+        "size/set" => {
             state.led_effect_params.size = message.parse_float().ok_or(ParseParameter)?;
         }
-        "led/pallet/effect/set" => {
+        "transition/set" => {
+            state.led_effect_params.transition = message.parse_float().ok_or(ParseParameter)?;
+        }
+        "effect/set" => {
             state.effect = message.parse_effect().ok_or(ParseParameter)?;
-            let _ = queue.enqueue(EffectCommand::ChangeEffect(state.effect));
         }
-        _ => return Err(InvalidTopic)
-    };
+        "gamma/set" => {
+            state.output_params.gamma = message.parse_gamma().ok_or(ParseParameter)?;
+        }
+        "brightness/set" => {
+            state.output_params.brightness = message.parse_float().ok_or(ParseParameter)?;
+        }
+        "power_budget/set" => {
+            state.output_params.power_budget = message.parse_float().ok_or(ParseParameter)?;
+        }
+        "dither/set" => {
+            state.output_params.dither = message.parse_bool().ok_or(ParseParameter)?;
+        }
+        _ => return Err(InvalidTopic),
+    }
+
+    Ok(())
+}
+
+/// Report back the complete state, so that clients such as Home Assistant stay in sync.
+async fn mqtt_publish_all<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    identity: &Identity,
+    state: &ServerState,
+) -> Result<(), Error>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    mqtt_publish_json(client, identity, "state", &JsonState::from(state)).await?;
+    mqtt_publish_state(client, identity, "on", MqttResponse::Bool(state.output_params.on)).await?;
+    mqtt_publish_state(client, identity, "color1", MqttResponse::RGB(state.led_effect_params.color1)).await?;
+    mqtt_publish_state(client, identity, "color2", MqttResponse::RGB(state.led_effect_params.color2)).await?;
+    mqtt_publish_state(client, identity, "effect", MqttResponse::Effect(state.effect)).await?;
+    mqtt_publish_state(client, identity, "chroma", MqttResponse::Number(state.led_effect_params.chroma)).await?;
+    mqtt_publish_state(client, identity, "luminance", MqttResponse::Number(state.led_effect_params.luminance)).await?;
+    mqtt_publish_state(client, identity, "size", MqttResponse::Number(state.led_effect_params.size)).await?;
+    mqtt_publish_state(client, identity, "speed", MqttResponse::Number(state.led_effect_params.speed)).await?;
+    mqtt_publish_state(client, identity, "transition", MqttResponse::Number(state.led_effect_params.transition)).await?;
+    mqtt_publish_state(client, identity, "gamma", MqttResponse::Gamma(state.output_params.gamma)).await?;
+    mqtt_publish_state(client, identity, "brightness", MqttResponse::Number(state.output_params.brightness)).await?;
+    mqtt_publish_state(client, identity, "dither", MqttResponse::Bool(state.output_params.dither)).await?;
+    mqtt_publish_state(client, identity, "power_budget", MqttResponse::Number(state.output_params.power_budget)).await?;
+    mqtt_publish_power(client, identity).await
+}
 
-    let _ = queue.enqueue(EffectCommand::ConfigureParams(state.led_effect_params));
-    info!("Update: {:?}", state);
+/// Announce the LED strip to Home Assistant, using retained discovery messages.
+async fn mqtt_publish_discovery<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    identity: &Identity,
+) -> Result<(), Error>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    let (topic, payload) = homeassistant::light_config(&identity.topic_prefix, &identity.device_id).ok_or(Error::Serialize)?;
+    client.send_message(&topic, payload.as_bytes(), QoS0, true).await.map_err(Error::MqttPublish)?;
 
-    mqtt_publish_state(client, "led/pallet/color1", MqttResponse::RGB(state.led_effect_params.color1)).await?;
-    mqtt_publish_state(client, "led/pallet/color2", MqttResponse::RGB(state.led_effect_params.color2)).await?;
-    mqtt_publish_state(client, "led/pallet/effect", MqttResponse::Effect(state.effect)).await?;
-    mqtt_publish_state(client, "led/pallet/chroma", MqttResponse::Number(state.led_effect_params.chroma)).await?;
-    mqtt_publish_state(client, "led/pallet/luminance", MqttResponse::Number(state.led_effect_params.luminance)).await?;
-    mqtt_publish_state(client, "led/pallet/size", MqttResponse::Number(state.led_effect_params.size)).await?;
-    mqtt_publish_state(client, "led/pallet/speed", MqttResponse::Number(state.led_effect_params.speed)).await?;
+    for (key, name) in homeassistant::NUMBERS {
+        let (topic, payload) = homeassistant::number_config(&identity.topic_prefix, &identity.device_id, key, name).ok_or(Error::Serialize)?;
+        client.send_message(&topic, payload.as_bytes(), QoS0, true).await.map_err(Error::MqttPublish)?;
+    }
 
     Ok(())
 }
 
+/// Publish the estimated current draw of the LED strip, and whether it is being limited.
+async fn mqtt_publish_power<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    identity: &Identity,
+) -> Result<(), Error>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    let estimate = power::latest();
+    mqtt_publish_state(client, identity, "power/current", MqttResponse::Number(estimate.milliamps)).await?;
+    mqtt_publish_state(client, identity, "power/limited", MqttResponse::Bool(estimate.limited)).await
+}
+
+async fn mqtt_publish_json<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    identity: &Identity,
+    name: &str,
+    payload: &impl Serialize,
+) -> Result<(), Error>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    let topic = identity.topic(name).ok_or(Error::Serialize)?;
+    let payload = serde_json_core::to_string::<_, 512>(payload).map_err(|_| Error::Serialize)?;
+    client.send_message(&topic, payload.as_bytes(), QoS0, false).await.map_err(Error::MqttPublish)
+}
+
 async fn mqtt_publish_state<'a, T, const MAX_PROPERTIES: usize, R>(
     client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
-    topic: &'static str,
+    identity: &Identity,
+    name: &str,
     payload: MqttResponse,
 ) -> Result<(), Error>
 where
     T: Read + Write,
     R: RngCore,
 {
+    let topic = identity.topic(name).ok_or(Error::Serialize)?;
     let payload = payload.serialize().ok_or(Error::Serialize)?;
-    client.send_message(topic, payload.as_bytes(), QoS0, false).await.map_err(Error::MqttPublish)
+    client.send_message(&topic, payload.as_bytes(), QoS0, false).await.map_err(Error::MqttPublish)
 }
\ No newline at end of file
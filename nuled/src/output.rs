@@ -0,0 +1,214 @@
+/// Output stage for the LED strip.
+///
+/// Transforms frames produced by effects into values for the LED driver.
+/// Each pixel is passed through white extraction, brightness limiting,
+/// gamma correction, power limiting and temporal dithering, in that order.
+
+use num_traits::Float;
+use crate::color::{WhiteMode, RGB, RGBW};
+use crate::config::POWER_BUDGET_MA;
+use crate::effect::RgbArray;
+use crate::power::{self, PowerEstimate};
+
+/// Number of channels on an RGBW LED.
+const CHANNELS: usize = 4;
+
+/// Output stage parameters, configurable at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputParams {
+    /// Whether the LED strip is switched on. Effects keep running while it is off.
+    pub on: bool,
+    /// Gamma exponent for the red, green, blue and white channels.
+    /// A value of `1.0` leaves the channel unchanged.
+    pub gamma: [f32; CHANNELS],
+    /// Brightness ceiling, in the range `0.0..1.0`.
+    /// Applied before gamma correction, so that brightness is scaled perceptually.
+    pub brightness: f32,
+    /// Carry rounding errors over to the next frame,
+    /// so that slow fades at low brightness do not move in visible steps.
+    pub dither: bool,
+    /// Maximum estimated current draw of the LED strip, in milliamps. 0 disables the limit.
+    pub power_budget: f32,
+}
+
+impl Default for OutputParams {
+    fn default() -> Self {
+        Self {
+            on: true,
+            gamma: [1.0; CHANNELS],
+            brightness: 1.0,
+            dither: true,
+            power_budget: POWER_BUDGET_MA as f32,
+        }
+    }
+}
+
+/// Whether all gamma exponents are finite and positive.
+/// A gamma of zero would switch every channel fully on, as `0.0.powf(0.0)` is `1.0`.
+pub fn valid_gamma(gamma: &[f32; CHANNELS]) -> bool {
+    gamma.iter().all(|gamma| gamma.is_finite() && *gamma > 0.0)
+}
+
+pub struct OutputStage<const N: usize> {
+    params: OutputParams,
+    white_mode: WhiteMode,
+    /// Accumulated quantization error for each channel of each LED.
+    error: [[f32; CHANNELS]; N],
+    /// Power estimate of the last rendered frame.
+    power: PowerEstimate,
+}
+
+impl<const N: usize> OutputStage<N> {
+    pub fn new(white_mode: WhiteMode) -> Self {
+        Self {
+            params: OutputParams::default(),
+            white_mode,
+            error: [[0.0; CHANNELS]; N],
+            power: PowerEstimate::default(),
+        }
+    }
+
+    pub fn configure(&mut self, params: OutputParams) {
+        self.params = params;
+        if !params.dither {
+            self.error = [[0.0; CHANNELS]; N];
+        }
+    }
+
+    /// Render a frame into values that can be written to the LED driver.
+    /// Only the first `led_count` LEDs are connected, the others are dark
+    /// and do not count towards the power budget.
+    pub fn render(&mut self, strip: RgbArray<N>, led_count: usize) -> [RGBW; N] {
+        let led_count = led_count.min(N);
+        let mut channels: [[f32; CHANNELS]; N] = core::array::from_fn(|i| {
+            if i < led_count { self.correct(strip.0[i]) } else { [0.0; CHANNELS] }
+        });
+        self.power = power::limit(&mut channels[..led_count], &power::SK6812_RGBW, self.params.power_budget);
+        core::array::from_fn(|i| self.quantize(i, channels[i]))
+    }
+
+    /// Power estimate of the last rendered frame.
+    pub fn power(&self) -> PowerEstimate {
+        self.power
+    }
+
+    /// Extract the white channel, and apply brightness and gamma correction.
+    /// Returns channel values in the range `0.0..255.0`.
+    fn correct(&self, rgb: RGB) -> [f32; CHANNELS] {
+        let (rgb, white) = rgb.extract_white(self.white_mode);
+        let brightness = if self.params.on {
+            self.params.brightness.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let mut channels = [rgb.r, rgb.g, rgb.b, white];
+
+        for (value, gamma) in channels.iter_mut().zip(self.params.gamma.iter()) {
+            let normalized = (*value / 255.0 * brightness).clamp(0.0, 1.0);
+            *value = normalized.powf(*gamma) * 255.0;
+        }
+
+        channels
+    }
+
+    fn quantize(&mut self, index: usize, channels: [f32; CHANNELS]) -> RGBW {
+        let mut output = [0_u8; CHANNELS];
+
+        for c in 0..CHANNELS {
+            output[c] = if self.params.dither {
+                let value = channels[c] + self.error[index][c];
+                let quantized = value.round().clamp(0.0, 255.0);
+                self.error[index][c] = value - quantized;
+                quantized as u8
+            } else {
+                channels[c].round() as u8
+            };
+        }
+
+        RGBW {
+            r: output[0],
+            g: output[1],
+            b: output[2],
+            a: smart_leds::White(output[3]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> OutputParams {
+        OutputParams {
+            dither: false,
+            ..OutputParams::default()
+        }
+    }
+
+    /// Render a single gray LED without white extraction.
+    fn render(stage: &mut OutputStage<1>, value: f32) -> [u8; CHANNELS] {
+        let [led] = stage.render(RgbArray([RGB { r: value, g: value, b: value }]), 1);
+        [led.r, led.g, led.b, led.a.0]
+    }
+
+    fn stage(params: OutputParams) -> OutputStage<1> {
+        let mut stage = OutputStage::new(WhiteMode::Off);
+        stage.configure(params);
+        stage
+    }
+
+    #[test]
+    fn gamma_per_channel() {
+        let mut stage = stage(OutputParams { gamma: [2.0, 1.0, 0.5, 1.0], ..params() });
+        // 128/255 squared is 64.25, and its square root is 180.67.
+        assert_eq!(render(&mut stage, 128.0), [64, 128, 181, 0]);
+    }
+
+    #[test]
+    fn brightness_is_a_ceiling() {
+        let mut stage = stage(OutputParams { brightness: 0.5, ..params() });
+        assert_eq!(render(&mut stage, 255.0), [128, 128, 128, 0]);
+        assert_eq!(render(&mut stage, 0.0), [0, 0, 0, 0]);
+
+        stage.configure(OutputParams { brightness: 2.0, ..params() });
+        assert_eq!(render(&mut stage, 255.0), [255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn off_is_dark() {
+        let mut stage = stage(OutputParams { on: false, ..params() });
+        assert_eq!(render(&mut stage, 255.0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average() {
+        let mut stage = stage(OutputParams { dither: true, ..params() });
+        let sum: u32 = (0..10).map(|_| render(&mut stage, 0.5)[0] as u32).sum();
+        assert_eq!(sum, 5);
+
+        stage.configure(params());
+        let sum: u32 = (0..10).map(|_| render(&mut stage, 0.5)[0] as u32).sum();
+        assert_eq!(sum, 10);
+    }
+
+    #[test]
+    fn gamma_must_be_positive() {
+        assert!(valid_gamma(&[2.2, 1.0, 0.5, 1.0]));
+        assert!(!valid_gamma(&[0.0; CHANNELS]));
+        assert!(!valid_gamma(&[2.2, -1.0, 2.2, 2.2]));
+        assert!(!valid_gamma(&[2.2, 2.2, f32::NAN, 2.2]));
+        assert!(!valid_gamma(&[2.2, 2.2, 2.2, f32::INFINITY]));
+    }
+
+    #[test]
+    fn power_counts_connected_leds() {
+        let white = RGB { r: 255.0, g: 255.0, b: 255.0 };
+        let mut stage = OutputStage::<2>::new(WhiteMode::Off);
+        stage.configure(OutputParams { power_budget: 0.0, ..params() });
+
+        let [_, unconnected] = stage.render(RgbArray([white; 2]), 1);
+        assert_eq!([unconnected.r, unconnected.g, unconnected.b, unconnected.a.0], [0, 0, 0, 0]);
+        let one_led = power::estimate(&[[255.0, 255.0, 255.0, 0.0]], &power::SK6812_RGBW);
+        assert_eq!(stage.power().milliamps, one_led);
+    }
+}
@@ -0,0 +1,81 @@
+/// Persist the LED state across restarts.
+///
+/// Changes are debounced, so that a burst of updates, such as dragging a slider
+/// in Home Assistant, results in a single flash write.
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use crate::config::{PRESET_FLASH_OFFSET, STATE_FLASH_OFFSET};
+use crate::settings::{PowerOn, SettingsError, RECORD_SIZE};
+use crate::state::{decode, encode, ServerState};
+
+/// How long the state must stay unchanged before it is written to flash.
+const SAVE_DELAY: Duration = Duration::from_secs(30);
+
+static PENDING: Signal<CriticalSectionRawMutex, ServerState> = Signal::new();
+
+/// Schedule the state to be saved to flash.
+pub fn store(state: &ServerState) {
+    PENDING.signal(*state);
+}
+
+/// The state to start with after a restart.
+pub fn load(power_on: PowerOn) -> ServerState {
+    let mut state = match power_on {
+        PowerOn::Preset => read(PRESET_FLASH_OFFSET),
+        PowerOn::Last | PowerOn::Off => read(STATE_FLASH_OFFSET),
+    };
+
+    if power_on == PowerOn::Off {
+        state.output_params.on = false;
+    }
+
+    state
+}
+
+/// Save `state` as the preset to start with when `power_on` is `preset`.
+pub fn save_preset(state: &ServerState) -> Result<(), SettingsError> {
+    let mut buf = [0_u8; RECORD_SIZE];
+    let len = encode(state, &mut buf);
+    FlashStorage::new().write(PRESET_FLASH_OFFSET, &buf[..len]).map_err(|_| SettingsError::Flash)
+}
+
+/// Read a state record, or the default state if there is none.
+fn read(offset: u32) -> ServerState {
+    let mut buf = [0_u8; RECORD_SIZE];
+    match FlashStorage::new().read(offset, &mut buf) {
+        Err(_) => {
+            warn!("Unable to read state from flash");
+            ServerState::default()
+        }
+        Ok(_) => match decode(&buf) {
+            Ok(state) => state,
+            Err(SettingsError::Missing) => ServerState::default(),
+            Err(err) => {
+                warn!("Discarding state stored in flash: {:?}", err);
+                ServerState::default()
+            }
+        },
+    }
+}
+
+#[embassy_executor::task]
+pub async fn persist_task() {
+    loop {
+        let mut state = PENDING.wait().await;
+        while let Either::First(newer) = select(PENDING.wait(), Timer::after(SAVE_DELAY)).await {
+            state = newer;
+        }
+
+        let mut buf = [0_u8; RECORD_SIZE];
+        let len = encode(&state, &mut buf);
+        match FlashStorage::new().write(STATE_FLASH_OFFSET, &buf[..len]) {
+            Ok(_) => info!("State saved to flash."),
+            Err(err) => error!("Unable to save state to flash: {:?}", err),
+        }
+    }
+}
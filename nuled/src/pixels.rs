@@ -0,0 +1,78 @@
+/// Pixel data received from the network, as consecutive 8-bit channel values.
+
+use crate::color::RGB;
+
+/// Channels of each pixel in received data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Rgb,
+    /// The white channel is added to red, green and blue, and moved back to the white LED by the output stage.
+    Rgbw,
+}
+
+impl Layout {
+    pub const fn channels(&self) -> usize {
+        match self {
+            Layout::Rgb => 3,
+            Layout::Rgbw => 4,
+        }
+    }
+}
+
+/// Copy pixels from `data` into `pixels`, starting at pixel `start`.
+/// Pixels beyond the end of the strip, and incomplete pixels at the end of the data, are ignored.
+/// Returns whether any pixels were changed.
+pub fn copy(pixels: &mut [RGB], start: usize, data: &[u8], layout: Layout) -> bool {
+    let Some(pixels) = pixels.get_mut(start..) else {
+        return false;
+    };
+
+    let mut changed = false;
+    for (pixel, channels) in pixels.iter_mut().zip(data.chunks_exact(layout.channels())) {
+        let white = match layout {
+            Layout::Rgb => 0.0,
+            Layout::Rgbw => channels[3] as f32,
+        };
+        *pixel = RGB {
+            r: (channels[0] as f32 + white).min(255.0),
+            g: (channels[1] as f32 + white).min(255.0),
+            b: (channels[2] as f32 + white).min(255.0),
+        };
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(pixel: RGB) -> (f32, f32, f32) {
+        (pixel.r, pixel.g, pixel.b)
+    }
+
+    #[test]
+    fn copy_rgb() {
+        let mut pixels = [RGB::default(); 3];
+        assert!(copy(&mut pixels, 1, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], Layout::Rgb));
+        assert_eq!(rgb(pixels[0]), (0.0, 0.0, 0.0));
+        assert_eq!(rgb(pixels[1]), (1.0, 2.0, 3.0));
+        assert_eq!(rgb(pixels[2]), (4.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn copy_rgbw_adds_white() {
+        let mut pixels = [RGB::default(); 2];
+        assert!(copy(&mut pixels, 0, &[10, 20, 30, 5, 200, 250, 0, 100], Layout::Rgbw));
+        assert_eq!(rgb(pixels[0]), (15.0, 25.0, 35.0));
+        assert_eq!(rgb(pixels[1]), (255.0, 255.0, 100.0));
+    }
+
+    #[test]
+    fn copy_outside_strip() {
+        let mut pixels = [RGB::default(); 2];
+        assert!(!copy(&mut pixels, 2, &[1, 2, 3], Layout::Rgb));
+        assert!(!copy(&mut pixels, 5, &[1, 2, 3], Layout::Rgb));
+        assert!(!copy(&mut pixels, 0, &[1, 2], Layout::Rgb));
+    }
+}
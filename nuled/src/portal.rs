@@ -0,0 +1,160 @@
+/// Provisioning portal page and form handling.
+///
+/// The page lets the user enter WiFi and MQTT settings while the device runs its own access point.
+/// Stored passwords are never shown, and leaving a password field empty keeps the stored password.
+
+use core::fmt::{self, Write};
+use heapless::String;
+use crate::http;
+use crate::settings::{Settings, SettingsError};
+
+/// Settings shown on the form, as key, label and input type.
+const FIELDS: [(&str, &str, &str); 6] = [
+    ("wifi_ssid", "WiFi network", "text"),
+    ("wifi_password", "WiFi password", "password"),
+    ("mqtt_server", "MQTT server", "text"),
+    ("mqtt_port", "MQTT port", "number"),
+    ("mqtt_username", "MQTT username", "text"),
+    ("mqtt_password", "MQTT password", "password"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum FormError {
+    /// The form could not be decoded.
+    Malformed,
+    Settings(SettingsError),
+}
+
+/// Render the provisioning page, with an optional message above the form.
+pub fn render(w: &mut impl Write, device_id: &str, settings: &Settings, message: Option<&str>) -> fmt::Result {
+    w.write_str("<!DOCTYPE html><html><head><meta charset=\"utf-8\">")?;
+    w.write_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
+    write!(w, "<title>NULED {}</title></head><body><h1>NULED ", Escaped(device_id))?;
+    write!(w, "{}</h1>", Escaped(device_id))?;
+    if let Some(message) = message {
+        write!(w, "<p><strong>{}</strong></p>", Escaped(message))?;
+    }
+    w.write_str("<form method=\"post\" action=\"/save\">")?;
+
+    for (key, label, input_type) in FIELDS {
+        write!(w, "<p><label>{label}<br><input name=\"{key}\" type=\"{input_type}\"")?;
+        match key {
+            "wifi_password" | "mqtt_password" => w.write_str(" placeholder=\"unchanged\"")?,
+            "mqtt_port" => write!(w, " value=\"{}\"", settings.mqtt_port)?,
+            "wifi_ssid" => write!(w, " value=\"{}\"", Escaped(&settings.wifi_ssid))?,
+            "mqtt_server" => write!(w, " value=\"{}\"", Escaped(&settings.mqtt_server))?,
+            "mqtt_username" => write!(w, " value=\"{}\"", Escaped(&settings.mqtt_username))?,
+            _ => {}
+        }
+        w.write_str("></label></p>")?;
+    }
+
+    w.write_str("<p><button type=\"submit\">Save and restart</button></p></form></body></html>")
+}
+
+/// Apply a submitted form to `settings`. Settings are only changed if the whole form is valid.
+pub fn apply(settings: &mut Settings, body: &[u8]) -> Result<(), FormError> {
+    let body = core::str::from_utf8(body).map_err(|_| FormError::Malformed)?;
+    let mut updated = settings.clone();
+
+    for (key, value) in http::form_fields(body) {
+        let key: String<32> = http::url_decode(key).ok_or(FormError::Malformed)?;
+        let value: String<64> = http::url_decode(value).ok_or(FormError::Settings(SettingsError::InvalidValue))?;
+        if !FIELDS.iter().any(|(field, _, _)| *field == key) {
+            return Err(FormError::Settings(SettingsError::UnknownKey));
+        }
+        if key.ends_with("_password") && value.is_empty() {
+            continue;
+        }
+        updated.set(&key, &value).map_err(FormError::Settings)?;
+    }
+
+    *settings = updated;
+    Ok(())
+}
+
+/// Escape text for use in HTML content and attribute values.
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::PowerOn;
+
+    fn settings() -> Settings {
+        Settings {
+            wifi_ssid: "Home".try_into().unwrap(),
+            wifi_password: "hunter2".try_into().unwrap(),
+            mqtt_server: "mqtt.lan".try_into().unwrap(),
+            mqtt_port: 1883,
+            mqtt_username: "nuled".try_into().unwrap(),
+            mqtt_password: "secret".try_into().unwrap(),
+            led_count: 30,
+            power_on: PowerOn::Last,
+        }
+    }
+
+    #[test]
+    fn apply_form() {
+        let mut settings = settings();
+        let body = b"wifi_ssid=Caf%C3%A9+%26+Bar&wifi_password=p%40ss&mqtt_server=10.0.0.2&mqtt_port=8883&mqtt_username=&mqtt_password=";
+        apply(&mut settings, body).unwrap();
+
+        assert_eq!(settings.wifi_ssid.as_str(), "Café & Bar");
+        assert_eq!(settings.wifi_password.as_str(), "p@ss");
+        assert_eq!(settings.mqtt_server.as_str(), "10.0.0.2");
+        assert_eq!(settings.mqtt_port, 8883);
+        assert_eq!(settings.mqtt_username.as_str(), "");
+        assert_eq!(settings.mqtt_password.as_str(), "secret");
+    }
+
+    #[test]
+    fn invalid_form_changes_nothing() {
+        let mut settings = settings();
+        let before = settings.clone();
+
+        assert_eq!(
+            apply(&mut settings, b"wifi_ssid=Other&mqtt_port=http"),
+            Err(FormError::Settings(SettingsError::InvalidValue)),
+        );
+        assert_eq!(
+            apply(&mut settings, b"wifi_ssid=Other&led_count=5"),
+            Err(FormError::Settings(SettingsError::UnknownKey)),
+        );
+        assert_eq!(apply(&mut settings, b"wifi_ssid=%zz"), Err(FormError::Settings(SettingsError::InvalidValue)));
+        assert_eq!(apply(&mut settings, b"\xff"), Err(FormError::Malformed));
+        assert!(settings == before);
+    }
+
+    #[test]
+    fn render_escapes_values_and_hides_passwords() {
+        let mut settings = settings();
+        settings.wifi_ssid = "\"><script>".try_into().unwrap();
+        let mut page = std::string::String::new();
+        render(&mut page, "a1b2c3", &settings, Some("Saved & restarting")).unwrap();
+
+        assert!(page.contains("<h1>NULED a1b2c3</h1>"));
+        assert!(page.contains("<strong>Saved &amp; restarting</strong>"));
+        assert!(page.contains("value=\"&quot;&gt;&lt;script&gt;\""));
+        assert!(page.contains("value=\"1883\""));
+        assert!(!page.contains("<script>"));
+        assert!(!page.contains("hunter2"));
+        assert!(!page.contains("secret"));
+    }
+}
@@ -0,0 +1,137 @@
+/// Power estimation and limiting.
+///
+/// The NULED board is powered through USB-PD and can draw at most 5A.
+/// Long strips at full white can exceed this, so the current draw of each
+/// frame is estimated, and frames are scaled down to stay within a budget.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Current drawn by a single LED.
+pub struct CurrentModel {
+    /// Current at full duty cycle for the red, green, blue and white channels, in milliamps.
+    pub channel_ma: [f32; 4],
+    /// Current drawn by the LED controller even when all channels are off, in milliamps.
+    pub idle_ma: f32,
+}
+
+/// Typical values for SK6812 RGBW LEDs.
+pub const SK6812_RGBW: CurrentModel = CurrentModel {
+    channel_ma: [12.0, 12.0, 12.0, 20.0],
+    idle_ma: 1.0,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PowerEstimate {
+    /// Estimated current draw of the LED strip, in milliamps.
+    pub milliamps: f32,
+    /// Whether the frame was scaled down to stay within the budget.
+    pub limited: bool,
+}
+
+/// Estimate the current drawn by a frame, with channel values in the range `0.0..255.0`.
+pub fn estimate(channels: &[[f32; 4]], model: &CurrentModel) -> f32 {
+    let active: f32 = channels
+        .iter()
+        .flat_map(|led| led.iter().zip(model.channel_ma.iter()))
+        .map(|(value, ma)| value / 255.0 * ma)
+        .sum();
+    active + model.idle_ma * channels.len() as f32
+}
+
+/// Scale down all channels of a frame so that the estimated current stays within `budget_ma`.
+/// A budget of 0 disables the limit.
+pub fn limit(channels: &mut [[f32; 4]], model: &CurrentModel, budget_ma: f32) -> PowerEstimate {
+    let milliamps = estimate(channels, model);
+    // A NaN budget disables the limit too, so that it does not end up in the frame.
+    if budget_ma.is_nan() || budget_ma <= 0.0 || milliamps <= budget_ma {
+        return PowerEstimate { milliamps, limited: false };
+    }
+
+    // Idle current cannot be reduced, so only the active part of the frame is scaled.
+    let idle = model.idle_ma * channels.len() as f32;
+    let scale = ((budget_ma - idle) / (milliamps - idle)).clamp(0.0, 1.0);
+    for value in channels.iter_mut().flatten() {
+        *value *= scale;
+    }
+
+    PowerEstimate {
+        milliamps: idle + (milliamps - idle) * scale,
+        limited: true,
+    }
+}
+
+static ESTIMATED_MILLIAMPS: AtomicU32 = AtomicU32::new(0);
+static LIMITED: AtomicBool = AtomicBool::new(false);
+
+/// Make the estimate of the latest frame available to other tasks.
+pub fn store(estimate: PowerEstimate) {
+    ESTIMATED_MILLIAMPS.store(estimate.milliamps as u32, Ordering::Relaxed);
+    LIMITED.store(estimate.limited, Ordering::Relaxed);
+}
+
+/// Retrieve the estimate of the latest frame shown on the LED strip.
+pub fn latest() -> PowerEstimate {
+    PowerEstimate {
+        milliamps: ESTIMATED_MILLIAMPS.load(Ordering::Relaxed) as f32,
+        limited: LIMITED.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tolerance for currents, in milliamps.
+    const TOLERANCE: f32 = 0.001;
+
+    const WHITE: [f32; 4] = [255.0; 4];
+
+    #[test]
+    fn full_white_strip() {
+        // Three RGB channels at 12 mA, the white channel at 20 mA, and 1 mA idle current.
+        assert!((estimate(&[WHITE; 10], &SK6812_RGBW) - 570.0).abs() < TOLERANCE);
+        assert!((estimate(&[[0.0; 4]; 10], &SK6812_RGBW) - 10.0).abs() < TOLERANCE);
+        assert!((estimate(&[[127.5, 0.0, 0.0, 0.0]], &SK6812_RGBW) - 7.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn within_budget_is_unchanged() {
+        let mut channels = [WHITE; 10];
+        let power = limit(&mut channels, &SK6812_RGBW, 570.0);
+        assert!(!power.limited);
+        assert!((power.milliamps - 570.0).abs() < TOLERANCE);
+        assert_eq!(channels, [WHITE; 10]);
+    }
+
+    #[test]
+    fn zero_budget_is_unlimited() {
+        let mut channels = [WHITE; 10];
+        let power = limit(&mut channels, &SK6812_RGBW, 0.0);
+        assert!(!power.limited);
+        assert_eq!(channels, [WHITE; 10]);
+
+        let power = limit(&mut channels, &SK6812_RGBW, f32::NAN);
+        assert!(!power.limited);
+        assert_eq!(channels, [WHITE; 10]);
+    }
+
+    #[test]
+    fn over_budget_is_scaled() {
+        let mut channels = [WHITE, [255.0, 0.0, 0.0, 0.0]];
+        // 56 + 12 mA active, 2 mA idle. Halving the active current leaves 36 mA.
+        let power = limit(&mut channels, &SK6812_RGBW, 36.0);
+        assert!(power.limited);
+        assert!((power.milliamps - 36.0).abs() < TOLERANCE);
+        assert_eq!(channels, [[127.5; 4], [127.5, 0.0, 0.0, 0.0]]);
+        assert!((estimate(&channels, &SK6812_RGBW) - 36.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn budget_below_idle_is_dark() {
+        let mut channels = [WHITE; 10];
+        let power = limit(&mut channels, &SK6812_RGBW, 5.0);
+        assert!(power.limited);
+        assert!((power.milliamps - 10.0).abs() < TOLERANCE);
+        assert_eq!(channels, [[0.0; 4]; 10]);
+    }
+}
@@ -0,0 +1,186 @@
+/// WiFi provisioning through a captive portal.
+///
+/// When the device cannot connect to WiFi, it starts an open access point named `NULED-<device id>`.
+/// Clients connecting to it get an address over DHCP, all host names resolve to the device,
+/// and any web page shows a form for WiFi and MQTT settings.
+/// Submitting the form saves the settings to flash, and restarts the device in station mode.
+
+use core::ptr::addr_of_mut;
+use embassy_futures::join::join3;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{Ipv4Address, Ipv4Cidr, Stack, StaticConfigV4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use esp_storage::FlashStorage;
+use esp_wifi::wifi::{WifiApDevice, WifiDevice};
+use heapless::String;
+use crate::config::SETTINGS_FLASH_OFFSET;
+use crate::dhcp::DhcpServer;
+use crate::http;
+use crate::identity::Identity;
+use crate::settings::Settings;
+use crate::{dns, portal};
+
+/// Address of the device on the provisioning access point.
+pub const ADDRESS: [u8; 4] = [192, 168, 4, 1];
+
+/// Signalled by the WiFi task when the access point has been started.
+pub static START: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+type ApStack = Stack<WifiDevice<'static, WifiApDevice>>;
+
+static mut RX_BUFFER: [u8; 1536] = [0; 1536];
+static mut TX_BUFFER: [u8; 1536] = [0; 1536];
+static mut REQUEST: [u8; 1024] = [0; 1024];
+static mut PAGE: String<4096> = String::new();
+
+/// Network configuration of the access point.
+pub fn network_config() -> embassy_net::Config {
+    let [a, b, c, d] = ADDRESS;
+    embassy_net::Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(Ipv4Address::new(a, b, c, d), 24),
+        gateway: None,
+        dns_servers: Default::default(),
+    })
+}
+
+#[embassy_executor::task]
+pub async fn portal_task(stack: &'static ApStack, identity: &'static Identity, settings: &'static Settings) {
+    START.wait().await;
+
+    info!("Starting provisioning portal on http://{}.{}.{}.{}/", ADDRESS[0], ADDRESS[1], ADDRESS[2], ADDRESS[3]);
+
+    join3(
+        http_server(stack, identity, settings),
+        dns_server(stack),
+        dhcp_server(stack),
+    ).await;
+}
+
+async fn http_server(stack: &'static ApStack, identity: &Identity, settings: &Settings) {
+    let request_buf = unsafe { &mut *addr_of_mut!(REQUEST) };
+    let page = unsafe { &mut *addr_of_mut!(PAGE) };
+
+    loop {
+        let mut socket = TcpSocket::new(
+            stack,
+            unsafe { &mut *addr_of_mut!(RX_BUFFER) },
+            unsafe { &mut *addr_of_mut!(TX_BUFFER) },
+        );
+        socket.set_timeout(Some(Duration::from_secs(10)));
+
+        if let Err(err) = socket.accept(80).await {
+            warn!("Portal accept failed: {:?}", err);
+            continue;
+        }
+
+        let mut saved = false;
+        let saved_flag = &mut saved;
+        let page = &mut *page;
+        let result = http::serve(&mut socket, request_buf, "text/html; charset=utf-8", move |request| {
+            // Every page shows the form, so that captive portal detection opens it.
+            let mut updated = settings.clone();
+            let status = match (request.method, request.path) {
+                ("POST", "/save") => match portal::apply(&mut updated, request.body) {
+                    Ok(()) => match updated.save(&mut FlashStorage::new(), SETTINGS_FLASH_OFFSET) {
+                        Ok(()) => {
+                            *saved_flag = true;
+                            200
+                        }
+                        Err(err) => {
+                            error!("Unable to save settings: {:?}", err);
+                            500
+                        }
+                    },
+                    Err(err) => {
+                        warn!("Invalid settings submitted: {:?}", err);
+                        400
+                    }
+                },
+                _ => 200,
+            };
+
+            let message = match status {
+                200 if *saved_flag => Some("Settings saved, restarting."),
+                200 => None,
+                400 => Some("Invalid settings, please check the values and try again."),
+                _ => Some("Unable to save settings."),
+            };
+
+            let page = page;
+            page.clear();
+            let _ = portal::render(page, &identity.device_id, &updated, message);
+            (status, page.as_bytes())
+        }).await;
+
+        match result {
+            Ok(()) => socket.close(),
+            Err(err) => {
+                warn!("Portal request failed: {:?}", err);
+                socket.abort();
+            }
+        }
+
+        if saved {
+            info!("Settings saved from provisioning portal, restarting.");
+            Timer::after_secs(1).await;
+            esp_hal::reset::software_reset();
+        }
+    }
+}
+
+async fn dns_server(stack: &'static ApStack) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 512];
+    let mut tx_buffer = [0; 512];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+
+    if let Err(err) = socket.bind(53) {
+        error!("Unable to bind portal DNS server: {:?}", err);
+        return;
+    }
+
+    let mut query = [0; 512];
+    let mut response = [0; 512];
+    loop {
+        let Ok((len, remote)) = socket.recv_from(&mut query).await else {
+            continue;
+        };
+        if let Some(len) = dns::answer(&query[..len], ADDRESS, &mut response) {
+            let _ = socket.send_to(&response[..len], remote).await;
+        }
+    }
+}
+
+async fn dhcp_server(stack: &'static ApStack) {
+    const SERVER: DhcpServer = DhcpServer {
+        address: ADDRESS,
+        lease_time: 3600,
+    };
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+
+    if let Err(err) = socket.bind(67) {
+        error!("Unable to bind portal DHCP server: {:?}", err);
+        return;
+    }
+
+    let mut request = [0; 576];
+    let mut reply = [0; 576];
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut request).await else {
+            continue;
+        };
+        if let Some(len) = SERVER.reply(&request[..len], &mut reply) {
+            // Clients do not have an address yet, so replies are broadcast.
+            let _ = socket.send_to(&reply[..len], (Ipv4Address::BROADCAST, 68)).await;
+        }
+    }
+}
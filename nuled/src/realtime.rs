@@ -0,0 +1,288 @@
+/// Realtime pixel input over E1.31 (sACN), Art-Net, DDP, the WLED UDP protocols and LEDServer.
+///
+/// While packets keep arriving, the LED task shows the received pixels instead of the effect.
+/// The effect takes over again when no packets have been received for `REALTIME_TIMEOUT_MS`,
+/// or for the timeout given in WLED packets.
+
+use core::cell::RefCell;
+use core::ptr::addr_of_mut;
+use embassy_futures::join::join5;
+use embassy_net::tcp::{self, TcpSocket};
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpAddress, Ipv4Address, Stack};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant};
+use embedded_io_async::{Read, Write};
+use esp_wifi::wifi::{WifiDevice, WifiStaDevice};
+use crate::color::RGB;
+use crate::config::{LED_COUNT, REALTIME_CHANNEL, REALTIME_TIMEOUT_MS, REALTIME_UNIVERSE};
+use crate::dmx::{self, Mapping};
+use crate::effect::RgbArray;
+use crate::zmtp::{self, ZmtpError};
+use crate::{ddp, ledserver, wled};
+
+const MAPPING: Mapping = Mapping {
+    start_universe: REALTIME_UNIVERSE,
+    start_channel: REALTIME_CHANNEL,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(REALTIME_TIMEOUT_MS as u64);
+
+type StaStack = Stack<WifiDevice<'static, WifiStaDevice>>;
+
+struct Frame {
+    pixels: RgbArray<LED_COUNT>,
+    /// Whether the pixels have changed since the LED task last took them.
+    changed: bool,
+    last_packet: Option<Instant>,
+    timeout: Duration,
+}
+
+static FRAME: Mutex<CriticalSectionRawMutex, RefCell<Frame>> = Mutex::new(RefCell::new(Frame {
+    pixels: RgbArray([RGB { r: 0.0, g: 0.0, b: 0.0 }; LED_COUNT]),
+    changed: false,
+    last_packet: None,
+    timeout: TIMEOUT,
+}));
+
+/// Socket buffers for one protocol. Packets are at most one Ethernet frame.
+struct Buffers {
+    rx_meta: [PacketMetadata; 4],
+    rx: [u8; 3072],
+    tx_meta: [PacketMetadata; 1],
+    tx: [u8; 64],
+    packet: [u8; 1500],
+}
+
+impl Buffers {
+    const NEW: Self = Self {
+        rx_meta: [PacketMetadata::EMPTY; 4],
+        rx: [0; 3072],
+        tx_meta: [PacketMetadata::EMPTY; 1],
+        tx: [0; 64],
+        packet: [0; 1500],
+    };
+}
+
+static mut BUFFERS: [Buffers; 4] = [Buffers::NEW, Buffers::NEW, Buffers::NEW, Buffers::NEW];
+
+static mut ZMQ_RX_BUFFER: [u8; 1536] = [0; 1536];
+static mut ZMQ_TX_BUFFER: [u8; 256] = [0; 256];
+/// Received data that has not been parsed into frames yet. LEDServer messages are much smaller.
+static mut ZMQ_FRAMES: [u8; 512] = [0; 512];
+
+/// Effect of a packet that changed the received pixels.
+struct Update {
+    /// Whether the frame is complete, and can be shown.
+    show: bool,
+    timeout: Duration,
+}
+
+/// Parse a packet, and apply it to the received pixels.
+type Protocol = fn(&[u8], &mut [RGB]) -> Option<Update>;
+
+#[derive(Debug)]
+enum ZmqError {
+    Tcp(tcp::Error),
+    Zmtp(ZmtpError),
+    Closed,
+}
+
+impl From<tcp::Error> for ZmqError {
+    fn from(err: tcp::Error) -> Self {
+        ZmqError::Tcp(err)
+    }
+}
+
+impl From<ZmtpError> for ZmqError {
+    fn from(err: ZmtpError) -> Self {
+        ZmqError::Zmtp(err)
+    }
+}
+
+/// Whether realtime pixel data has been received recently.
+pub fn is_active() -> bool {
+    FRAME.lock(|frame| {
+        let frame = frame.borrow();
+        match frame.last_packet {
+            Some(last_packet) => last_packet.elapsed() < frame.timeout,
+            None => false,
+        }
+    })
+}
+
+/// The received pixels, if they have changed since the last call.
+pub fn take_frame() -> Option<RgbArray<LED_COUNT>> {
+    FRAME.lock(|frame| {
+        let mut frame = frame.borrow_mut();
+        if !frame.changed {
+            return None;
+        }
+        frame.changed = false;
+        Some(frame.pixels)
+    })
+}
+
+fn store(packet: &[u8], protocol: Protocol) {
+    FRAME.lock(|frame| {
+        let mut frame = frame.borrow_mut();
+        if let Some(update) = protocol(packet, &mut frame.pixels.0) {
+            frame.changed |= update.show;
+            frame.last_packet = Some(Instant::now());
+            frame.timeout = update.timeout;
+        }
+    });
+}
+
+fn e131(packet: &[u8], pixels: &mut [RGB]) -> Option<Update> {
+    let universe = dmx::parse_e131(packet)?;
+    MAPPING.apply(&universe, pixels).then_some(Update { show: true, timeout: TIMEOUT })
+}
+
+fn artnet(packet: &[u8], pixels: &mut [RGB]) -> Option<Update> {
+    let universe = dmx::parse_artnet(packet)?;
+    MAPPING.apply(&universe, pixels).then_some(Update { show: true, timeout: TIMEOUT })
+}
+
+fn ddp(packet: &[u8], pixels: &mut [RGB]) -> Option<Update> {
+    let packet = ddp::parse(packet)?;
+    packet.apply(pixels);
+    Some(Update { show: packet.push, timeout: TIMEOUT })
+}
+
+fn wled(packet: &[u8], pixels: &mut [RGB]) -> Option<Update> {
+    let packet = wled::parse(packet)?;
+    packet.apply(pixels);
+    let timeout = match packet.timeout_secs() {
+        Some(secs) => Duration::from_secs(secs as u64),
+        None => Duration::MAX,
+    };
+    Some(Update { show: true, timeout })
+}
+
+fn ledserver(packet: &[u8], pixels: &mut [RGB]) -> Option<Update> {
+    let led = ledserver::decode(packet).ok()?;
+    led.apply(pixels);
+    Some(Update { show: led.render, timeout: TIMEOUT })
+}
+
+#[embassy_executor::task]
+pub async fn realtime_task(stack: &'static StaStack) {
+    join_e131_groups(stack).await;
+
+    let buffers = unsafe { &mut *addr_of_mut!(BUFFERS) };
+    let [e131_buffers, artnet_buffers, ddp_buffers, wled_buffers] = buffers;
+
+    join5(
+        receive(stack, dmx::E131_PORT, e131_buffers, e131),
+        receive(stack, dmx::ARTNET_PORT, artnet_buffers, artnet),
+        receive(stack, ddp::PORT, ddp_buffers, ddp),
+        receive(stack, wled::PORT, wled_buffers, wled),
+        receive_zmq(stack),
+    ).await;
+}
+
+async fn receive(stack: &'static StaStack, port: u16, buffers: &mut Buffers, protocol: Protocol) {
+    let mut socket = UdpSocket::new(stack, &mut buffers.rx_meta, &mut buffers.rx, &mut buffers.tx_meta, &mut buffers.tx);
+
+    if let Err(err) = socket.bind(port) {
+        error!("Unable to bind realtime receiver to port {}: {:?}", port, err);
+        return;
+    }
+
+    info!("Receiving realtime pixel data on port {}.", port);
+
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut buffers.packet).await else {
+            continue;
+        };
+        store(&buffers.packet[..len], protocol);
+    }
+}
+
+/// Accept LEDServer messages from ZeroMQ publishers such as `blinken`, one connection at a time.
+async fn receive_zmq(stack: &'static StaStack) {
+    let frames = unsafe { &mut *addr_of_mut!(ZMQ_FRAMES) };
+
+    info!("Receiving LEDServer messages on port {}.", ledserver::PORT);
+
+    loop {
+        let mut socket = TcpSocket::new(
+            stack,
+            unsafe { &mut *addr_of_mut!(ZMQ_RX_BUFFER) },
+            unsafe { &mut *addr_of_mut!(ZMQ_TX_BUFFER) },
+        );
+        socket.set_timeout(Some(Duration::from_secs(60)));
+
+        if let Err(err) = socket.accept(ledserver::PORT).await {
+            warn!("LEDServer accept failed: {:?}", err);
+            continue;
+        }
+
+        info!("ZeroMQ publisher connected from {:?}", socket.remote_endpoint());
+
+        if let Err(err) = zmq_session(&mut socket, frames).await {
+            warn!("ZeroMQ publisher disconnected: {:?}", err);
+        }
+        socket.abort();
+    }
+}
+
+/// Subscribe to all messages from a ZeroMQ publisher, and apply them until the connection is closed.
+async fn zmq_session(socket: &mut TcpSocket<'_>, buf: &mut [u8]) -> Result<(), ZmqError> {
+    let mut greeting = [0; zmtp::GREETING_SIZE];
+    socket.write_all(&zmtp::greeting()).await?;
+    socket.read_exact(&mut greeting).await.map_err(|_| ZmqError::Closed)?;
+    zmtp::check_greeting(&greeting)?;
+
+    socket.write_all(zmtp::READY).await?;
+    socket.write_all(zmtp::SUBSCRIBE_ALL).await?;
+    socket.flush().await?;
+
+    let mut ready = false;
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            return Err(ZmqError::Zmtp(ZmtpError::Malformed));
+        }
+        match socket.read(&mut buf[len..]).await? {
+            0 => return Err(ZmqError::Closed),
+            n => len += n,
+        }
+
+        let mut pos = 0;
+        loop {
+            let (frame, size) = match zmtp::parse_frame(&buf[pos..len]) {
+                Ok(frame) => frame,
+                Err(ZmtpError::Incomplete) => break,
+                Err(err) => return Err(err.into()),
+            };
+            if !ready {
+                ready = frame.is_ready();
+                if !ready {
+                    return Err(ZmqError::Zmtp(ZmtpError::NotReady));
+                }
+            } else if !frame.command {
+                store(frame.body, ledserver);
+            }
+            pos += size;
+        }
+
+        buf.copy_within(pos..len, 0);
+        len -= pos;
+    }
+}
+
+/// E1.31 sources usually send each universe to its own multicast group, `239.255.<universe>`.
+async fn join_e131_groups(stack: &'static StaStack) {
+    stack.wait_config_up().await;
+
+    for universe in (0..MAPPING.universes(LED_COUNT)).map(|i| REALTIME_UNIVERSE + i) {
+        let [hi, lo] = universe.to_be_bytes();
+        let group = IpAddress::Ipv4(Ipv4Address::new(239, 255, hi, lo));
+        if let Err(err) = stack.join_multicast_group(group).await {
+            warn!("Unable to join multicast group for universe {}: {:?}", universe, err);
+        }
+    }
+}
@@ -27,7 +27,7 @@ use heapless::Vec;
 use rand_core::RngCore;
 
 use super::client_config::ClientConfig;
-use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService::{self, QoS1};
+use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService::{self, QoS1, QoS2};
 use crate::rust_mqtt::packet::v5::reason_codes::ReasonCode;
 
 use super::raw_client::{Event, RawMqttClient};
@@ -37,6 +37,82 @@ where
     T: Read + Write,
 {
     raw: RawMqttClient<'a, T, MAX_PROPERTIES, R>,
+    queue: MessageQueue<'a>,
+}
+
+/// Application messages received while waiting for an acknowledgement. They are returned
+/// by `receive_message` before any message from the network, in the order they arrived.
+///
+/// Each entry is the topic length and the payload length as big endian u16,
+/// followed by the topic and the payload.
+struct MessageQueue<'a> {
+    buffer: &'a mut [u8],
+    /// Bytes in use by entries.
+    len: usize,
+    /// Size of the front entry, if it was returned by `receive_message`. It is removed
+    /// when the queue is used again, as the caller can no longer borrow it.
+    returned: usize,
+}
+
+impl<'a> MessageQueue<'a> {
+    const HEADER_LEN: usize = 4;
+
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            returned: 0,
+        }
+    }
+
+    /// Longest topic and payload that fit together in a new entry.
+    fn space(&self) -> usize {
+        let used = self.len - self.returned + Self::HEADER_LEN;
+        self.buffer
+            .len()
+            .saturating_sub(used)
+            .min(u16::MAX as usize)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == self.returned
+    }
+
+    fn remove_returned(&mut self) {
+        self.buffer.copy_within(self.returned..self.len, 0);
+        self.len -= self.returned;
+        self.returned = 0;
+    }
+
+    fn push(&mut self, topic: &str, payload: &[u8]) -> Result<(), ReasonCode> {
+        self.remove_returned();
+        let entry_len = Self::HEADER_LEN + topic.len() + payload.len();
+        if self.len + entry_len > self.buffer.len() || payload.len() > u16::MAX as usize {
+            error!("Message queue is full, dropping message on {}", topic);
+            return Err(ReasonCode::BuffError);
+        }
+        let entry = &mut self.buffer[self.len..self.len + entry_len];
+        entry[0..2].copy_from_slice(&(topic.len() as u16).to_be_bytes());
+        entry[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        entry[4..4 + topic.len()].copy_from_slice(topic.as_bytes());
+        entry[4 + topic.len()..].copy_from_slice(payload);
+        self.len += entry_len;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<(&str, &[u8])> {
+        self.remove_returned();
+        if self.len == 0 {
+            return None;
+        }
+        let topic_len = u16::from_be_bytes([self.buffer[0], self.buffer[1]]) as usize;
+        let payload_len = u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as usize;
+        let topic_end = Self::HEADER_LEN + topic_len;
+        self.returned = topic_end + payload_len;
+        // Topics are only queued from `&str`.
+        let topic = core::str::from_utf8(&self.buffer[Self::HEADER_LEN..topic_end]).ok()?;
+        Some((topic, &self.buffer[topic_end..self.returned]))
+    }
 }
 
 impl<'a, T, const MAX_PROPERTIES: usize, R> MqttClient<'a, T, MAX_PROPERTIES, R>
@@ -44,6 +120,8 @@ where
     T: Read + Write,
     R: RngCore,
 {
+    /// Client without a message queue, see `new_with_queue`. A message that arrives while
+    /// a method waits for an acknowledgement makes the method return `QuotaExceeded`.
     pub fn new(
         network_driver: T,
         buffer: &'a mut [u8],
@@ -51,6 +129,33 @@ where
         recv_buffer: &'a mut [u8],
         recv_buffer_len: usize,
         config: ClientConfig<'a, MAX_PROPERTIES, R>,
+    ) -> Self {
+        Self::new_with_queue(
+            network_driver,
+            buffer,
+            buffer_len,
+            recv_buffer,
+            recv_buffer_len,
+            &mut [],
+            config,
+        )
+    }
+
+    /// Messages that arrive while a method waits for an acknowledgement are kept in
+    /// `queue_buffer` until they are returned by `receive_message`, so it should hold at
+    /// least `recv_buffer_len` bytes. A message that does not fit is left unacknowledged,
+    /// and the method returns `QuotaExceeded`. The message is kept, so the caller should
+    /// drain the queue with `receive_message` while `has_queued_message` is true, and retry.
+    /// If the caller reconnects instead, a QoS 0 message is dropped, while QoS 1 and 2
+    /// messages may be sent again by the broker when the session is resumed.
+    pub fn new_with_queue(
+        network_driver: T,
+        buffer: &'a mut [u8],
+        buffer_len: usize,
+        recv_buffer: &'a mut [u8],
+        recv_buffer_len: usize,
+        queue_buffer: &'a mut [u8],
+        config: ClientConfig<'a, MAX_PROPERTIES, R>,
     ) -> Self {
         Self {
             raw: RawMqttClient::new(
@@ -61,9 +166,62 @@ where
                 recv_buffer_len,
                 config,
             ),
+            queue: MessageQueue::new(queue_buffer),
         }
     }
 
+    /// Wait for the next event that is not an application message. Messages are queued
+    /// for `receive_message`, so they are not lost while waiting for an acknowledgement.
+    /// Messages are only acknowledged once there is space for them in the queue.
+    async fn poll_ack<const TOPICS: usize>(&mut self) -> Result<Event<'static>, ReasonCode> {
+        loop {
+            let space = self.queue.space();
+            let event = match self.raw.poll_limited::<TOPICS>(space).await? {
+                Event::Message(topic, payload) => {
+                    self.queue.push(topic, payload)?;
+                    continue;
+                }
+                Event::Connack => Event::Connack,
+                Event::Puback(identifier) => Event::Puback(identifier),
+                Event::Pubrec(identifier) => Event::Pubrec(identifier),
+                Event::Pubcomp(identifier) => Event::Pubcomp(identifier),
+                Event::Suback(identifier) => Event::Suback(identifier),
+                Event::Unsuback(identifier) => Event::Unsuback(identifier),
+                Event::Pingresp => Event::Pingresp,
+                Event::Disconnect(reason) => Event::Disconnect(reason),
+            };
+            return Ok(event);
+        }
+    }
+
+    /// Wait for the acknowledgement of the request with `identifier`. Acknowledgements
+    /// of other identifiers answer requests that were abandoned, for example after
+    /// `QuotaExceeded`, and are skipped.
+    async fn poll_ack_of<const TOPICS: usize>(
+        &mut self,
+        identifier: u16,
+    ) -> Result<Event<'static>, ReasonCode> {
+        loop {
+            match self.poll_ack::<TOPICS>().await? {
+                Event::Puback(other)
+                | Event::Pubrec(other)
+                | Event::Pubcomp(other)
+                | Event::Suback(other)
+                | Event::Unsuback(other)
+                    if other != identifier =>
+                {
+                    continue
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    /// Whether `receive_message` returns a queued message without reading from the network.
+    pub fn has_queued_message(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
     /// Method allows client connect to server. Client is connecting to the specified broker
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the connection to the broker fails, method returns Err variable that contains
@@ -71,10 +229,9 @@ where
     pub async fn connect_to_broker<'b>(&'b mut self) -> Result<(), ReasonCode> {
         self.raw.connect_to_broker().await?;
 
-        match self.raw.poll::<0>().await? {
+        match self.poll_ack::<0>().await? {
             Event::Connack => Ok(()),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
@@ -104,22 +261,25 @@ where
             .send_message(topic_name, message, qos, retain)
             .await?;
 
-        // QoS1
-        if qos == QoS1 {
-            match self.raw.poll::<0>().await? {
-                Event::Puback(ack_identifier) => {
-                    if identifier == ack_identifier {
-                        Ok(())
-                    } else {
-                        Err(ReasonCode::PacketIdentifierNotFound)
-                    }
-                }
+        match qos {
+            QoS1 => match self.poll_ack_of::<0>(identifier).await? {
+                Event::Puback(_) => Ok(()),
                 Event::Disconnect(reason) => Err(reason),
-                // If an application message comes at this moment, it is lost.
                 _ => Err(ReasonCode::ImplementationSpecificError),
+            },
+            QoS2 => {
+                match self.poll_ack_of::<0>(identifier).await? {
+                    Event::Pubrec(_) => {}
+                    Event::Disconnect(reason) => return Err(reason),
+                    _ => return Err(ReasonCode::ImplementationSpecificError),
+                }
+                match self.poll_ack_of::<0>(identifier).await? {
+                    Event::Pubcomp(_) => Ok(()),
+                    Event::Disconnect(reason) => Err(reason),
+                    _ => Err(ReasonCode::ImplementationSpecificError),
+                }
             }
-        } else {
-            Ok(())
+            _ => Ok(()),
         }
     }
 
@@ -133,16 +293,9 @@ where
     ) -> Result<(), ReasonCode> {
         let identifier = self.raw.subscribe_to_topics(topic_names).await?;
 
-        match self.raw.poll::<TOPICS>().await? {
-            Event::Suback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
-            }
+        match self.poll_ack_of::<TOPICS>(identifier).await? {
+            Event::Suback(_) => Ok(()),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
@@ -156,16 +309,9 @@ where
     ) -> Result<(), ReasonCode> {
         let identifier = self.raw.unsubscribe_from_topic(topic_name).await?;
 
-        match self.raw.poll::<0>().await? {
-            Event::Unsuback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
-            }
+        match self.poll_ack_of::<0>(identifier).await? {
+            Event::Unsuback(_) => Ok(()),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
@@ -182,43 +328,53 @@ where
 
         let identifier = self.raw.subscribe_to_topics(&topic_names).await?;
 
-        match self.raw.poll::<1>().await? {
-            Event::Suback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
-            }
+        match self.poll_ack_of::<1>(identifier).await? {
+            Event::Suback(_) => Ok(()),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
 
     /// Method allows client receive a message. The work of this method strictly depends on the
     /// network implementation passed in the `ClientConfig`. It expects the PUBLISH packet
-    /// from the broker.
+    /// from the broker. Messages that arrived while waiting for an acknowledgement are returned first.
+    /// Method is cancel-safe if the network driver is, see `RawMqttClient::poll`.
     pub async fn receive_message<'b>(&'b mut self) -> Result<(&'b str, &'b [u8]), ReasonCode> {
+        if let Some(message) = self.queue.pop() {
+            return Ok(message);
+        }
+
         match self.raw.poll::<0>().await? {
             Event::Message(topic, payload) => Ok((topic, payload)),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
 
+    /// Keep alive interval in seconds, as requested in `ClientConfig` or assigned by the
+    /// broker in CONNACK. 0 means keep alive is disabled.
+    pub fn keep_alive(&self) -> u16 {
+        self.raw.keep_alive()
+    }
+
+    /// Method sends PINGREQ without waiting for the PINGRESP, which is handled by
+    /// `receive_message`. It should be called every `keep_alive` seconds. Returns
+    /// `KeepAliveTimeout` if the broker did not answer the previous call, which means
+    /// the connection is dead.
+    pub async fn send_keep_alive<'b>(&'b mut self) -> Result<(), ReasonCode> {
+        self.raw.send_keep_alive().await
+    }
+
     /// Method allows client send PING message to the broker specified in the `ClientConfig`.
     /// If there is expectation for long running connection. Method should be executed
     /// regularly by the timer that counts down the session expiry interval.
     pub async fn send_ping<'b>(&'b mut self) -> Result<(), ReasonCode> {
         self.raw.send_ping().await?;
 
-        match self.raw.poll::<0>().await? {
+        match self.poll_ack::<0>().await? {
             Event::Pingresp => Ok(()),
             Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
             _ => Err(ReasonCode::ImplementationSpecificError),
         }
     }
-}
\ No newline at end of file
+}
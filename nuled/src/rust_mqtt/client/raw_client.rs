@@ -3,8 +3,8 @@ use heapless::Vec;
 use rand_core::RngCore;
 
 use crate::{
-    rust_mqtt::encoding::variable_byte_integer::{VariableByteInteger, VariableByteIntegerDecoder},
     rust_mqtt::network::NetworkConnection,
+    rust_mqtt::packet::v3::{self, mqtt_packet::Packet as _},
     rust_mqtt::packet::v5::{
         connack_packet::ConnackPacket,
         connect_packet::ConnectPacket,
@@ -13,22 +13,33 @@ use crate::{
         packet_type::PacketType,
         pingreq_packet::PingreqPacket,
         pingresp_packet::PingrespPacket,
+        property::Property,
         puback_packet::PubackPacket,
+        pubcomp_packet::PubcompPacket,
         publish_packet::{PublishPacket, QualityOfService},
+        pubrec_packet::PubrecPacket,
+        pubrel_packet::PubrelPacket,
         reason_codes::ReasonCode,
         suback_packet::SubackPacket,
         subscription_packet::SubscriptionPacket,
         unsuback_packet::UnsubackPacket,
         unsubscription_packet::UnsubscriptionPacket,
     },
-    rust_mqtt::utils::{buffer_reader::BuffReader, buffer_writer::BuffWriter, types::BufferError},
+    rust_mqtt::utils::{buffer_reader::BuffReader, types::BufferError},
 };
 
 use super::client_config::{ClientConfig, MqttVersion};
 
+/// Number of QoS 2 exchanges that can be in flight at once, in each direction.
+const MAX_INFLIGHT: usize = 8;
+
 pub enum Event<'a> {
     Connack,
     Puback(u16),
+    /// The broker received a QoS 2 message, and PUBREL was sent.
+    Pubrec(u16),
+    /// The QoS 2 exchange for the sent message is complete.
+    Pubcomp(u16),
     Suback(u16),
     Unsuback(u16),
     Pingresp,
@@ -46,6 +57,16 @@ where
     recv_buffer: &'a mut [u8],
     recv_buffer_len: usize,
     config: ClientConfig<'a, MAX_PROPERTIES, R>,
+    /// Identifiers of sent QoS 2 messages, waiting for PUBCOMP.
+    outgoing_qos2: Vec<u16, MAX_INFLIGHT>,
+    /// Identifiers of received QoS 2 messages, waiting for PUBREL.
+    incoming_qos2: Vec<u16, MAX_INFLIGHT>,
+    /// A PINGREQ sent by `send_keep_alive` is waiting for PINGRESP.
+    keep_alive_pending: bool,
+    /// Bytes of the next packet already in `recv_buffer`.
+    received: usize,
+    /// Acknowledgement queued by `poll`, sent before anything else.
+    ack: PendingAck,
 }
 
 impl<'a, T, const MAX_PROPERTIES: usize, R> RawMqttClient<'a, T, MAX_PROPERTIES, R>
@@ -68,6 +89,27 @@ where
             recv_buffer,
             recv_buffer_len,
             config,
+            outgoing_qos2: Vec::new(),
+            incoming_qos2: Vec::new(),
+            keep_alive_pending: false,
+            received: 0,
+            ack: PendingAck::default(),
+        }
+    }
+
+    /// Keep alive interval in seconds, as requested in `ClientConfig` or assigned by the
+    /// broker in CONNACK. 0 means keep alive is disabled.
+    pub fn keep_alive(&self) -> u16 {
+        self.config.keep_alive
+    }
+
+    /// Returns a packet identifier that is not 0, and not used by a QoS 2 exchange in flight.
+    fn next_identifier(&mut self) -> u16 {
+        loop {
+            let identifier = self.config.rng.next_u32() as u16;
+            if identifier != 0 && !self.outgoing_qos2.contains(&identifier) {
+                return identifier;
+            }
         }
     }
 
@@ -103,6 +145,43 @@ where
         }
         let conn = self.connection.as_mut().unwrap();
         trace!("Sending connect");
+        self.ack.flush(conn).await?;
+        conn.send(&self.buffer[0..len.unwrap()]).await?;
+
+        Ok(())
+    }
+
+    async fn connect_to_broker_v3<'b>(&'b mut self) -> Result<(), ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        let len = {
+            let mut connect = v3::connect_packet::ConnectPacket::<'b>::new();
+            connect.keep_alive = self.config.keep_alive;
+            if self.config.username_flag {
+                connect.add_username(&self.config.username);
+            }
+            if self.config.password_flag {
+                connect.add_password(&self.config.password)
+            }
+            if self.config.will_flag {
+                connect.add_will(
+                    &self.config.will_topic,
+                    &self.config.will_payload,
+                    self.config.will_retain,
+                )
+            }
+            connect.add_client_id(&self.config.client_id);
+            connect.encode(self.buffer, self.buffer_len)
+        };
+
+        if let Err(err) = len {
+            error!("[DECODE ERR]: {}", err);
+            return Err(ReasonCode::BuffError);
+        }
+        let conn = self.connection.as_mut().unwrap();
+        trace!("Sending connect");
+        self.ack.flush(conn).await?;
         conn.send(&self.buffer[0..len.unwrap()]).await?;
 
         Ok(())
@@ -114,7 +193,7 @@ where
     /// Reason codes returned from the broker.
     pub async fn connect_to_broker<'b>(&'b mut self) -> Result<(), ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.connect_to_broker_v3().await,
             MqttVersion::MQTTv5 => self.connect_to_broker_v5().await,
         }
     }
@@ -142,13 +221,36 @@ where
         Ok(())
     }
 
+    async fn disconnect_v3(&mut self) -> Result<(), ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        let conn = self.connection.as_mut().unwrap();
+        trace!("Creating disconnect packet!");
+        let mut disconnect = v3::disconnect_packet::DisconnectPacket::new();
+        let len = disconnect.encode(self.buffer, self.buffer_len);
+        if let Err(err) = len {
+            warn!("[DECODE ERR]: {}", err);
+            let _ = self.connection.take();
+            return Err(ReasonCode::BuffError);
+        }
+
+        if let Err(_e) = conn.send(&self.buffer[0..len.unwrap()]).await {
+            warn!("Could not send DISCONNECT packet");
+        }
+
+        // Drop connection
+        let _ = self.connection.take();
+        Ok(())
+    }
+
     /// Method allows client disconnect from the server. Client disconnects from the specified broker
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the disconnect from the broker fails, method returns Err variable that contains
     /// Reason codes returned from the broker.
     pub async fn disconnect<'b>(&'b mut self) -> Result<(), ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.disconnect_v3().await,
             MqttVersion::MQTTv5 => self.disconnect_v5().await,
         }
     }
@@ -163,9 +265,11 @@ where
         if self.connection.is_none() {
             return Err(ReasonCode::NetworkError);
         }
+        if qos == QualityOfService::QoS2 && self.outgoing_qos2.is_full() {
+            return Err(ReasonCode::ReceiveMaximumExceeded);
+        }
+        let identifier = self.next_identifier();
         let conn = self.connection.as_mut().unwrap();
-        let identifier: u16 = self.config.rng.next_u32() as u16;
-        //self.rng.next_u32() as u16;
         let len = {
             let mut packet = PublishPacket::<'b, MAX_PROPERTIES>::new();
             packet.add_topic_name(topic_name);
@@ -181,10 +285,55 @@ where
             return Err(ReasonCode::BuffError);
         }
         trace!("Sending message");
+        self.ack.flush(conn).await?;
         conn.send(&self.buffer[0..len.unwrap()]).await?;
+        if qos == QualityOfService::QoS2 {
+            // Checked above, there is room.
+            let _ = self.outgoing_qos2.push(identifier);
+        }
 
         Ok(identifier)
     }
+    async fn send_message_v3<'b>(
+        &'b mut self,
+        topic_name: &'b str,
+        message: &'b [u8],
+        qos: QualityOfService,
+        retain: bool,
+    ) -> Result<u16, ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        if qos == QualityOfService::QoS2 && self.outgoing_qos2.is_full() {
+            return Err(ReasonCode::ReceiveMaximumExceeded);
+        }
+        let identifier = self.next_identifier();
+        let conn = self.connection.as_mut().unwrap();
+        let len = {
+            let mut packet = v3::publish_packet::PublishPacket::<'b>::new();
+            packet.add_topic_name(topic_name);
+            packet.add_qos(qos);
+            packet.add_identifier(identifier);
+            packet.add_message(message);
+            packet.add_retain(retain);
+            packet.encode(self.buffer, self.buffer_len)
+        };
+
+        if let Err(err) = len {
+            error!("[DECODE ERR]: {}", err);
+            return Err(ReasonCode::BuffError);
+        }
+        trace!("Sending message");
+        self.ack.flush(conn).await?;
+        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        if qos == QualityOfService::QoS2 {
+            // Checked above, there is room.
+            let _ = self.outgoing_qos2.push(identifier);
+        }
+
+        Ok(identifier)
+    }
+
     /// Method allows sending message to broker specified from the ClientConfig. Client sends the
     /// message from the parameter `message` to the topic `topic_name` on the broker
     /// specified in the ClientConfig. If the send fails method returns Err with reason code
@@ -197,7 +346,7 @@ where
         retain: bool,
     ) -> Result<u16, ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.send_message_v3(topic_name, message, qos, retain).await,
             MqttVersion::MQTTv5 => self.send_message_v5(topic_name, message, qos, retain).await,
         }
     }
@@ -209,8 +358,8 @@ where
         if self.connection.is_none() {
             return Err(ReasonCode::NetworkError);
         }
+        let identifier = self.next_identifier();
         let conn = self.connection.as_mut().unwrap();
-        let identifier: u16 = self.config.rng.next_u32() as u16;
         let len = {
             let mut subs = SubscriptionPacket::<'b, TOPICS, MAX_PROPERTIES>::new();
             subs.packet_identifier = identifier;
@@ -225,6 +374,36 @@ where
             return Err(ReasonCode::BuffError);
         }
 
+        self.ack.flush(conn).await?;
+        conn.send(&self.buffer[0..len.unwrap()]).await?;
+
+        Ok(identifier)
+    }
+
+    async fn subscribe_to_topics_v3<'b, const TOPICS: usize>(
+        &'b mut self,
+        topic_names: &'b Vec<&'b str, TOPICS>,
+    ) -> Result<u16, ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        let identifier = self.next_identifier();
+        let conn = self.connection.as_mut().unwrap();
+        let len = {
+            let mut subs = v3::subscription_packet::SubscriptionPacket::<'b, TOPICS>::new();
+            subs.packet_identifier = identifier;
+            for topic_name in topic_names.iter() {
+                subs.add_new_filter(topic_name, self.config.max_subscribe_qos);
+            }
+            subs.encode(self.buffer, self.buffer_len)
+        };
+
+        if let Err(err) = len {
+            error!("[DECODE ERR]: {}", err);
+            return Err(ReasonCode::BuffError);
+        }
+
+        self.ack.flush(conn).await?;
         conn.send(&self.buffer[0..len.unwrap()]).await?;
 
         Ok(identifier)
@@ -239,7 +418,7 @@ where
         topic_names: &'b Vec<&'b str, TOPICS>,
     ) -> Result<u16, ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.subscribe_to_topics_v3(topic_names).await,
             MqttVersion::MQTTv5 => self.subscribe_to_topics_v5(topic_names).await,
         }
     }
@@ -252,7 +431,7 @@ where
         topic_name: &'b str,
     ) -> Result<u16, ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.unsubscribe_from_topic_v3(topic_name).await,
             MqttVersion::MQTTv5 => self.unsubscribe_from_topic_v5(topic_name).await,
         }
     }
@@ -264,8 +443,8 @@ where
         if self.connection.is_none() {
             return Err(ReasonCode::NetworkError);
         }
+        let identifier = self.next_identifier();
         let conn = self.connection.as_mut().unwrap();
-        let identifier = self.config.rng.next_u32() as u16;
 
         let len = {
             let mut unsub = UnsubscriptionPacket::<'b, 1, MAX_PROPERTIES>::new();
@@ -278,11 +457,60 @@ where
             error!("[DECODE ERR]: {}", err);
             return Err(ReasonCode::BuffError);
         }
+        self.ack.flush(conn).await?;
+        conn.send(&self.buffer[0..len.unwrap()]).await?;
+
+        Ok(identifier)
+    }
+
+    async fn unsubscribe_from_topic_v3<'b>(
+        &'b mut self,
+        topic_name: &'b str,
+    ) -> Result<u16, ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        let identifier = self.next_identifier();
+        let conn = self.connection.as_mut().unwrap();
+
+        let len = {
+            let mut unsub = v3::unsubscription_packet::UnsubscriptionPacket::<'b, 1>::new();
+            unsub.packet_identifier = identifier;
+            unsub.add_new_filter(topic_name);
+            unsub.encode(self.buffer, self.buffer_len)
+        };
+
+        if let Err(err) = len {
+            error!("[DECODE ERR]: {}", err);
+            return Err(ReasonCode::BuffError);
+        }
+        self.ack.flush(conn).await?;
         conn.send(&self.buffer[0..len.unwrap()]).await?;
 
         Ok(identifier)
     }
 
+    async fn send_ping_v3(&mut self) -> Result<(), ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+        let conn = self.connection.as_mut().unwrap();
+        let len = {
+            let mut packet = v3::pingreq_packet::PingreqPacket::new();
+            packet.encode(self.buffer, self.buffer_len)
+        };
+
+        if let Err(err) = len {
+            error!("[DECODE ERR]: {}", err);
+            return Err(ReasonCode::BuffError);
+        }
+
+        self.ack.flush(conn).await?;
+        conn.send(&self.buffer[0..len.unwrap()]).await?;
+
+        Ok(())
+    }
+
     async fn send_ping_v5<'b>(&'b mut self) -> Result<(), ReasonCode> {
         if self.connection.is_none() {
             return Err(ReasonCode::NetworkError);
@@ -298,6 +526,7 @@ where
             return Err(ReasonCode::BuffError);
         }
 
+        self.ack.flush(conn).await?;
         conn.send(&self.buffer[0..len.unwrap()]).await?;
 
         Ok(())
@@ -308,163 +537,562 @@ where
     /// regularly by the timer that counts down the session expiry interval.
     pub async fn send_ping<'b>(&'b mut self) -> Result<(), ReasonCode> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => self.send_ping_v3().await,
             MqttVersion::MQTTv5 => self.send_ping_v5().await,
         }
     }
 
+    /// Method sends PINGREQ without waiting for PINGRESP, which is handled by `poll` when it
+    /// arrives. It should be called every `keep_alive` seconds while waiting for messages. If the
+    /// PINGRESP to the previous call has not arrived, the broker is considered unreachable, and
+    /// `KeepAliveTimeout` is returned.
+    pub async fn send_keep_alive<'b>(&'b mut self) -> Result<(), ReasonCode> {
+        if self.keep_alive_pending {
+            return Err(ReasonCode::KeepAliveTimeout);
+        }
+        self.send_ping().await?;
+        self.keep_alive_pending = true;
+        Ok(())
+    }
+
+    /// Method waits for the next packet from the broker. Acknowledgements of received
+    /// QoS 1 and QoS 2 messages are sent here, and PUBREL packets are answered without
+    /// returning, so a QoS 2 message is returned once even if the broker sends it again.
+    /// The PINGRESP to `send_keep_alive` is not returned either.
+    ///
+    /// Method is cancel-safe if reading and writing the network driver is. A partially
+    /// received packet is kept and resumed by the next call, and acknowledgements are
+    /// queued, to be sent by the next call or before the next packet from the client.
     pub async fn poll<'b, const MAX_TOPICS: usize>(&'b mut self) -> Result<Event<'b>, ReasonCode> {
+        self.poll_limited::<MAX_TOPICS>(usize::MAX).await
+    }
+
+    /// Method works like `poll`, but only accepts messages whose topic and payload together
+    /// are at most `max_message_len` bytes. A longer message is not acknowledged, and
+    /// `QuotaExceeded` is returned. The message is kept, and returned by the next call
+    /// that accepts it.
+    pub async fn poll_limited<'b, const MAX_TOPICS: usize>(
+        &'b mut self,
+        max_message_len: usize,
+    ) -> Result<Event<'b>, ReasonCode> {
+        match self.config.mqtt_version {
+            MqttVersion::MQTTv3 => self.poll_v3::<MAX_TOPICS>(max_message_len).await,
+            MqttVersion::MQTTv5 => self.poll_v5::<MAX_TOPICS>(max_message_len).await,
+        }
+    }
+
+    async fn poll_v3<'b, const MAX_TOPICS: usize>(
+        &'b mut self,
+        max_message_len: usize,
+    ) -> Result<Event<'b>, ReasonCode> {
         if self.connection.is_none() {
             return Err(ReasonCode::NetworkError);
         }
 
-        let conn = self.connection.as_mut().unwrap();
+        loop {
+            let conn = self.connection.as_mut().unwrap();
 
-        trace!("Waiting for a packet");
+            self.ack.flush(conn).await?;
 
-        let read = { receive_packet(self.buffer, self.buffer_len, self.recv_buffer, conn).await? };
+            trace!("Waiting for a packet");
 
-        let buf_reader = BuffReader::new(self.buffer, read);
+            let read = receive_packet(
+                &mut self.recv_buffer[..self.recv_buffer_len],
+                &mut self.received,
+                conn,
+            )
+            .await?;
 
-        match PacketType::from(buf_reader.peek_u8().map_err(|_| ReasonCode::BuffError)?) {
-            PacketType::Reserved
-            | PacketType::Connect
-            | PacketType::Subscribe
-            | PacketType::Unsubscribe
-            | PacketType::Pingreq => Err(ReasonCode::ProtocolError),
-            PacketType::Pubrec | PacketType::Pubrel | PacketType::Pubcomp | PacketType::Auth => {
-                Err(ReasonCode::ImplementationSpecificError)
-            }
-            PacketType::Connack => {
-                let mut packet = ConnackPacket::<'b, MAX_PROPERTIES>::new();
-                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
-                    // if err == BufferError::PacketTypeMismatch {
-                    //     let mut disc = DisconnectPacket::<'b, MAX_PROPERTIES>::new();
-                    //     if disc.decode(&mut BuffReader::new(self.buffer, read)).is_ok() {
-                    //         error!("Client was disconnected with reason: ");
-                    //         return Err(ReasonCode::from(disc.disconnect_reason));
-                    //     }
-                    // }
-                    error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
-                } else if packet.connect_reason_code != 0x00 {
-                    Err(ReasonCode::from(packet.connect_reason_code))
-                } else {
-                    Ok(Event::Connack)
+            let buf_reader = BuffReader::new(self.recv_buffer, read);
+
+            match PacketType::from(buf_reader.peek_u8().map_err(|_| ReasonCode::BuffError)?) {
+                // The broker does not send DISCONNECT in MQTTv3.1.1, and AUTH is reserved.
+                PacketType::Reserved
+                | PacketType::Connect
+                | PacketType::Subscribe
+                | PacketType::Unsubscribe
+                | PacketType::Pingreq
+                | PacketType::Disconnect
+                | PacketType::Auth => return Err(ReasonCode::ProtocolError),
+                PacketType::Connack => {
+                    let mut packet = v3::connack_packet::ConnackPacket::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    } else if packet.return_code != 0x00 {
+                        return Err(packet.reason_code());
+                    } else {
+                        return Ok(Event::Connack);
+                    }
                 }
-            }
-            PacketType::Puback => {
-                let reason: Result<[u16; 2], BufferError> = {
-                    let mut packet = PubackPacket::<'b, MAX_PROPERTIES>::new();
-                    packet
-                        .decode(&mut BuffReader::new(self.buffer, read))
-                        .map(|_| [packet.packet_identifier, packet.reason_code as u16])
-                };
-
-                if let Err(err) = reason {
-                    error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+                PacketType::Puback => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = v3::puback_packet::PubackPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
+
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let identifier = res.unwrap();
+                    return Ok(Event::Puback(identifier));
                 }
+                PacketType::Pubrec => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = v3::pubrec_packet::PubrecPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
 
-                let res = reason.unwrap();
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
 
-                if res[1] != 0 {
-                    return Err(ReasonCode::from(res[1] as u8));
+                    let identifier = res.unwrap();
+                    let mut pubrel = v3::pubrel_packet::PubrelPacket::new();
+                    pubrel.packet_identifier = identifier;
+                    self.ack.queue_v3(&mut pubrel)?;
+                    return Ok(Event::Pubrec(identifier));
                 }
+                PacketType::Pubcomp => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = v3::pubcomp_packet::PubcompPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
 
-                Ok(Event::Puback(res[0]))
-            }
-            PacketType::Suback => {
-                let reason: Result<(u16, Vec<u8, MAX_TOPICS>), BufferError> = {
-                    let mut packet = SubackPacket::<'b, MAX_TOPICS, MAX_PROPERTIES>::new();
-                    packet
-                        .decode(&mut BuffReader::new(self.buffer, read))
-                        .map(|_| (packet.packet_identifier, packet.reason_codes))
-                };
-
-                if let Err(err) = reason {
-                    error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let identifier = res.unwrap();
+                    release(&mut self.outgoing_qos2, identifier);
+                    return Ok(Event::Pubcomp(identifier));
+                }
+                PacketType::Pubrel => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = v3::pubrel_packet::PubrelPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
+
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let identifier = res.unwrap();
+                    release(&mut self.incoming_qos2, identifier);
+                    let mut pubcomp = v3::pubcomp_packet::PubcompPacket::new();
+                    pubcomp.packet_identifier = identifier;
+                    self.ack.queue_v3(&mut pubcomp)?;
                 }
-                let (packet_identifier, reasons) = reason.unwrap();
-                for reason_code in &reasons {
-                    if *reason_code
-                        != (<QualityOfService as Into<u8>>::into(self.config.max_subscribe_qos)
-                            >> 1)
-                    {
-                        return Err(ReasonCode::from(*reason_code));
+                PacketType::Suback => {
+                    let reason: Result<(u16, Vec<u8, MAX_TOPICS>), BufferError> = {
+                        let mut packet = v3::suback_packet::SubackPacket::<MAX_TOPICS>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| (packet.packet_identifier, packet.return_codes))
+                    };
+
+                    if let Err(err) = reason {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
                     }
+                    let (packet_identifier, return_codes) = reason.unwrap();
+                    for return_code in &return_codes {
+                        if *return_code
+                            != (<QualityOfService as Into<u8>>::into(self.config.max_subscribe_qos)
+                                >> 1)
+                        {
+                            return Err(ReasonCode::from(*return_code));
+                        }
+                    }
+                    return Ok(Event::Suback(packet_identifier));
                 }
-                Ok(Event::Suback(packet_identifier))
-            }
-            PacketType::Unsuback => {
-                let res: Result<u16, BufferError> = {
-                    let mut packet = UnsubackPacket::<'b, 1, MAX_PROPERTIES>::new();
-                    packet
-                        .decode(&mut BuffReader::new(self.buffer, read))
-                        .map(|_| packet.packet_identifier)
-                };
-
-                if let Err(err) = res {
-                    error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
-                } else {
-                    Ok(Event::Unsuback(res.unwrap()))
+                PacketType::Unsuback => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = v3::unsuback_packet::UnsubackPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
+
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let identifier = res.unwrap();
+                    return Ok(Event::Unsuback(identifier));
                 }
-            }
-            PacketType::Pingresp => {
-                let mut packet = PingrespPacket::new();
-                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
-                    error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
-                } else {
-                    Ok(Event::Pingresp)
+                PacketType::Pingresp => {
+                    let mut packet = v3::pingresp_packet::PingrespPacket::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    } else if core::mem::take(&mut self.keep_alive_pending) {
+                        continue;
+                    } else {
+                        return Ok(Event::Pingresp);
+                    }
+                }
+                PacketType::Publish => {
+                    // Decoded twice, as the message can only be borrowed once it is certain to be returned.
+                    let header: Result<(QualityOfService, u16, usize), BufferError> = {
+                        let mut packet = v3::publish_packet::PublishPacket::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| {
+                                (
+                                    QualityOfService::from(packet.fixed_header & 0x06),
+                                    packet.packet_identifier,
+                                    packet.topic_name.string.len()
+                                        + packet.message.map_or(0, |message| message.len()),
+                                )
+                            })
+                    };
+
+                    if let Err(err) = header {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let (qos, identifier, message_len) = header.unwrap();
+                    // A resent QoS 2 message is not returned, so it is acknowledged regardless.
+                    let returned =
+                        qos != QualityOfService::QoS2 || !self.incoming_qos2.contains(&identifier);
+                    if returned && message_len > max_message_len {
+                        warn!(
+                            "Message of {} bytes does not fit, leaving it unacknowledged",
+                            message_len
+                        );
+                        // Kept, so that the next call returns it again.
+                        self.received = read;
+                        return Err(ReasonCode::QuotaExceeded);
+                    }
+                    match qos {
+                        QualityOfService::QoS1 => {
+                            let mut puback = v3::puback_packet::PubackPacket::new();
+                            puback.packet_identifier = identifier;
+                            self.ack.queue_v3(&mut puback)?;
+                        }
+                        QualityOfService::QoS2 => {
+                            let duplicate = self.incoming_qos2.contains(&identifier);
+                            // MQTTv3.1.1 can not reject the message, so it is left unacknowledged.
+                            if !duplicate && self.incoming_qos2.push(identifier).is_err() {
+                                return Err(ReasonCode::ReceiveMaximumExceeded);
+                            }
+                            let mut pubrec = v3::pubrec_packet::PubrecPacket::new();
+                            pubrec.packet_identifier = identifier;
+                            self.ack.queue_v3(&mut pubrec)?;
+
+                            if duplicate {
+                                // Resent because our PUBREC was lost, the message was already returned.
+                                continue;
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let mut packet = v3::publish_packet::PublishPacket::<'b>::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    return Ok(Event::Message(
+                        packet.topic_name.string,
+                        packet.message.unwrap(),
+                    ));
                 }
             }
-            PacketType::Publish => {
-                let mut packet = PublishPacket::<'b, 5>::new();
-                if let Err(err) = { packet.decode(&mut BuffReader::new(self.buffer, read)) } {
-                    // if err == BufferError::PacketTypeMismatch {
-                    //     let mut disc = DisconnectPacket::<'b, 5>::new();
-                    //     if disc.decode(&mut BuffReader::new(self.buffer, read)).is_ok() {
-                    //         error!("Client was disconnected with reason: ");
-                    //         return Err(ReasonCode::from(disc.disconnect_reason));
-                    //     }
-                    // }
-                    error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+        }
+    }
+
+    async fn poll_v5<'b, const MAX_TOPICS: usize>(
+        &'b mut self,
+        max_message_len: usize,
+    ) -> Result<Event<'b>, ReasonCode> {
+        if self.connection.is_none() {
+            return Err(ReasonCode::NetworkError);
+        }
+
+        loop {
+            let conn = self.connection.as_mut().unwrap();
+
+            self.ack.flush(conn).await?;
+
+            trace!("Waiting for a packet");
+
+            let read = receive_packet(
+                &mut self.recv_buffer[..self.recv_buffer_len],
+                &mut self.received,
+                conn,
+            )
+            .await?;
+
+            let buf_reader = BuffReader::new(self.recv_buffer, read);
+
+            match PacketType::from(buf_reader.peek_u8().map_err(|_| ReasonCode::BuffError)?) {
+                PacketType::Reserved
+                | PacketType::Connect
+                | PacketType::Subscribe
+                | PacketType::Unsubscribe
+                | PacketType::Pingreq => return Err(ReasonCode::ProtocolError),
+                PacketType::Auth => return Err(ReasonCode::ImplementationSpecificError),
+                PacketType::Connack => {
+                    let mut packet = ConnackPacket::<'b, MAX_PROPERTIES>::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    } else if packet.connect_reason_code != 0x00 {
+                        return Err(ReasonCode::from(packet.connect_reason_code));
+                    } else {
+                        for property in &packet.properties {
+                            if let Property::ServerKeepAlive(keep_alive) = property {
+                                self.config.keep_alive = *keep_alive;
+                            }
+                        }
+                        return Ok(Event::Connack);
+                    }
                 }
+                PacketType::Puback => {
+                    let reason: Result<[u16; 2], BufferError> = {
+                        let mut packet = PubackPacket::<MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| [packet.packet_identifier, packet.reason_code as u16])
+                    };
 
-                if (packet.fixed_header & 0x06)
-                    == <QualityOfService as Into<u8>>::into(QualityOfService::QoS1)
-                {
-                    let mut puback = PubackPacket::<'b, MAX_PROPERTIES>::new();
-                    puback.packet_identifier = packet.packet_identifier;
-                    puback.reason_code = 0x00;
-                    {
-                        let len = { puback.encode(self.recv_buffer, self.recv_buffer_len) };
-                        if let Err(err) = len {
-                            error!("[DECODE ERR]: {}", err);
-                            return Err(ReasonCode::BuffError);
+                    if let Err(err) = reason {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let res = reason.unwrap();
+
+                    if res[1] != 0 {
+                        return Err(ReasonCode::from(res[1] as u8));
+                    }
+
+                    return Ok(Event::Puback(res[0]));
+                }
+                PacketType::Pubrec => {
+                    let reason: Result<[u16; 2], BufferError> = {
+                        let mut packet = PubrecPacket::<MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| [packet.packet_identifier, packet.reason_code as u16])
+                    };
+
+                    if let Err(err) = reason {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let res = reason.unwrap();
+
+                    // An error ends the exchange, without PUBREL.
+                    if res[1] >= 0x80 {
+                        release(&mut self.outgoing_qos2, res[0]);
+                        return Err(ReasonCode::from(res[1] as u8));
+                    }
+
+                    let mut pubrel = PubrelPacket::<MAX_PROPERTIES>::new();
+                    pubrel.packet_identifier = res[0];
+                    if !self.outgoing_qos2.contains(&res[0]) {
+                        pubrel.reason_code = ReasonCode::PacketIdentifierNotFound.into();
+                    }
+                    self.ack.queue(&mut pubrel)?;
+
+                    return Ok(Event::Pubrec(res[0]));
+                }
+                PacketType::Pubcomp => {
+                    let reason: Result<[u16; 2], BufferError> = {
+                        let mut packet = PubcompPacket::<MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| [packet.packet_identifier, packet.reason_code as u16])
+                    };
+
+                    if let Err(err) = reason {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let res = reason.unwrap();
+                    release(&mut self.outgoing_qos2, res[0]);
+
+                    if res[1] != 0 {
+                        return Err(ReasonCode::from(res[1] as u8));
+                    }
+
+                    return Ok(Event::Pubcomp(res[0]));
+                }
+                PacketType::Pubrel => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = PubrelPacket::<MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
+
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let identifier = res.unwrap();
+                    let mut pubcomp = PubcompPacket::<MAX_PROPERTIES>::new();
+                    pubcomp.packet_identifier = identifier;
+                    if !release(&mut self.incoming_qos2, identifier) {
+                        pubcomp.reason_code = ReasonCode::PacketIdentifierNotFound.into();
+                    }
+                    self.ack.queue(&mut pubcomp)?;
+                }
+                PacketType::Suback => {
+                    let reason: Result<(u16, Vec<u8, MAX_TOPICS>), BufferError> = {
+                        let mut packet = SubackPacket::<MAX_TOPICS, MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| (packet.packet_identifier, packet.reason_codes))
+                    };
+
+                    if let Err(err) = reason {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+                    let (packet_identifier, reasons) = reason.unwrap();
+                    for reason_code in &reasons {
+                        if *reason_code
+                            != (<QualityOfService as Into<u8>>::into(self.config.max_subscribe_qos)
+                                >> 1)
+                        {
+                            return Err(ReasonCode::from(*reason_code));
                         }
-                        conn.send(&self.recv_buffer[0..len.unwrap()]).await?;
                     }
+                    return Ok(Event::Suback(packet_identifier));
                 }
+                PacketType::Unsuback => {
+                    let res: Result<u16, BufferError> = {
+                        let mut packet = UnsubackPacket::<1, MAX_PROPERTIES>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| packet.packet_identifier)
+                    };
 
-                Ok(Event::Message(
-                    packet.topic_name.string,
-                    packet.message.unwrap(),
-                ))
-            }
-            PacketType::Disconnect => {
-                let mut disc = DisconnectPacket::<'b, 5>::new();
-                let res = disc.decode(&mut BuffReader::new(self.buffer, read));
+                    if let Err(err) = res {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    } else {
+                        return Ok(Event::Unsuback(res.unwrap()));
+                    }
+                }
+                PacketType::Pingresp => {
+                    let mut packet = PingrespPacket::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    } else if core::mem::take(&mut self.keep_alive_pending) {
+                        continue;
+                    } else {
+                        return Ok(Event::Pingresp);
+                    }
+                }
+                PacketType::Publish => {
+                    // Decoded twice, as the message can only be borrowed once it is certain to be returned.
+                    let header: Result<(QualityOfService, u16, usize), BufferError> = {
+                        let mut packet = PublishPacket::<5>::new();
+                        packet
+                            .decode(&mut BuffReader::new(self.recv_buffer, read))
+                            .map(|_| {
+                                (
+                                    QualityOfService::from(packet.fixed_header & 0x06),
+                                    packet.packet_identifier,
+                                    packet.topic_name.string.len()
+                                        + packet.message.map_or(0, |message| message.len()),
+                                )
+                            })
+                    };
 
-                match res {
-                    Ok(_) => Ok(Event::Disconnect(ReasonCode::from(disc.disconnect_reason))),
-                    Err(err) => {
+                    if let Err(err) = header {
                         error!("[DECODE ERR]: {}", err);
-                        Err(ReasonCode::BuffError)
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    let (qos, identifier, message_len) = header.unwrap();
+                    // A resent QoS 2 message is not returned, so it is acknowledged regardless.
+                    let returned =
+                        qos != QualityOfService::QoS2 || !self.incoming_qos2.contains(&identifier);
+                    if returned && message_len > max_message_len {
+                        warn!(
+                            "Message of {} bytes does not fit, leaving it unacknowledged",
+                            message_len
+                        );
+                        // Kept, so that the next call returns it again.
+                        self.received = read;
+                        return Err(ReasonCode::QuotaExceeded);
+                    }
+                    match qos {
+                        QualityOfService::QoS1 => {
+                            let mut puback = PubackPacket::<MAX_PROPERTIES>::new();
+                            puback.packet_identifier = identifier;
+                            puback.reason_code = 0x00;
+                            self.ack.queue(&mut puback)?;
+                        }
+                        QualityOfService::QoS2 => {
+                            let duplicate = self.incoming_qos2.contains(&identifier);
+                            let mut pubrec = PubrecPacket::<MAX_PROPERTIES>::new();
+                            pubrec.packet_identifier = identifier;
+                            if !duplicate && self.incoming_qos2.push(identifier).is_err() {
+                                pubrec.reason_code = ReasonCode::ReceiveMaximumExceeded.into();
+                            }
+                            self.ack.queue(&mut pubrec)?;
+
+                            if pubrec.reason_code != 0x00 {
+                                return Err(ReasonCode::ReceiveMaximumExceeded);
+                            }
+                            if duplicate {
+                                // Resent because our PUBREC was lost, the message was already returned.
+                                continue;
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let mut packet = PublishPacket::<'b, 5>::new();
+                    if let Err(err) = packet.decode(&mut BuffReader::new(self.recv_buffer, read)) {
+                        error!("[DECODE ERR]: {}", err);
+                        return Err(ReasonCode::BuffError);
+                    }
+
+                    return Ok(Event::Message(
+                        packet.topic_name.string,
+                        packet.message.unwrap(),
+                    ));
+                }
+                PacketType::Disconnect => {
+                    let mut disc = DisconnectPacket::<'b, 5>::new();
+                    let res = disc.decode(&mut BuffReader::new(self.recv_buffer, read));
+
+                    match res {
+                        Ok(_) => {
+                            return Ok(Event::Disconnect(ReasonCode::from(disc.disconnect_reason)))
+                        }
+                        Err(err) => {
+                            error!("[DECODE ERR]: {}", err);
+                            return Err(ReasonCode::BuffError);
+                        }
                     }
                 }
             }
@@ -472,91 +1100,123 @@ where
     }
 }
 
-#[cfg(not(feature = "tls"))]
-async fn receive_packet<'c, T: Read + Write>(
-    buffer: &mut [u8],
-    buffer_len: usize,
-    recv_buffer: &mut [u8],
-    conn: &'c mut NetworkConnection<T>,
-) -> Result<usize, ReasonCode> {
-    use crate::rust_mqtt::utils::buffer_writer::RemLenError;
+/// Removes `identifier` from the in-flight identifiers. Returns false if it was not there.
+fn release(inflight: &mut Vec<u16, MAX_INFLIGHT>, identifier: u16) -> bool {
+    match inflight.iter().position(|id| *id == identifier) {
+        Some(index) => {
+            inflight.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
 
-    let target_len: usize;
-    let mut rem_len: Result<VariableByteInteger, RemLenError>;
-    let mut writer = BuffWriter::new(buffer, buffer_len);
-    let mut i = 0;
+/// Size of an acknowledgement without properties: fixed header, remaining length, packet
+/// identifier, reason code and property length.
+const ACK_SIZE: usize = 6;
 
-    // Get len of packet
-    trace!("Reading lenght of packet");
-    loop {
-        trace!("    Reading in loop!");
-        let len: usize = conn
-            .receive(&mut recv_buffer[writer.position..(writer.position + 1)])
-            .await?;
-        trace!("    Received data!");
-        if len == 0 {
-            trace!("Zero byte len packet received, dropping connection.");
-            return Err(ReasonCode::NetworkError);
-        }
-        i += len;
-        if let Err(_e) = writer.insert_ref(len, &recv_buffer[writer.position..i]) {
-            error!("Error occurred during write to buffer!");
-            return Err(ReasonCode::BuffError);
-        }
-        if i > 1 {
-            rem_len = writer.get_rem_len();
-            if rem_len.is_ok() {
-                break;
+/// Acknowledgement queued by `poll`.
+///
+/// `poll` only queues acknowledgements, and sends them when it is called again or before the
+/// next packet, so that it can be cancelled while waiting without losing or repeating one.
+#[derive(Default)]
+struct PendingAck {
+    bytes: [u8; ACK_SIZE],
+    len: usize,
+    /// Bytes already written, if sending was cancelled.
+    sent: usize,
+}
+
+impl PendingAck {
+    /// Encodes an acknowledgement to be sent by `flush`.
+    fn queue<'c, P: Packet<'c>>(&mut self, packet: &mut P) -> Result<(), ReasonCode> {
+        let len = packet.encode(&mut self.bytes, ACK_SIZE);
+        self.queued(len)
+    }
+
+    /// Encodes an MQTTv3.1.1 acknowledgement to be sent by `flush`.
+    fn queue_v3<'c, P: v3::mqtt_packet::Packet<'c>>(
+        &mut self,
+        packet: &mut P,
+    ) -> Result<(), ReasonCode> {
+        let len = packet.encode(&mut self.bytes, ACK_SIZE);
+        self.queued(len)
+    }
+
+    fn queued(&mut self, len: Result<usize, BufferError>) -> Result<(), ReasonCode> {
+        match len {
+            Ok(len) => {
+                self.len = len;
+                self.sent = 0;
+                Ok(())
             }
-            if i >= 5 {
-                error!("Could not read len of packet!");
-                return Err(ReasonCode::NetworkError);
+            Err(err) => {
+                error!("[DECODE ERR]: {}", err);
+                Err(ReasonCode::BuffError)
             }
         }
     }
-    trace!("Lenght done!");
-    let rem_len_len = i;
-    i = 0;
-    if let Ok(l) = VariableByteIntegerDecoder::decode(rem_len.unwrap()) {
-        trace!("Reading packet with target len {}", l);
-        target_len = l as usize;
-    } else {
-        error!("Could not decode len of packet!");
-        return Err(ReasonCode::BuffError);
+
+    /// Sends the queued acknowledgement, if any.
+    async fn flush<T: Read + Write>(
+        &mut self,
+        conn: &mut NetworkConnection<T>,
+    ) -> Result<(), ReasonCode> {
+        while self.sent < self.len {
+            self.sent += conn.write(&self.bytes[self.sent..self.len]).await?;
+        }
+        self.len = 0;
+        self.sent = 0;
+        Ok(())
     }
+}
 
+/// Receives the next packet into `buffer`, and returns its length.
+///
+/// `received` counts the bytes of the packet already in `buffer`, so that a call which was
+/// cancelled is resumed by the next one. It is reset once the packet is complete.
+async fn receive_packet<T: Read + Write>(
+    buffer: &mut [u8],
+    received: &mut usize,
+    conn: &mut NetworkConnection<T>,
+) -> Result<usize, ReasonCode> {
     loop {
-        if writer.position == target_len + rem_len_len {
-            trace!("Received packet with len: {}", (target_len + rem_len_len));
-            return Ok(target_len + rem_len_len);
+        // The remaining length is read one byte at a time, so no byte of the next packet is read.
+        let target_len = packet_len(&buffer[..*received])?.unwrap_or(*received + 1);
+        if *received == target_len {
+            trace!("Received packet with len: {}", target_len);
+            *received = 0;
+            return Ok(target_len);
         }
-        let len: usize = conn
-            .receive(&mut recv_buffer[writer.position..writer.position + (target_len - i)])
-            .await?;
-        i += len;
-        if let Err(_e) =
-            writer.insert_ref(len, &recv_buffer[writer.position..(writer.position + i)])
-        {
-            error!("Error occurred during write to buffer!");
+        if target_len > buffer.len() {
+            error!("Packet does not fit into the receive buffer!");
+            *received = 0;
             return Err(ReasonCode::BuffError);
         }
+
+        let len = conn.receive(&mut buffer[*received..target_len]).await?;
+        if len == 0 {
+            trace!("Zero byte len packet received, dropping connection.");
+            *received = 0;
+            return Err(ReasonCode::NetworkError);
+        }
+        *received += len;
     }
 }
 
-#[cfg(feature = "tls")]
-async fn receive_packet<'c, T: Read + Write>(
-    buffer: &mut [u8],
-    buffer_len: usize,
-    recv_buffer: &mut [u8],
-    conn: &'c mut NetworkConnection<T>,
-) -> Result<usize, ReasonCode> {
-    trace!("Reading packet");
-    let mut writer = BuffWriter::new(buffer, buffer_len);
-    let len = conn.receive(recv_buffer).await?;
-    if let Err(_e) = writer.insert_ref(len, &recv_buffer[writer.position..(writer.position + len)])
-    {
-        error!("Error occurred during write to buffer!");
-        return Err(ReasonCode::BuffError);
-    }
-    Ok(len)
-}
\ No newline at end of file
+/// Length of the packet starting with `header`, or `None` if its remaining length is not
+/// complete yet.
+fn packet_len(header: &[u8]) -> Result<Option<usize>, ReasonCode> {
+    let mut remaining_len = 0;
+    for (i, byte) in header.iter().skip(1).enumerate() {
+        remaining_len |= usize::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(1 + (i + 1) + remaining_len));
+        }
+        if i == 3 {
+            error!("Could not read len of packet!");
+            return Err(ReasonCode::NetworkError);
+        }
+    }
+    Ok(None)
+}
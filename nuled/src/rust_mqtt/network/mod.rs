@@ -52,6 +52,14 @@ where
         Ok(())
     }
 
+    /// Send data from `buffer`, returning how much of it was written.
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<usize, ReasonCode> {
+        self.io
+            .write(buffer)
+            .await
+            .map_err(|_| ReasonCode::NetworkError)
+    }
+
     /// Receive data to the `buffer` from TCP connection.
     pub async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, ReasonCode> {
         self.io
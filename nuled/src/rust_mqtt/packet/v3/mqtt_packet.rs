@@ -0,0 +1,54 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::utils::types::BufferError;
+
+/// This trait provide interface for mapping MQTTv3.1.1 packets to human readable structures,
+/// like the MQTTv5 `Packet` trait. MQTTv3.1.1 packets do not have properties.
+pub trait Packet<'a> {
+    fn new() -> Self;
+    /// Method encode provide way how to transfer Packet struct into Byte array (buffer)
+    fn encode(&mut self, buffer: &mut [u8], buff_len: usize) -> Result<usize, BufferError>;
+    /// Decode method is opposite of encode - decoding Byte array and mapping it into corresponding Packet struct
+    fn decode(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError>;
+
+    /// Setter for packet fixed header
+    fn set_fixed_header(&mut self, header: u8);
+    /// Setter for remaining len
+    fn set_remaining_len(&mut self, remaining_len: u32);
+
+    /// Method is decoding packet header into fixed header part and remaining length
+    fn decode_fixed_header(
+        &mut self,
+        buff_reader: &mut BuffReader,
+    ) -> Result<PacketType, BufferError> {
+        let first_byte: u8 = buff_reader.read_u8()?;
+        trace!("First byte of accepted packet: {:02X}", first_byte);
+        self.set_fixed_header(first_byte);
+        self.set_remaining_len(buff_reader.read_variable_byte_int()?);
+        Ok(PacketType::from(first_byte))
+    }
+}
@@ -79,8 +79,16 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PubrecPacket<'a, MAX_PROPER
             return Err(BufferError::PacketTypeMismatch);
         }
         self.packet_identifier = buff_reader.read_u16()?;
-        self.reason_code = buff_reader.read_u8()?;
-        self.decode_properties(buff_reader)
+        // The reason code and properties may be left out when they are empty.
+        if self.remain_len != 2 {
+            self.reason_code = buff_reader.read_u8()?;
+        }
+        if self.remain_len < 4 {
+            self.property_len = 0;
+        } else {
+            self.decode_properties(buff_reader)?;
+        }
+        Ok(())
     }
 
     fn set_property_len(&mut self, value: u32) {
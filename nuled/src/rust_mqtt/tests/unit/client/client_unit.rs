@@ -0,0 +1,511 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::cell::Cell;
+use core::convert::Infallible;
+use core::future::pending;
+
+use embedded_io_async::{ErrorType, Read, Write};
+use heapless::Vec;
+use rand_core::{impls, Error, RngCore};
+use tokio_test::{block_on, task};
+
+use crate::rust_mqtt::client::client::MqttClient;
+use crate::rust_mqtt::client::client_config::{ClientConfig, MqttVersion};
+use crate::rust_mqtt::client::raw_client::RawMqttClient;
+use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use crate::rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use crate::rust_mqtt::utils::rng_generator::CountingRng;
+
+/// Replays the packets of a broker, and records what the client sends.
+struct ScriptedStream<'a> {
+    input: &'a [u8],
+    position: usize,
+    /// Bytes of `input` that have arrived, reading waits at this position.
+    available: Option<&'a Cell<usize>>,
+    output: Vec<u8, 128>,
+}
+
+impl<'a> ScriptedStream<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            position: 0,
+            available: None,
+            output: Vec::new(),
+        }
+    }
+
+    fn arriving(input: &'a [u8], available: &'a Cell<usize>) -> Self {
+        Self {
+            available: Some(available),
+            ..Self::new(input)
+        }
+    }
+}
+
+impl ErrorType for ScriptedStream<'_> {
+    type Error = Infallible;
+}
+
+impl Read for ScriptedStream<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let end = self.available.map_or(self.input.len(), Cell::get);
+        if self.position == end {
+            pending::<()>().await;
+        }
+        let len = buf.len().min(end - self.position);
+        buf[..len].copy_from_slice(&self.input[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Write for ScriptedStream<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.output.extend_from_slice(buf).unwrap();
+        Ok(buf.len())
+    }
+}
+
+/// Returns the given values in order.
+struct SequenceRng(&'static [u32]);
+
+impl RngCore for SequenceRng {
+    fn next_u32(&mut self) -> u32 {
+        let (first, rest) = self.0.split_first().unwrap();
+        self.0 = rest;
+        *first
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_u32() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn client<'a, 's, R: RngCore>(
+    stream: &'a mut ScriptedStream<'s>,
+    buffer: &'a mut [u8; 64],
+    recv_buffer: &'a mut [u8; 64],
+    queue_buffer: &'a mut [u8],
+    rng: R,
+) -> MqttClient<'a, &'a mut ScriptedStream<'s>, 5, R> {
+    let config = ClientConfig::new(MqttVersion::MQTTv5, rng);
+    MqttClient::<_, 5, _>::new_with_queue(stream, buffer, 64, recv_buffer, 64, queue_buffer, config)
+}
+
+#[test]
+fn test_send_qos2() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBREC and PUBCOMP, without reason codes.
+    let mut stream = ScriptedStream::new(&[0x50, 0x02, 0x00, 0x01, 0x70, 0x02, 0x00, 0x01]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        let res = block_on(client.send_message("a/b", b"hi", QualityOfService::QoS2, false));
+        assert_eq!(res, Ok(()));
+    }
+    assert_eq!(stream.position, 8);
+    assert_eq!(
+        stream.output,
+        [
+            0x34, 0x0A, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x01, 0x00, 0x68, 0x69, 0x62, 0x04,
+            0x00, 0x01, 0x00, 0x00
+        ]
+    )
+}
+
+#[test]
+fn test_send_qos2_rejected() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBREC with quota exceeded ends the exchange.
+    let mut stream = ScriptedStream::new(&[0x50, 0x03, 0x00, 0x01, 0x97]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        let res = block_on(client.send_message("a/b", b"hi", QualityOfService::QoS2, false));
+        assert_eq!(res, Err(ReasonCode::QuotaExceeded));
+    }
+    assert_eq!(stream.output.len(), 12);
+}
+
+#[test]
+fn test_receive_qos2() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBLISH 7, the same PUBLISH with DUP set, PUBREL 7 and PUBLISH 8.
+    let mut stream = ScriptedStream::new(&[
+        0x34, 0x0A, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x07, 0x00, 0x68, 0x69, 0x3C, 0x0A, 0x00,
+        0x03, 0x61, 0x2F, 0x62, 0x00, 0x07, 0x00, 0x68, 0x69, 0x62, 0x02, 0x00, 0x07, 0x34, 0x0A,
+        0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x08, 0x00, 0x79, 0x6F,
+    ]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"yo"[..])));
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+    }
+    assert_eq!(
+        stream.output,
+        // PUBREC 7 twice, PUBCOMP 7, and PUBREC 8 before the next packet, PINGREQ.
+        [
+            0x50, 0x04, 0x00, 0x07, 0x00, 0x00, 0x50, 0x04, 0x00, 0x07, 0x00, 0x00, 0x70, 0x04,
+            0x00, 0x07, 0x00, 0x00, 0x50, 0x04, 0x00, 0x08, 0x00, 0x00, 0xC0, 0x00
+        ]
+    )
+}
+
+#[test]
+fn test_unknown_pubrel() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBREL 9, then PINGRESP.
+    let mut stream = ScriptedStream::new(&[0x62, 0x02, 0x00, 0x09, 0xD0, 0x00]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        assert_eq!(block_on(client.send_ping()), Ok(()));
+    }
+    // PINGREQ, then PUBCOMP with packet identifier not found.
+    assert_eq!(
+        stream.output,
+        [0xC0, 0x00, 0x70, 0x04, 0x00, 0x09, 0x92, 0x00]
+    )
+}
+
+#[test]
+fn test_identifiers_in_flight() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut stream = ScriptedStream::new(&[]);
+    let config = ClientConfig::new(MqttVersion::MQTTv5, SequenceRng(&[0, 5, 5, 6]));
+    let mut raw =
+        RawMqttClient::<_, 5, _>::new(&mut stream, &mut buffer, 64, &mut recv_buffer, 64, config);
+    let first = block_on(raw.send_message("a/b", b"hi", QualityOfService::QoS2, false));
+    let second = block_on(raw.send_message("a/b", b"hi", QualityOfService::QoS2, false));
+    assert_eq!(first, Ok(5));
+    assert_eq!(second, Ok(6));
+}
+
+#[test]
+fn test_v3_session() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // CONNACK, SUBACK 1 granting QoS 1, PUBLISH 7 with QoS 1 and PUBACK 2.
+    let mut stream = ScriptedStream::new(&[
+        0x20, 0x02, 0x00, 0x00, 0x90, 0x03, 0x00, 0x01, 0x01, 0x32, 0x09, 0x00, 0x03, 0x61, 0x2F,
+        0x62, 0x00, 0x07, 0x68, 0x69, 0x40, 0x02, 0x00, 0x02,
+    ]);
+    {
+        let mut config = ClientConfig::new(MqttVersion::MQTTv3, CountingRng(0));
+        config.add_client_id("abc");
+        config.add_max_subscribe_qos(QualityOfService::QoS1);
+        let mut client =
+            MqttClient::<_, 5, _>::new(&mut stream, &mut buffer, 64, &mut recv_buffer, 64, config);
+        assert_eq!(block_on(client.connect_to_broker()), Ok(()));
+        assert_eq!(block_on(client.subscribe_to_topic("a/b")), Ok(()));
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+        let res = block_on(client.send_message("a/b", b"hi", QualityOfService::QoS1, false));
+        assert_eq!(res, Ok(()));
+    }
+    assert_eq!(
+        stream.output,
+        // CONNECT, SUBSCRIBE 1, PUBACK 7 and PUBLISH 2.
+        [
+            0x10, 0x0F, 0x00, 0x04, 0x4D, 0x51, 0x54, 0x54, 0x04, 0x02, 0x00, 0x3C, 0x00, 0x03,
+            0x61, 0x62, 0x63, 0x82, 0x08, 0x00, 0x01, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x01, 0x40,
+            0x02, 0x00, 0x07, 0x32, 0x09, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x02, 0x68, 0x69
+        ]
+    )
+}
+
+#[test]
+fn test_v3_connect_refused() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // CONNACK, not authorized.
+    let mut stream = ScriptedStream::new(&[0x20, 0x02, 0x00, 0x05]);
+    let config = ClientConfig::new(MqttVersion::MQTTv3, CountingRng(0));
+    let mut client =
+        MqttClient::<_, 5, _>::new(&mut stream, &mut buffer, 64, &mut recv_buffer, 64, config);
+    assert_eq!(
+        block_on(client.connect_to_broker()),
+        Err(ReasonCode::NotAuthorized)
+    );
+}
+
+#[test]
+fn test_v3_qos2() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBREC 1, PUBCOMP 1, PUBLISH 7, PUBREL 7 and PUBLISH 8.
+    let mut stream = ScriptedStream::new(&[
+        0x50, 0x02, 0x00, 0x01, 0x70, 0x02, 0x00, 0x01, 0x34, 0x09, 0x00, 0x03, 0x61, 0x2F, 0x62,
+        0x00, 0x07, 0x68, 0x69, 0x62, 0x02, 0x00, 0x07, 0x34, 0x09, 0x00, 0x03, 0x61, 0x2F, 0x62,
+        0x00, 0x08, 0x79, 0x6F,
+    ]);
+    {
+        let config = ClientConfig::new(MqttVersion::MQTTv3, CountingRng(0));
+        let mut client =
+            MqttClient::<_, 5, _>::new(&mut stream, &mut buffer, 64, &mut recv_buffer, 64, config);
+        let res = block_on(client.send_message("a/b", b"hi", QualityOfService::QoS2, false));
+        assert_eq!(res, Ok(()));
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"yo"[..])));
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+    }
+    assert_eq!(
+        stream.output,
+        // PUBLISH 1, PUBREL 1, PUBREC 7, PUBCOMP 7, PUBREC 8 and PINGREQ.
+        [
+            0x34, 0x09, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x01, 0x68, 0x69, 0x62, 0x02, 0x00,
+            0x01, 0x50, 0x02, 0x00, 0x07, 0x70, 0x02, 0x00, 0x07, 0x50, 0x02, 0x00, 0x08, 0xC0,
+            0x00
+        ]
+    )
+}
+
+#[test]
+fn test_queue_message_during_ack() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut queue_buffer = [0; 64];
+    // PUBLISH "hi" and PUBLISH "yo" with QoS 0, PUBACK 1 and PUBLISH "ok".
+    let mut stream = ScriptedStream::new(&[
+        0x30, 0x08, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x68, 0x69, 0x30, 0x08, 0x00, 0x03, 0x61,
+        0x2F, 0x63, 0x00, 0x79, 0x6F, 0x40, 0x02, 0x00, 0x01, 0x30, 0x08, 0x00, 0x03, 0x61, 0x2F,
+        0x64, 0x00, 0x6F, 0x6B,
+    ]);
+    let mut client = client(
+        &mut stream,
+        &mut buffer,
+        &mut recv_buffer,
+        &mut queue_buffer,
+        CountingRng(0),
+    );
+    let res = block_on(client.send_message("a/b", b"hi", QualityOfService::QoS1, false));
+    assert_eq!(res, Ok(()));
+    assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+    assert_eq!(block_on(client.receive_message()), Ok(("a/c", &b"yo"[..])));
+    assert_eq!(block_on(client.receive_message()), Ok(("a/d", &b"ok"[..])));
+}
+
+#[test]
+fn test_queue_full() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut queue_buffer = [0; 8];
+    // PUBLISH "hi" with QoS 0, which does not fit in the queue.
+    let mut stream = ScriptedStream::new(&[
+        0x30, 0x08, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x68, 0x69, 0xD0, 0x00,
+    ]);
+    let mut client = client(
+        &mut stream,
+        &mut buffer,
+        &mut recv_buffer,
+        &mut queue_buffer,
+        CountingRng(0),
+    );
+    assert_eq!(block_on(client.send_ping()), Err(ReasonCode::QuotaExceeded));
+}
+
+#[test]
+fn test_queue_full_withholds_ack() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut queue_buffer = [0; 8];
+    // PUBLISH 7 "hi" with QoS 1, which does not fit in the queue.
+    let mut stream = ScriptedStream::new(&[
+        0x32, 0x0A, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x07, 0x00, 0x68, 0x69,
+    ]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut queue_buffer,
+            CountingRng(0),
+        );
+        assert_eq!(block_on(client.send_ping()), Err(ReasonCode::QuotaExceeded));
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+    }
+    // PINGREQ twice, without PUBACK 7.
+    assert_eq!(stream.output, [0xC0, 0x00, 0xC0, 0x00]);
+}
+
+#[test]
+fn test_queue_full_retry() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut queue_buffer = [0; 16];
+    // PUBLISH "hi" and PUBLISH "yo" with QoS 0, of which only the first fits in the queue,
+    // SUBACK 1 to the abandoned request and SUBACK 2 to the retry.
+    let mut stream = ScriptedStream::new(&[
+        0x30, 0x08, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x68, 0x69, 0x30, 0x08, 0x00, 0x03, 0x61,
+        0x2F, 0x63, 0x00, 0x79, 0x6F, 0x90, 0x04, 0x00, 0x01, 0x00, 0x00, 0x90, 0x04, 0x00, 0x02,
+        0x00, 0x00,
+    ]);
+    let mut client = client(
+        &mut stream,
+        &mut buffer,
+        &mut recv_buffer,
+        &mut queue_buffer,
+        CountingRng(0),
+    );
+    let res = block_on(client.subscribe_to_topic("a/#"));
+    assert_eq!(res, Err(ReasonCode::QuotaExceeded));
+    assert!(client.has_queued_message());
+    assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+    assert!(!client.has_queued_message());
+    assert_eq!(block_on(client.subscribe_to_topic("a/#")), Ok(()));
+    // The message that did not fit is not lost.
+    assert_eq!(block_on(client.receive_message()), Ok(("a/c", &b"yo"[..])));
+}
+
+#[test]
+fn test_no_queue() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PUBLISH "hi" with QoS 0 and PINGRESP.
+    let mut stream = ScriptedStream::new(&[
+        0x30, 0x08, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x68, 0x69, 0xD0, 0x00,
+    ]);
+    let config = ClientConfig::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client =
+        MqttClient::<_, 5, _>::new(&mut stream, &mut buffer, 64, &mut recv_buffer, 64, config);
+    assert_eq!(block_on(client.send_ping()), Err(ReasonCode::QuotaExceeded));
+}
+
+#[test]
+fn test_server_keep_alive() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // CONNACK with server keep alive 30.
+    let mut stream = ScriptedStream::new(&[0x20, 0x06, 0x00, 0x00, 0x03, 0x13, 0x00, 0x1E]);
+    let mut client = client(
+        &mut stream,
+        &mut buffer,
+        &mut recv_buffer,
+        &mut [],
+        CountingRng(0),
+    );
+    assert_eq!(client.keep_alive(), 60);
+    assert_eq!(block_on(client.connect_to_broker()), Ok(()));
+    assert_eq!(client.keep_alive(), 30);
+}
+
+#[test]
+fn test_keep_alive() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    // PINGRESP, then PUBLISH with QoS 0.
+    let mut stream = ScriptedStream::new(&[
+        0xD0, 0x00, 0x30, 0x08, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x68, 0x69,
+    ]);
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+        assert_eq!(
+            block_on(client.send_keep_alive()),
+            Err(ReasonCode::KeepAliveTimeout)
+        );
+    }
+    // PINGREQ twice.
+    assert_eq!(stream.output, [0xC0, 0x00, 0xC0, 0x00]);
+}
+
+#[test]
+fn test_cancel_receive() {
+    let mut buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let available = Cell::new(1);
+    // PUBLISH 7 with QoS 1, arriving in three parts.
+    let mut stream = ScriptedStream::arriving(
+        &[
+            0x32, 0x0A, 0x00, 0x03, 0x61, 0x2F, 0x62, 0x00, 0x07, 0x00, 0x68, 0x69,
+        ],
+        &available,
+    );
+    {
+        let mut client = client(
+            &mut stream,
+            &mut buffer,
+            &mut recv_buffer,
+            &mut [],
+            CountingRng(0),
+        );
+        // Each receive is cancelled while waiting for the rest of the packet.
+        assert!(task::spawn(client.receive_message()).poll().is_pending());
+        available.set(5);
+        assert!(task::spawn(client.receive_message()).poll().is_pending());
+        available.set(12);
+        assert_eq!(block_on(client.receive_message()), Ok(("a/b", &b"hi"[..])));
+        assert_eq!(block_on(client.send_keep_alive()), Ok(()));
+    }
+    // PUBACK 7 and PINGREQ.
+    assert_eq!(
+        stream.output,
+        [0x40, 0x04, 0x00, 0x07, 0x00, 0x00, 0xC0, 0x00]
+    );
+}
@@ -22,10 +22,10 @@
  * SOFTWARE.
  */
 
-use crate::encoding::variable_byte_integer::{
+use crate::rust_mqtt::encoding::variable_byte_integer::{
     VariableByteInteger, VariableByteIntegerDecoder, VariableByteIntegerEncoder,
 };
-use crate::utils::types::BufferError;
+use crate::rust_mqtt::utils::types::BufferError;
 
 #[test]
 fn test_decode() {
@@ -22,6 +22,7 @@
  * SOFTWARE.
  */
 
+pub mod client;
 pub mod encoding;
 pub mod packet;
 pub mod utils;
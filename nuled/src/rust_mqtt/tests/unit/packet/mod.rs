@@ -22,4 +22,5 @@
  * SOFTWARE.
  */
 
+pub mod v3;
 pub mod v5;
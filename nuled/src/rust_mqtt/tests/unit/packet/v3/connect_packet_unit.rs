@@ -0,0 +1,81 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::rust_mqtt::packet::v3::connect_packet::ConnectPacket;
+use crate::rust_mqtt::packet::v3::mqtt_packet::Packet;
+use crate::rust_mqtt::utils::types::{BinaryData, EncodedString};
+
+#[test]
+fn test_encode() {
+    let mut buffer: [u8; 100] = [0; 100];
+    let mut connect = ConnectPacket::new();
+    connect.keep_alive = 60;
+    let mut client_id = EncodedString::new();
+    client_id.string = "abc";
+    client_id.len = 3;
+    connect.add_client_id(&client_id);
+    let res = connect.encode(&mut buffer, 100);
+
+    assert!(res.is_ok());
+    assert_eq!(
+        buffer[0..res.unwrap()],
+        [
+            0x10, 0x0F, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x04, 0x02, 0x00, 0x3c, 0x00, 0x03,
+            0x61, 0x62, 0x63
+        ]
+    )
+}
+
+#[test]
+fn test_encode_will_and_credentials() {
+    let mut buffer: [u8; 100] = [0; 100];
+    let mut connect = ConnectPacket::new();
+    connect.keep_alive = 60;
+    let mut topic = EncodedString::new();
+    topic.string = "a/b";
+    topic.len = 3;
+    let mut payload = BinaryData::new();
+    payload.bin = b"off";
+    payload.len = 3;
+    connect.add_will(&topic, &payload, true);
+    let mut username = EncodedString::new();
+    username.string = "u";
+    username.len = 1;
+    connect.add_username(&username);
+    let mut password = BinaryData::new();
+    password.bin = b"p";
+    password.len = 1;
+    connect.add_password(&password);
+    let res = connect.encode(&mut buffer, 100);
+
+    assert!(res.is_ok());
+    assert_eq!(
+        buffer[0..res.unwrap()],
+        [
+            0x10, 0x1C, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x04, 0xE6, 0x00, 0x3c, 0x00, 0x00,
+            0x00, 0x03, 0x61, 0x2f, 0x62, 0x00, 0x03, 0x6f, 0x66, 0x66, 0x00, 0x01, 0x75, 0x00,
+            0x01, 0x70
+        ]
+    )
+}
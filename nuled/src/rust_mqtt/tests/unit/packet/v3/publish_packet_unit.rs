@@ -0,0 +1,73 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::rust_mqtt::packet::v3::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v3::publish_packet::PublishPacket;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+
+#[test]
+fn test_encode() {
+    let mut buffer: [u8; 11] = [0; 11];
+    let mut packet = PublishPacket::new();
+    packet.add_topic_name("a/b");
+    packet.add_qos(QualityOfService::QoS1);
+    packet.add_identifier(0x1234);
+    packet.add_message(b"hi");
+    packet.add_retain(true);
+    let res = packet.encode(&mut buffer, 11);
+    assert!(res.is_ok());
+    assert_eq!(res.unwrap(), 11);
+    assert_eq!(
+        buffer,
+        [0x33, 0x09, 0x00, 0x03, 0x61, 0x2f, 0x62, 0x12, 0x34, 0x68, 0x69]
+    )
+}
+
+#[test]
+fn test_decode() {
+    let buffer: [u8; 9] = [0x30, 0x07, 0x00, 0x03, 0x61, 0x2f, 0x62, 0x68, 0x69];
+    let mut packet = PublishPacket::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 9));
+    assert!(res.is_ok());
+    assert_eq!(packet.fixed_header, PacketType::Publish.into());
+    assert_eq!(packet.remain_len, 7);
+    assert_eq!(packet.topic_name.string, "a/b");
+    assert_eq!(packet.message.unwrap(), b"hi");
+}
+
+#[test]
+fn test_decode_qos2() {
+    let buffer: [u8; 11] = [
+        0x34, 0x09, 0x00, 0x03, 0x61, 0x2f, 0x62, 0x12, 0x34, 0x68, 0x69,
+    ];
+    let mut packet = PublishPacket::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 11));
+    assert!(res.is_ok());
+    assert_eq!(packet.fixed_header & 0x06, QualityOfService::QoS2.into());
+    assert_eq!(packet.packet_identifier, 0x1234);
+    assert_eq!(packet.topic_name.string, "a/b");
+    assert_eq!(packet.message.unwrap(), b"hi");
+}
@@ -0,0 +1,48 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::rust_mqtt::packet::v3::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v3::suback_packet::SubackPacket;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+
+#[test]
+fn test_decode() {
+    let buffer: [u8; 6] = [0x90, 0x04, 0x00, 0x01, 0x01, 0x80];
+    let mut packet = SubackPacket::<2>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 6));
+    assert!(res.is_ok());
+    assert_eq!(packet.fixed_header, PacketType::Suback.into());
+    assert_eq!(packet.remain_len, 4);
+    assert_eq!(packet.packet_identifier, 1);
+    assert_eq!(packet.return_codes, [0x01, 0x80]);
+}
+
+#[test]
+fn test_decode_too_many() {
+    let buffer: [u8; 6] = [0x90, 0x04, 0x00, 0x01, 0x01, 0x80];
+    let mut packet = SubackPacket::<1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 6));
+    assert!(res.is_err());
+}
@@ -0,0 +1,43 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::rust_mqtt::packet::v3::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v3::subscription_packet::SubscriptionPacket;
+use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService;
+
+#[test]
+fn test_encode() {
+    let mut buffer: [u8; 14] = [0; 14];
+    let mut packet = SubscriptionPacket::<2>::new();
+    packet.packet_identifier = 1;
+    packet.add_new_filter("a/b", QualityOfService::QoS1);
+    packet.add_new_filter("c", QualityOfService::QoS0);
+    let res = packet.encode(&mut buffer, 14);
+    assert!(res.is_ok());
+    assert_eq!(res.unwrap(), 14);
+    assert_eq!(
+        buffer,
+        [0x82, 0x0C, 0x00, 0x01, 0x00, 0x03, 0x61, 0x2f, 0x62, 0x01, 0x00, 0x01, 0x63, 0x00]
+    )
+}
@@ -22,11 +22,11 @@
  * SOFTWARE.
  */
 
-use crate::packet::v5::connack_packet::ConnackPacket;
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::property::Property;
-use crate::packet::v5::reason_codes::ReasonCode;
-use crate::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::packet::v5::connack_packet::ConnackPacket;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
 
 #[test]
 fn test_encode() {
@@ -57,7 +57,7 @@ fn test_encode() {
 
 #[test]
 fn test_decode() {
-    let mut buffer: [u8; 8] = [
+    let buffer: [u8; 8] = [
         0x20,
         0x06,
         0x45,
@@ -22,8 +22,9 @@
  * SOFTWARE.
  */
 
-use crate::packet::v5::connect_packet::ConnectPacket;
-use crate::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::connect_packet::ConnectPacket;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::utils::types::{BinaryData, EncodedString};
 
 #[test]
 fn test_encode() {
@@ -40,3 +41,27 @@ fn test_encode() {
         ]
     )
 }
+
+#[test]
+fn test_encode_will() {
+    let mut buffer: [u8; 100] = [0; 100];
+    let mut connect = ConnectPacket::<1, 0>::clean();
+    let mut topic = EncodedString::new();
+    topic.string = "led/a";
+    topic.len = 5;
+    let mut payload = BinaryData::new();
+    payload.bin = b"offline";
+    payload.len = 7;
+    connect.add_will(&topic, &payload, true);
+    let res = connect.encode(&mut buffer, 100);
+
+    assert!(res.is_ok());
+    assert_eq!(
+        buffer[0..res.unwrap()],
+        [
+            0x10, 0x21, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x05, 0x26, 0x00, 0x3c, 0x03, 0x21,
+            0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x05, 0x6c, 0x65, 0x64, 0x2f, 0x61, 0x00, 0x07,
+            0x6f, 0x66, 0x66, 0x6c, 0x69, 0x6e, 0x65
+        ]
+    )
+}
@@ -24,11 +24,11 @@
 
 use heapless::Vec;
 
-use crate::packet::v5::disconnect_packet::DisconnectPacket;
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::property::Property;
-use crate::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::packet::v5::disconnect_packet::DisconnectPacket;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
 
 #[test]
 fn test_encode() {
@@ -22,9 +22,9 @@
  * SOFTWARE.
  */
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::pingreq_packet::PingreqPacket;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::pingreq_packet::PingreqPacket;
 
 #[test]
 fn test_encode() {
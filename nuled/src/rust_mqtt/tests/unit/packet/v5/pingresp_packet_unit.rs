@@ -22,10 +22,10 @@
  * SOFTWARE.
  */
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::pingresp_packet::PingrespPacket;
-use crate::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::pingresp_packet::PingrespPacket;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
 
 #[test]
 fn test_encode() {
@@ -24,12 +24,12 @@
 
 use heapless::Vec;
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::property::Property;
-use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
-use crate::utils::buffer_reader::BuffReader;
-use crate::utils::types::EncodedString;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::utils::types::EncodedString;
 
 #[test]
 fn test_encode() {
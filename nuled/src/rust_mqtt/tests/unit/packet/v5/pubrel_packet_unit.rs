@@ -24,12 +24,12 @@
 
 use heapless::Vec;
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::property::Property;
-use crate::packet::v5::pubrel_packet::PubrelPacket;
-use crate::utils::buffer_reader::BuffReader;
-use crate::utils::types::{EncodedString, StringPair};
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::packet::v5::pubrel_packet::PubrelPacket;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::utils::types::{EncodedString, StringPair};
 
 #[test]
 fn test_encode() {
@@ -86,3 +86,27 @@ fn test_decode() {
         assert_eq!(u.value.string, "hehe89");
     }
 }
+
+#[test]
+fn test_decode_short() {
+    let buffer: [u8; 4] = [0x62, 0x02, 0x8A, 0x5C];
+    let mut packet = PubrelPacket::<1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 4));
+    assert!(res.is_ok());
+    assert_eq!(packet.fixed_header, PacketType::Pubrel.into());
+    assert_eq!(packet.remain_len, 2);
+    assert_eq!(packet.packet_identifier, 35420);
+    assert_eq!(packet.reason_code, 0x00);
+    assert_eq!(packet.property_len, 0);
+}
+
+#[test]
+fn test_decode_reason_only() {
+    let buffer: [u8; 5] = [0x62, 0x03, 0x8A, 0x5C, 0x92];
+    let mut packet = PubrelPacket::<1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 5));
+    assert!(res.is_ok());
+    assert_eq!(packet.packet_identifier, 35420);
+    assert_eq!(packet.reason_code, 0x92);
+    assert_eq!(packet.property_len, 0);
+}
@@ -24,11 +24,11 @@
 
 use heapless::Vec;
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::property::Property;
-use crate::packet::v5::publish_packet::QualityOfService::{QoS0, QoS1};
-use crate::packet::v5::subscription_packet::SubscriptionPacket;
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::packet::v5::publish_packet::QualityOfService::{QoS0, QoS1};
+use crate::rust_mqtt::packet::v5::subscription_packet::SubscriptionPacket;
 
 #[test]
 fn test_encode() {
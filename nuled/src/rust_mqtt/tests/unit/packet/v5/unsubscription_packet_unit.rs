@@ -24,11 +24,11 @@
 
 use heapless::Vec;
 
-use crate::packet::v5::mqtt_packet::Packet;
-use crate::packet::v5::packet_type::PacketType;
-use crate::packet::v5::property::Property;
-use crate::packet::v5::unsubscription_packet::UnsubscriptionPacket;
-use crate::utils::types::{EncodedString, StringPair};
+use crate::rust_mqtt::packet::v5::mqtt_packet::Packet;
+use crate::rust_mqtt::packet::v5::packet_type::PacketType;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::packet::v5::unsubscription_packet::UnsubscriptionPacket;
+use crate::rust_mqtt::utils::types::{EncodedString, StringPair};
 
 #[test]
 fn test_encode() {
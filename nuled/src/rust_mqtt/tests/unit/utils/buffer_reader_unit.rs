@@ -22,8 +22,8 @@
  * SOFTWARE.
  */
 
-use crate::utils::buffer_reader::BuffReader;
-use crate::utils::types::BufferError;
+use crate::rust_mqtt::utils::buffer_reader::BuffReader;
+use crate::rust_mqtt::utils::types::BufferError;
 
 #[test]
 fn buffer_read_variable_byte() {
@@ -25,10 +25,10 @@
 use heapless::Vec;
 use tokio_test::{assert_err, assert_ok};
 
-use crate::encoding::variable_byte_integer::VariableByteInteger;
-use crate::packet::v5::property::Property;
-use crate::utils::buffer_writer::BuffWriter;
-use crate::utils::types::{BinaryData, BufferError, EncodedString, StringPair, TopicFilter};
+use crate::rust_mqtt::encoding::variable_byte_integer::VariableByteInteger;
+use crate::rust_mqtt::packet::v5::property::Property;
+use crate::rust_mqtt::utils::buffer_writer::BuffWriter;
+use crate::rust_mqtt::utils::types::{BinaryData, BufferError, EncodedString, StringPair, TopicFilter};
 
 #[test]
 fn buffer_write_ref() {
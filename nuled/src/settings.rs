@@ -0,0 +1,433 @@
+/// Runtime settings, persisted in flash.
+///
+/// Data in flash is stored as records with a header and a CRC-32 checksum:
+///
+/// ```text
+/// magic: [u8; 4] | version: u16 | length: u16 | payload: [u8; length] | crc32: u32
+/// ```
+///
+/// Integers and floats are little endian, and strings are stored as a length byte followed by UTF-8.
+/// The checksum covers the header and the payload. A missing, corrupt or unknown settings record
+/// is ignored, and the compile-time configuration is used instead.
+
+use embedded_storage::{ReadStorage, Storage};
+use heapless::String;
+
+const MAGIC: [u8; 4] = *b"NULD";
+const HEADER_SIZE: usize = 8;
+const CRC_SIZE: usize = 4;
+
+/// Version of the settings payload. Increase when changing the layout, and keep reading older versions.
+///
+/// 1. Initial version.
+/// 2. Added `power_on`.
+pub const VERSION: u16 = 2;
+
+/// Maximum size of a stored record.
+pub const RECORD_SIZE: usize = 512;
+
+#[derive(Debug, PartialEq)]
+pub enum SettingsError {
+    Flash,
+    /// No record found at the given flash offset.
+    Missing,
+    UnsupportedVersion(u16),
+    Checksum,
+    Corrupt,
+    UnknownKey,
+    InvalidValue,
+}
+
+/// What to show on the LED strip after a power cut or restart.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PowerOn {
+    /// Restore the last state.
+    #[default]
+    Last,
+    /// Start with the saved preset, or the default effect and parameters if none is saved.
+    Preset,
+    /// Restore the last state, but keep the LED strip switched off.
+    Off,
+}
+
+impl PowerOn {
+    pub const ALL: [PowerOn; 3] = [PowerOn::Last, PowerOn::Preset, PowerOn::Off];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerOn::Last => "last",
+            PowerOn::Preset => "preset",
+            PowerOn::Off => "off",
+        }
+    }
+}
+
+/// Settings that can be changed without reflashing. Changes take effect after a restart.
+#[derive(Clone, PartialEq)]
+pub struct Settings {
+    pub wifi_ssid: String<32>,
+    pub wifi_password: String<64>,
+    pub mqtt_server: String<64>,
+    pub mqtt_port: u16,
+    pub mqtt_username: String<64>,
+    pub mqtt_password: String<64>,
+    pub led_count: u16,
+    pub power_on: PowerOn,
+}
+
+impl Settings {
+    /// Names of all settings, as used on the MQTT configuration topics.
+    pub const KEYS: [&'static str; 8] = [
+        "wifi_ssid",
+        "wifi_password",
+        "mqtt_server",
+        "mqtt_port",
+        "mqtt_username",
+        "mqtt_password",
+        "led_count",
+        "power_on",
+    ];
+
+    /// Change a setting by name.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        fn string<const N: usize>(value: &str) -> Result<String<N>, SettingsError> {
+            String::try_from(value).map_err(|_| SettingsError::InvalidValue)
+        }
+
+        match key {
+            "wifi_ssid" => self.wifi_ssid = string(value)?,
+            "wifi_password" => self.wifi_password = string(value)?,
+            "mqtt_server" => self.mqtt_server = string(value)?,
+            "mqtt_port" => self.mqtt_port = value.parse().map_err(|_| SettingsError::InvalidValue)?,
+            "mqtt_username" => self.mqtt_username = string(value)?,
+            "mqtt_password" => self.mqtt_password = string(value)?,
+            "led_count" => {
+                self.led_count = match value.parse() {
+                    Ok(0) | Err(_) => return Err(SettingsError::InvalidValue),
+                    Ok(count) => count,
+                }
+            }
+            "power_on" => {
+                self.power_on = PowerOn::ALL.into_iter()
+                    .find(|power_on| power_on.name() == value)
+                    .ok_or(SettingsError::InvalidValue)?
+            }
+            _ => return Err(SettingsError::UnknownKey),
+        }
+        Ok(())
+    }
+
+    /// Serialize into a complete record, including header and checksum.
+    /// Returns the number of bytes written.
+    pub fn encode(&self, buf: &mut [u8; RECORD_SIZE]) -> usize {
+        encode_record(buf, MAGIC, VERSION, |w| {
+            w.string(&self.wifi_ssid);
+            w.string(&self.wifi_password);
+            w.string(&self.mqtt_server);
+            w.u16(self.mqtt_port);
+            w.string(&self.mqtt_username);
+            w.string(&self.mqtt_password);
+            w.u16(self.led_count);
+            w.u8(self.power_on as u8);
+        })
+    }
+
+    /// Deserialize a record, verifying its header and checksum.
+    pub fn decode(buf: &[u8]) -> Result<Self, SettingsError> {
+        let (version, mut r) = decode_record(buf, MAGIC)?;
+        if version > VERSION {
+            return Err(SettingsError::UnsupportedVersion(version));
+        }
+
+        let mut settings = Settings {
+            wifi_ssid: r.string()?,
+            wifi_password: r.string()?,
+            mqtt_server: r.string()?,
+            mqtt_port: r.u16()?,
+            mqtt_username: r.string()?,
+            mqtt_password: r.string()?,
+            led_count: r.u16()?,
+            power_on: PowerOn::default(),
+        };
+
+        if version >= 2 {
+            settings.power_on = *PowerOn::ALL.get(r.u8()? as usize).ok_or(SettingsError::Corrupt)?;
+        }
+
+        r.finish()?;
+        Ok(settings)
+    }
+
+    /// Read settings from flash at `offset`.
+    pub fn load<S: ReadStorage>(storage: &mut S, offset: u32) -> Result<Self, SettingsError> {
+        let mut buf = [0_u8; RECORD_SIZE];
+        storage.read(offset, &mut buf).map_err(|_| SettingsError::Flash)?;
+        Self::decode(&buf)
+    }
+
+    /// Write settings to flash at `offset`.
+    pub fn save<S: Storage>(&self, storage: &mut S, offset: u32) -> Result<(), SettingsError> {
+        let mut buf = [0_u8; RECORD_SIZE];
+        let len = self.encode(&mut buf);
+        storage.write(offset, &buf[..len]).map_err(|_| SettingsError::Flash)
+    }
+}
+
+/// Write a complete record into `buf`, with the payload written by `payload`.
+/// Returns the length of the record.
+pub fn encode_record(
+    buf: &mut [u8; RECORD_SIZE],
+    magic: [u8; 4],
+    version: u16,
+    payload: impl FnOnce(&mut Writer),
+) -> usize {
+    let mut w = Writer { buf, pos: HEADER_SIZE };
+    payload(&mut w);
+
+    let end = w.pos;
+    buf[0..4].copy_from_slice(&magic);
+    buf[4..6].copy_from_slice(&version.to_le_bytes());
+    buf[6..8].copy_from_slice(&((end - HEADER_SIZE) as u16).to_le_bytes());
+    let crc = crc32(&buf[..end]);
+    buf[end..end + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    end + CRC_SIZE
+}
+
+/// Verify the header and checksum of a record.
+/// Returns the version of the record, and a reader for its payload.
+pub fn decode_record(buf: &[u8], magic: [u8; 4]) -> Result<(u16, Reader<'_>), SettingsError> {
+    if buf.len() < HEADER_SIZE || buf[0..4] != magic {
+        return Err(SettingsError::Missing);
+    }
+
+    let version = u16::from_le_bytes([buf[4], buf[5]]);
+    let end = HEADER_SIZE + u16::from_le_bytes([buf[6], buf[7]]) as usize;
+    if end + CRC_SIZE > buf.len() {
+        return Err(SettingsError::Corrupt);
+    }
+
+    let crc = u32::from_le_bytes(buf[end..end + CRC_SIZE].try_into().unwrap());
+    if crc != crc32(&buf[..end]) {
+        return Err(SettingsError::Checksum);
+    }
+
+    Ok((version, Reader { buf: &buf[..end], pos: HEADER_SIZE }))
+}
+
+pub struct Writer<'a> {
+    buf: &'a mut [u8; RECORD_SIZE],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    // Records are small, and always fit well within RECORD_SIZE.
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Strings must be shorter than 256 bytes.
+    pub fn string(&mut self, value: &str) {
+        self.u8(value.len() as u8);
+        self.bytes(value.as_bytes());
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], SettingsError> {
+        let bytes = self.slice(N)?;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&[u8], SettingsError> {
+        let bytes = self.buf.get(self.pos..self.pos + len).ok_or(SettingsError::Corrupt)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SettingsError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SettingsError> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, SettingsError> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    pub fn string<const N: usize>(&mut self) -> Result<String<N>, SettingsError> {
+        let len = self.u8()? as usize;
+        let s = core::str::from_utf8(self.slice(len)?).map_err(|_| SettingsError::Corrupt)?;
+        String::try_from(s).map_err(|_| SettingsError::Corrupt)
+    }
+
+    /// Check that the whole payload has been read.
+    pub fn finish(self) -> Result<(), SettingsError> {
+        match self.pos == self.buf.len() {
+            true => Ok(()),
+            false => Err(SettingsError::Corrupt),
+        }
+    }
+}
+
+/// CRC-32 as used by Ethernet and zlib (reflected polynomial 0xEDB88320).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flash memory emulated in RAM, erased to 0xFF like real NOR flash.
+    struct RamStorage([u8; 4096]);
+
+    impl ReadStorage for RamStorage {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(self.0.get(offset..offset + bytes.len()).ok_or(())?);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl Storage for RamStorage {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.0.get_mut(offset..offset + bytes.len()).ok_or(())?.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            wifi_ssid: "ssid".try_into().unwrap(),
+            wifi_password: "hunter2".try_into().unwrap(),
+            mqtt_server: "mqtt.example.com".try_into().unwrap(),
+            mqtt_port: 1883,
+            mqtt_username: "nuled".try_into().unwrap(),
+            mqtt_password: "secret".try_into().unwrap(),
+            led_count: 30,
+            power_on: PowerOn::Off,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut storage = RamStorage([0xFF; 4096]);
+        settings().save(&mut storage, 1024).unwrap();
+        assert!(Settings::load(&mut storage, 1024).unwrap() == settings());
+    }
+
+    #[test]
+    fn erased_flash_is_missing() {
+        let mut storage = RamStorage([0xFF; 4096]);
+        assert_eq!(Settings::load(&mut storage, 0).err(), Some(SettingsError::Missing));
+    }
+
+    #[test]
+    fn corrupt_record_fails_checksum() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        settings().encode(&mut buf);
+        buf[HEADER_SIZE + 2] ^= 0x01;
+        assert_eq!(Settings::decode(&buf).err(), Some(SettingsError::Checksum));
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        encode_record(&mut buf, MAGIC, VERSION + 1, |w| w.u8(0));
+        assert_eq!(Settings::decode(&buf).err(), Some(SettingsError::UnsupportedVersion(VERSION + 1)));
+    }
+
+    #[test]
+    fn version_1_is_migrated() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        encode_record(&mut buf, MAGIC, 1, |w| {
+            w.string("ssid");
+            w.string("hunter2");
+            w.string("mqtt.example.com");
+            w.u16(1883);
+            w.string("nuled");
+            w.string("secret");
+            w.u16(30);
+        });
+        let settings = Settings::decode(&buf).unwrap();
+        assert_eq!(settings.led_count, 30);
+        assert_eq!(settings.power_on, PowerOn::Last);
+    }
+
+    #[test]
+    fn trailing_data_is_corrupt() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        encode_record(&mut buf, MAGIC, VERSION, |w| {
+            let mut buf = [0_u8; RECORD_SIZE];
+            settings().encode(&mut buf);
+            w.bytes(&buf[HEADER_SIZE..HEADER_SIZE + 64]);
+        });
+        assert_eq!(Settings::decode(&buf).err(), Some(SettingsError::Corrupt));
+    }
+
+    #[test]
+    fn truncated_record_is_corrupt() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        let len = settings().encode(&mut buf);
+        assert_eq!(Settings::decode(&buf[..len - 1]).err(), Some(SettingsError::Corrupt));
+    }
+
+    #[test]
+    fn set_by_key() {
+        let mut settings = settings();
+        settings.set("mqtt_port", "8883").unwrap();
+        settings.set("wifi_ssid", "other").unwrap();
+        assert_eq!(settings.mqtt_port, 8883);
+        assert_eq!(settings.wifi_ssid.as_str(), "other");
+        assert_eq!(settings.set("led_count", "0"), Err(SettingsError::InvalidValue));
+        assert_eq!(settings.set("mqtt_port", "http"), Err(SettingsError::InvalidValue));
+        assert_eq!(settings.set("wifi_ssid", &"x".repeat(33)), Err(SettingsError::InvalidValue));
+        assert_eq!(settings.set("power_on", "preset"), Ok(()));
+        assert_eq!(settings.power_on, PowerOn::Preset);
+        assert_eq!(settings.set("power_on", "sometimes"), Err(SettingsError::InvalidValue));
+        assert_eq!(settings.set("colour", "red"), Err(SettingsError::UnknownKey));
+        for key in Settings::KEYS {
+            assert_ne!(settings.set(key, "off"), Err(SettingsError::UnknownKey));
+        }
+    }
+}
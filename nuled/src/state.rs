@@ -0,0 +1,345 @@
+/// The LED state controlled over MQTT, the HTTP API and LIFX,
+/// its JSON representation, and its record format for persisting it in flash.
+
+use serde::{Deserialize, Serialize};
+use crate::color::RGB;
+use crate::effect::Params;
+use crate::output::{valid_gamma, OutputParams};
+use crate::settings::{decode_record, encode_record, Reader, SettingsError, RECORD_SIZE};
+
+const MAGIC: [u8; 4] = *b"NUST";
+
+/// Version of the state payload. Stored state with a different version is discarded.
+const VERSION: u16 = 1;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ServerState {
+    pub effect: Effect,
+    pub led_effect_params: Params,
+    pub output_params: OutputParams,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Solid,
+    #[default]
+    Rainbow,
+    Gradient,
+    Polyrhythm,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 4] = [Effect::Solid, Effect::Rainbow, Effect::Gradient, Effect::Polyrhythm];
+
+    /// Name of the effect as used in the MQTT API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Solid => "solid",
+            Effect::Rainbow => "rainbow",
+            Effect::Gradient => "gradient",
+            Effect::Polyrhythm => "polyrhythm",
+        }
+    }
+}
+
+/// Encode the state into a record, and return its length.
+pub fn encode(state: &ServerState, buf: &mut [u8; RECORD_SIZE]) -> usize {
+    encode_record(buf, MAGIC, VERSION, |w| {
+        let params = &state.led_effect_params;
+        let output = &state.output_params;
+        // Stored as the position in `Effect::ALL`, which is also what `decode` expects.
+        let effect = Effect::ALL.iter().position(|effect| *effect == state.effect).unwrap_or_default();
+        w.u8(effect as u8);
+        for color in [params.color1, params.color2] {
+            w.f32(color.r);
+            w.f32(color.g);
+            w.f32(color.b);
+        }
+        for value in [params.chroma, params.luminance, params.size, params.speed, params.transition] {
+            w.f32(value);
+        }
+        w.u8(output.on as u8);
+        for gamma in output.gamma {
+            w.f32(gamma);
+        }
+        w.f32(output.brightness);
+        w.u8(output.dither as u8);
+        w.f32(output.power_budget);
+    })
+}
+
+pub fn decode(buf: &[u8]) -> Result<ServerState, SettingsError> {
+    let (version, mut r) = decode_record(buf, MAGIC)?;
+    if version != VERSION {
+        return Err(SettingsError::UnsupportedVersion(version));
+    }
+
+    fn rgb(r: &mut Reader) -> Result<RGB, SettingsError> {
+        Ok(RGB { r: r.f32()?, g: r.f32()?, b: r.f32()? })
+    }
+
+    let mut state = ServerState::default();
+    let params = &mut state.led_effect_params;
+    let output = &mut state.output_params;
+
+    state.effect = *Effect::ALL.get(r.u8()? as usize).ok_or(SettingsError::Corrupt)?;
+    params.color1 = rgb(&mut r)?;
+    params.color2 = rgb(&mut r)?;
+    params.chroma = r.f32()?;
+    params.luminance = r.f32()?;
+    params.size = r.f32()?;
+    params.speed = r.f32()?;
+    params.transition = r.f32()?;
+    output.on = r.u8()? != 0;
+    for gamma in output.gamma.iter_mut() {
+        *gamma = r.f32()?;
+    }
+    if !valid_gamma(&output.gamma) {
+        return Err(SettingsError::Corrupt);
+    }
+    output.brightness = r.f32()?;
+    output.dither = r.u8()? != 0;
+    output.power_budget = r.f32()?;
+
+    r.finish()?;
+    Ok(state)
+}
+
+/// Parse `on` or `off`, and their aliases `true`/`false` and `1`/`0`.
+pub fn parse_on_off(s: &str) -> Option<bool> {
+    if ["on", "true", "1"].iter().any(|x| s.eq_ignore_ascii_case(x)) {
+        Some(true)
+    } else if ["off", "false", "0"].iter().any(|x| s.eq_ignore_ascii_case(x)) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidCommand;
+
+/// JSON command, accepted on the MQTT `<prefix>/set` topic and by `POST /api/state`.
+/// All fields are optional, and the ones present are applied as a single update.
+#[derive(Deserialize)]
+pub struct JsonCommand<'a> {
+    #[serde(borrow)]
+    state: Option<&'a str>,
+    effect: Option<Effect>,
+    color1: Option<JsonRgb>,
+    color2: Option<JsonRgb>,
+    chroma: Option<f32>,
+    luminance: Option<f32>,
+    size: Option<f32>,
+    speed: Option<f32>,
+    transition: Option<f32>,
+    brightness: Option<f32>,
+    gamma: Option<[f32; 4]>,
+    dither: Option<bool>,
+    power_budget: Option<f32>,
+}
+
+impl JsonCommand<'_> {
+    /// Apply all fields at once.
+    /// The command is rejected as a whole if any of the fields are invalid.
+    pub fn apply(self, state: &mut ServerState) -> Result<(), InvalidCommand> {
+        let on = match self.state {
+            Some(s) => Some(parse_on_off(s).ok_or(InvalidCommand)?),
+            None => None,
+        };
+        if self.gamma.is_some_and(|gamma| !valid_gamma(&gamma)) {
+            return Err(InvalidCommand);
+        }
+
+        let params = &mut state.led_effect_params;
+        if let Some(color) = self.color1 {
+            params.color1 = color.into();
+        }
+        if let Some(color) = self.color2 {
+            params.color2 = color.into();
+        }
+        params.chroma = self.chroma.unwrap_or(params.chroma);
+        params.luminance = self.luminance.unwrap_or(params.luminance);
+        params.size = self.size.unwrap_or(params.size);
+        params.speed = self.speed.unwrap_or(params.speed);
+        params.transition = self.transition.unwrap_or(params.transition);
+
+        let output = &mut state.output_params;
+        output.on = on.unwrap_or(output.on);
+        output.brightness = self.brightness.unwrap_or(output.brightness);
+        output.gamma = self.gamma.unwrap_or(output.gamma);
+        output.dither = self.dither.unwrap_or(output.dither);
+        output.power_budget = self.power_budget.unwrap_or(output.power_budget);
+
+        state.effect = self.effect.unwrap_or(state.effect);
+
+        Ok(())
+    }
+}
+
+/// JSON state document, published on the MQTT `<prefix>/state` topic and returned by `/api/state`.
+#[derive(Serialize)]
+pub struct JsonState {
+    state: &'static str,
+    effect: Effect,
+    color1: JsonRgb,
+    color2: JsonRgb,
+    chroma: f32,
+    luminance: f32,
+    size: f32,
+    speed: f32,
+    transition: f32,
+    brightness: f32,
+    gamma: [f32; 4],
+    dither: bool,
+    power_budget: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonRgb {
+    r: f32,
+    g: f32,
+    b: f32,
+}
+
+impl From<JsonRgb> for RGB {
+    fn from(value: JsonRgb) -> Self {
+        RGB { r: value.r, g: value.g, b: value.b }
+    }
+}
+
+impl From<RGB> for JsonRgb {
+    fn from(value: RGB) -> Self {
+        JsonRgb { r: value.r, g: value.g, b: value.b }
+    }
+}
+
+impl From<&ServerState> for JsonState {
+    fn from(state: &ServerState) -> Self {
+        let params = &state.led_effect_params;
+        let output = &state.output_params;
+        JsonState {
+            state: if output.on { "on" } else { "off" },
+            effect: state.effect,
+            color1: params.color1.into(),
+            color2: params.color2.into(),
+            chroma: params.chroma,
+            luminance: params.luminance,
+            size: params.size,
+            speed: params.speed,
+            transition: params.transition,
+            brightness: output.brightness,
+            gamma: output.gamma,
+            dither: output.dither,
+            power_budget: output.power_budget,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> ServerState {
+        let mut state = ServerState { effect: Effect::Gradient, ..ServerState::default() };
+        let params = &mut state.led_effect_params;
+        params.color1 = RGB { r: 255.0, g: 128.0, b: 0.0 };
+        params.color2 = RGB { r: 0.0, g: 64.0, b: 255.0 };
+        params.chroma = 0.5;
+        params.luminance = 0.25;
+        params.size = 3.0;
+        params.speed = 1.5;
+        params.transition = 2000.0;
+        let output = &mut state.output_params;
+        output.on = false;
+        output.gamma = [2.2, 2.0, 1.8, 1.0];
+        output.brightness = 0.75;
+        output.dither = false;
+        output.power_budget = 0.0;
+        state
+    }
+
+    fn round_trip(state: &ServerState) -> Result<ServerState, SettingsError> {
+        let mut buf = [0_u8; RECORD_SIZE];
+        let len = encode(state, &mut buf);
+        decode(&buf[..len])
+    }
+
+    #[test]
+    fn round_trip_state() {
+        assert_eq!(round_trip(&state()), Ok(state()));
+        assert_eq!(round_trip(&ServerState::default()), Ok(ServerState::default()));
+    }
+
+    #[test]
+    fn round_trip_every_effect() {
+        for effect in Effect::ALL {
+            let state = ServerState { effect, ..state() };
+            assert_eq!(round_trip(&state), Ok(state));
+        }
+    }
+
+    #[test]
+    fn invalid_gamma_is_corrupt() {
+        let mut state = state();
+        state.output_params.gamma[1] = 0.0;
+        assert_eq!(round_trip(&state), Err(SettingsError::Corrupt));
+    }
+
+    #[test]
+    fn other_records_are_missing() {
+        let mut buf = [0_u8; RECORD_SIZE];
+        let len = encode_record(&mut buf, *b"NULD", VERSION, |w| w.u8(0));
+        assert_eq!(decode(&buf[..len]), Err(SettingsError::Missing));
+
+        let len = encode_record(&mut buf, MAGIC, VERSION + 1, |w| w.u8(0));
+        assert_eq!(decode(&buf[..len]), Err(SettingsError::UnsupportedVersion(VERSION + 1)));
+    }
+
+    fn command(json: &str) -> JsonCommand<'_> {
+        serde_json_core::from_str(json).unwrap().0
+    }
+
+    #[test]
+    fn command_changes_given_fields() {
+        let mut state = state();
+        let json = r#"{"state": "ON", "effect": "solid", "color1": {"r": 1, "g": 2, "b": 3}, "brightness": 0.5}"#;
+        command(json).apply(&mut state).unwrap();
+
+        let mut expected = self::state();
+        expected.effect = Effect::Solid;
+        expected.led_effect_params.color1 = RGB { r: 1.0, g: 2.0, b: 3.0 };
+        expected.output_params.on = true;
+        expected.output_params.brightness = 0.5;
+        assert_eq!(state, expected);
+
+        command("{}").apply(&mut state).unwrap();
+        assert_eq!(state, expected);
+    }
+
+    #[test]
+    fn invalid_command_changes_nothing() {
+        let mut state = state();
+        assert!(command(r#"{"speed": 4, "state": "dim"}"#).apply(&mut state).is_err());
+        assert!(command(r#"{"speed": 4, "gamma": [2.2, 2.2, 0, 2.2]}"#).apply(&mut state).is_err());
+        assert_eq!(state, self::state());
+    }
+
+    #[test]
+    fn state_document_is_a_command() {
+        let json: heapless::String<512> = serde_json_core::to_string(&JsonState::from(&state())).unwrap();
+        let mut applied = ServerState::default();
+        command(&json).apply(&mut applied).unwrap();
+        assert_eq!(applied, state());
+    }
+
+    #[test]
+    fn on_off_aliases() {
+        assert_eq!(parse_on_off("On"), Some(true));
+        assert_eq!(parse_on_off("true"), Some(true));
+        assert_eq!(parse_on_off("0"), Some(false));
+        assert_eq!(parse_on_off("OFF"), Some(false));
+        assert_eq!(parse_on_off("dim"), None);
+    }
+}
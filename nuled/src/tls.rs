@@ -0,0 +1,73 @@
+/// MQTT over TLS 1.2 or 1.3, enabled with the `tls` feature.
+///
+/// The server certificate must be signed by `MQTT_CA_CERT`, which can be a public root,
+/// a private CA, or the server's own self-signed certificate to pin it. The name in the
+/// certificate must match the configured MQTT server.
+///
+/// Reading the TLS session is not known to be cancel-safe, so it must not be raced against
+/// other events. Instead, `readable` waits for data on the TCP socket, and only then is the
+/// session read. Data that mbedTLS already decrypted, but the MQTT client did not ask for yet,
+/// does not wake `readable`. It is received with the next data from the broker, at the latest
+/// the answer to the next ping.
+
+use core::cell::RefCell;
+use embassy_net::tcp::{Error, TcpSocket};
+use embedded_io_async::{ErrorType, Read, Write};
+use esp_mbedtls::asynch::{AsyncConnectedSession, Session};
+use esp_mbedtls::{Certificates, Mode, TlsError, TlsVersion, X509};
+use crate::config::MQTT_CA_CERT;
+
+/// Size of the buffers between mbedTLS and the socket.
+const BUFFER_SIZE: usize = 4096;
+
+pub type TlsSocket<'s, 'a> = AsyncConnectedSession<'s, SharedSocket<'s, 'a>, BUFFER_SIZE, BUFFER_SIZE>;
+
+/// TCP socket used by the TLS session, and by `readable` while the session is idle.
+pub struct SharedSocket<'s, 'a>(&'s RefCell<TcpSocket<'a>>);
+
+impl ErrorType for SharedSocket<'_, '_> {
+    type Error = Error;
+}
+
+impl Read for SharedSocket<'_, '_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.0.borrow_mut().read(buf).await
+    }
+}
+
+impl Write for SharedSocket<'_, '_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.0.borrow_mut().write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.0.borrow_mut().flush().await
+    }
+}
+
+/// Perform the TLS handshake on a connected socket.
+pub async fn connect<'s, 'a>(socket: &'s RefCell<TcpSocket<'a>>, server_name: &'s str) -> Result<TlsSocket<'s, 'a>, TlsError> {
+    let certificates = Certificates {
+        ca_chain: Some(X509::pem(MQTT_CA_CERT.as_bytes())?),
+        ..Default::default()
+    };
+
+    let session = Session::<_, BUFFER_SIZE, BUFFER_SIZE>::new(
+        SharedSocket(socket),
+        server_name,
+        Mode::Client,
+        // Minimum version, TLS 1.3 is used when the server supports it.
+        TlsVersion::Tls1_2,
+        certificates,
+        None,
+    )?;
+
+    session.connect().await
+}
+
+/// Wait until the TCP socket has data, or is closed. Nothing is read, so this is cancel-safe.
+/// Must not be called while the TLS session is in use.
+pub async fn readable(socket: &RefCell<TcpSocket<'_>>) {
+    // A closed socket is reported by the read that follows.
+    let _ = socket.borrow_mut().read_with(|_| (0, ())).await;
+}
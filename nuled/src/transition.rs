@@ -0,0 +1,111 @@
+/// Crossfade from the frame currently shown on the LED strip
+/// into the output of a new or reconfigured effect.
+///
+/// Blending is done in the CIELUV color space, so that the colors
+/// in between look natural instead of passing through gray.
+
+use crate::color::CIELUV;
+use crate::effect::RgbArray;
+
+pub struct Transition<const N: usize> {
+    from: [CIELUV; N],
+    start_ms: u64,
+    duration_ms: u64,
+}
+
+impl<const N: usize> Default for Transition<N> {
+    fn default() -> Self {
+        Self {
+            from: [CIELUV::default(); N],
+            start_ms: 0,
+            duration_ms: 0,
+        }
+    }
+}
+
+impl<const N: usize> Transition<N> {
+    /// Start fading out the frame `from` over `duration_ms` milliseconds.
+    pub fn start(&mut self, from: &RgbArray<N>, duration_ms: u64, now_ms: u64) {
+        self.from = from.0.map(CIELUV::from);
+        self.start_ms = now_ms;
+        self.duration_ms = duration_ms;
+    }
+
+    pub fn is_active(&self, now_ms: u64) -> bool {
+        now_ms < self.start_ms + self.duration_ms
+    }
+
+    /// Blend the outgoing frame with a frame from the new effect.
+    pub fn apply(&self, strip: RgbArray<N>, now_ms: u64) -> RgbArray<N> {
+        if !self.is_active(now_ms) {
+            return strip;
+        }
+
+        let t = now_ms.saturating_sub(self.start_ms) as f32 / self.duration_ms as f32;
+        RgbArray(core::array::from_fn(|i| {
+            self.from[i].interpolate(&CIELUV::from(strip.0[i]), t).into()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::RGB;
+
+    const RED: RGB = RGB { r: 255.0, g: 0.0, b: 0.0 };
+    const BLUE: RGB = RGB { r: 0.0, g: 0.0, b: 255.0 };
+    const GREEN: RGB = RGB { r: 0.0, g: 255.0, b: 0.0 };
+
+    /// Colors pass through CIELUV, so allow for rounding below half an output step.
+    fn assert_rgb_close(actual: RGB, expected: RGB) {
+        let close = |a: f32, b: f32| (a - b).abs() < 0.5;
+        assert!(
+            close(actual.r, expected.r) && close(actual.g, expected.g) && close(actual.b, expected.b),
+            "expected {expected:?}, got {actual:?}",
+        );
+    }
+
+    #[test]
+    fn starts_from_the_old_frame() {
+        let mut transition = Transition::<1>::default();
+        transition.start(&RgbArray([RED]), 1000, 100);
+        assert!(transition.is_active(100));
+        let [led] = transition.apply(RgbArray([BLUE]), 100).0;
+        assert_rgb_close(led, RED);
+    }
+
+    #[test]
+    fn ends_at_the_new_frame() {
+        let mut transition = Transition::<1>::default();
+        transition.start(&RgbArray([RED]), 1000, 100);
+        assert!(transition.is_active(1099));
+        assert!(!transition.is_active(1100));
+        let [led] = transition.apply(RgbArray([BLUE]), 1100).0;
+        assert_rgb_close(led, BLUE);
+    }
+
+    #[test]
+    fn zero_duration_is_a_cut() {
+        let mut transition = Transition::<1>::default();
+        assert!(!transition.is_active(0));
+        transition.start(&RgbArray([RED]), 0, 100);
+        assert!(!transition.is_active(100));
+        let [led] = transition.apply(RgbArray([BLUE]), 100).0;
+        assert_rgb_close(led, BLUE);
+    }
+
+    #[test]
+    fn restart_continues_from_the_shown_frame() {
+        let mut transition = Transition::<1>::default();
+        transition.start(&RgbArray([RED]), 1000, 0);
+        let shown = transition.apply(RgbArray([BLUE]), 500);
+
+        // A new fade starts from the blend on the strip, without a jump.
+        transition.start(&shown, 1000, 500);
+        let [led] = transition.apply(RgbArray([GREEN]), 500).0;
+        assert_rgb_close(led, shown.0[0]);
+        let [led] = transition.apply(RgbArray([GREEN]), 1500).0;
+        assert_rgb_close(led, GREEN);
+    }
+}
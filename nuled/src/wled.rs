@@ -0,0 +1,156 @@
+/// WLED UDP realtime protocols: WARLS, DRGB, DRGBW and DNRGB.
+///
+/// The first byte selects the protocol, and the second byte is the number of seconds to keep
+/// showing the received pixels after the last packet. A timeout of 255 never returns to the effect,
+/// and a timeout of 0 uses the default of two seconds.
+/// WLED sync notifications, which share the port, are ignored.
+
+use crate::color::RGB;
+use crate::pixels::{self, Layout};
+
+pub const PORT: u16 = 21324;
+
+/// Timeout that keeps the received pixels until the next packet.
+const NO_TIMEOUT: u8 = 255;
+/// Seconds used for a timeout of 0, which would hide the pixels before they are shown.
+const DEFAULT_TIMEOUT_SECS: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// Pixels as index, red, green and blue. Only reaches the first 256 pixels.
+    Warls,
+    /// Red, green and blue, from the first pixel.
+    Drgb,
+    /// Red, green, blue and white, from the first pixel.
+    Drgbw,
+    /// Start index, followed by red, green and blue.
+    Dnrgb,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Packet<'a> {
+    pub protocol: Protocol,
+    timeout: u8,
+    data: &'a [u8],
+}
+
+pub fn parse(packet: &[u8]) -> Option<Packet<'_>> {
+    let protocol = match *packet.first()? {
+        1 => Protocol::Warls,
+        2 => Protocol::Drgb,
+        3 => Protocol::Drgbw,
+        4 => Protocol::Dnrgb,
+        _ => return None,
+    };
+    let timeout = *packet.get(1)?;
+    let data = &packet[2..];
+    if protocol == Protocol::Dnrgb && data.len() < 2 {
+        return None;
+    }
+
+    Some(Packet { protocol, timeout, data })
+}
+
+impl Packet<'_> {
+    /// Seconds to keep showing the received pixels, or `None` to keep them until the next packet.
+    pub fn timeout_secs(&self) -> Option<u8> {
+        match self.timeout {
+            NO_TIMEOUT => None,
+            0 => Some(DEFAULT_TIMEOUT_SECS),
+            secs => Some(secs),
+        }
+    }
+
+    /// Copy the pixels in the packet onto the strip.
+    /// Returns whether any pixels were changed.
+    pub fn apply(&self, pixels: &mut [RGB]) -> bool {
+        match self.protocol {
+            Protocol::Warls => {
+                let mut changed = false;
+                for pixel in self.data.chunks_exact(4) {
+                    changed |= pixels::copy(pixels, pixel[0] as usize, &pixel[1..], Layout::Rgb);
+                }
+                changed
+            }
+            Protocol::Drgb => pixels::copy(pixels, 0, self.data, Layout::Rgb),
+            Protocol::Drgbw => pixels::copy(pixels, 0, self.data, Layout::Rgbw),
+            Protocol::Dnrgb => {
+                let start = u16::from_be_bytes([self.data[0], self.data[1]]) as usize;
+                pixels::copy(pixels, start, &self.data[2..], Layout::Rgb)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(pixel: RGB) -> (f32, f32, f32) {
+        (pixel.r, pixel.g, pixel.b)
+    }
+
+    #[test]
+    fn warls() {
+        // Pixels 2 and 0, with a timeout of two seconds.
+        let packet = parse(&[0x01, 0x02, 0x02, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff]).unwrap();
+        assert_eq!(packet.protocol, Protocol::Warls);
+        assert_eq!(packet.timeout_secs(), Some(2));
+
+        let mut pixels = [RGB::default(); 3];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[0]), (0.0, 0.0, 255.0));
+        assert_eq!(rgb(pixels[1]), (0.0, 0.0, 0.0));
+        assert_eq!(rgb(pixels[2]), (255.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn drgb() {
+        let packet = parse(&[0x02, 0x01, 0x0a, 0x14, 0x1e, 0x28, 0x32, 0x3c, 0x46]).unwrap();
+
+        let mut pixels = [RGB::default(); 3];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[0]), (10.0, 20.0, 30.0));
+        assert_eq!(rgb(pixels[1]), (40.0, 50.0, 60.0));
+        assert_eq!(rgb(pixels[2]), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn drgbw() {
+        let packet = parse(&[0x03, 0xff, 0x0a, 0x14, 0x1e, 0x05]).unwrap();
+        assert_eq!(packet.timeout_secs(), None);
+
+        let mut pixels = [RGB::default(); 1];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[0]), (15.0, 25.0, 35.0));
+    }
+
+    #[test]
+    fn dnrgb() {
+        // Start at pixel 258.
+        let packet = parse(&[0x04, 0x05, 0x01, 0x02, 0x01, 0x02, 0x03]).unwrap();
+
+        let mut pixels = [RGB::default(); 260];
+        assert!(packet.apply(&mut pixels));
+        assert_eq!(rgb(pixels[258]), (1.0, 2.0, 3.0));
+        assert_eq!(rgb(pixels[257]), (0.0, 0.0, 0.0));
+
+        assert!(!packet.apply(&mut [RGB::default(); 10]));
+    }
+
+    #[test]
+    fn zero_timeout_is_default() {
+        let packet = parse(&[0x02, 0x00, 0x0a, 0x14, 0x1e]).unwrap();
+        assert_eq!(packet.timeout_secs(), Some(DEFAULT_TIMEOUT_SECS));
+    }
+
+    #[test]
+    fn other_packets_are_ignored() {
+        // WLED sync notification.
+        assert_eq!(parse(&[0x00, 0x01, 0x80, 0xff]), None);
+        assert_eq!(parse(&[0x05, 0x01, 0x00, 0x00]), None);
+        assert_eq!(parse(&[0x04, 0x01, 0x00]), None);
+        assert_eq!(parse(&[0x02]), None);
+        assert_eq!(parse(&[]), None);
+    }
+}
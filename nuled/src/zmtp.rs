@@ -0,0 +1,173 @@
+/// Minimal ZeroMQ subscriber, speaking ZMTP 3.0 over TCP.
+///
+/// Accepts a connection from a ZeroMQ `PUB` socket, and subscribes to all of its messages.
+/// Only the NULL security mechanism is supported. Both sides send their greeting and a `READY`
+/// command, after which the publisher sends messages as frames.
+
+pub const GREETING_SIZE: usize = 64;
+
+const SIGNATURE_START: u8 = 0xff;
+const SIGNATURE_END: u8 = 0x7f;
+const VERSION: [u8; 2] = [3, 0];
+const MECHANISM_NULL: &[u8] = b"NULL";
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+/// `READY` command, announcing a `SUB` socket.
+pub const READY: &[u8] = b"\x04\x19\x05READY\x0bSocket-Type\x00\x00\x00\x03SUB";
+
+/// Subscription to all messages. In ZMTP 3.0, subscriptions are messages starting with 1.
+pub const SUBSCRIBE_ALL: &[u8] = &[0x00, 0x01, 0x01];
+
+#[derive(Debug, PartialEq)]
+pub enum ZmtpError {
+    /// More data is needed.
+    Incomplete,
+    Malformed,
+    UnsupportedVersion,
+    UnsupportedMechanism,
+    /// The peer did not send `READY` after the greeting.
+    NotReady,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub command: bool,
+    /// Whether more frames follow in the same message.
+    pub more: bool,
+    pub body: &'a [u8],
+}
+
+/// Greeting for version 3.0, with the NULL mechanism.
+pub fn greeting() -> [u8; GREETING_SIZE] {
+    let mut greeting = [0; GREETING_SIZE];
+    greeting[0] = SIGNATURE_START;
+    greeting[9] = SIGNATURE_END;
+    greeting[10..12].copy_from_slice(&VERSION);
+    greeting[12..12 + MECHANISM_NULL.len()].copy_from_slice(MECHANISM_NULL);
+    greeting
+}
+
+/// Check the greeting of the peer. Later versions are backwards compatible with 3.0.
+pub fn check_greeting(greeting: &[u8; GREETING_SIZE]) -> Result<(), ZmtpError> {
+    if greeting[0] != SIGNATURE_START || greeting[9] != SIGNATURE_END {
+        return Err(ZmtpError::Malformed);
+    }
+    if greeting[10] < VERSION[0] {
+        return Err(ZmtpError::UnsupportedVersion);
+    }
+    let mechanism = &greeting[12..32];
+    if !mechanism.starts_with(MECHANISM_NULL) || mechanism[MECHANISM_NULL.len()..].iter().any(|&b| b != 0) {
+        return Err(ZmtpError::UnsupportedMechanism);
+    }
+    Ok(())
+}
+
+/// Parse the frame at the start of `buf`, and return it along with its size.
+pub fn parse_frame(buf: &[u8]) -> Result<(Frame<'_>, usize), ZmtpError> {
+    let flags = *buf.first().ok_or(ZmtpError::Incomplete)?;
+    if flags & !(FLAG_MORE | FLAG_LONG | FLAG_COMMAND) != 0 {
+        return Err(ZmtpError::Malformed);
+    }
+
+    let (header_size, body_size) = if flags & FLAG_LONG != 0 {
+        let size = buf.get(1..9).ok_or(ZmtpError::Incomplete)?;
+        (9, u64::from_be_bytes(size.try_into().unwrap()))
+    } else {
+        (2, *buf.get(1).ok_or(ZmtpError::Incomplete)? as u64)
+    };
+
+    let end = usize::try_from(body_size).ok().and_then(|size| size.checked_add(header_size)).ok_or(ZmtpError::Malformed)?;
+    let body = buf.get(header_size..end).ok_or(ZmtpError::Incomplete)?;
+
+    let frame = Frame {
+        command: flags & FLAG_COMMAND != 0,
+        more: flags & FLAG_MORE != 0,
+        body,
+    };
+    Ok((frame, end))
+}
+
+impl Frame<'_> {
+    /// Whether this is a `READY` command.
+    pub fn is_ready(&self) -> bool {
+        self.command && self.body.starts_with(b"\x05READY")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Greeting from libzmq 4.3, version 3.1 with the NULL mechanism.
+    const LIBZMQ_GREETING: [u8; GREETING_SIZE] = [
+        0xff, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x7f, 0x03, 0x01, b'N', b'U', b'L', b'L',
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    /// `READY` command from a libzmq `PUB` socket.
+    const LIBZMQ_READY: &[u8] = b"\x04\x19\x05READY\x0bSocket-Type\x00\x00\x00\x03PUB";
+
+    #[test]
+    fn greetings() {
+        let greeting = greeting();
+        assert_eq!(&greeting[..16], b"\xff\0\0\0\0\0\0\0\0\x7f\x03\x00NULL");
+        assert!(greeting[16..].iter().all(|&b| b == 0));
+        assert_eq!(check_greeting(&greeting), Ok(()));
+        assert_eq!(check_greeting(&LIBZMQ_GREETING), Ok(()));
+
+        let mut old = LIBZMQ_GREETING;
+        old[10] = 2;
+        let mut plain = LIBZMQ_GREETING;
+        plain[12..17].copy_from_slice(b"PLAIN");
+        let mut http = LIBZMQ_GREETING;
+        http[..4].copy_from_slice(b"GET ");
+
+        assert_eq!(check_greeting(&old), Err(ZmtpError::UnsupportedVersion));
+        assert_eq!(check_greeting(&plain), Err(ZmtpError::UnsupportedMechanism));
+        assert_eq!(check_greeting(&http), Err(ZmtpError::Malformed));
+    }
+
+    #[test]
+    fn ready_commands() {
+        let (frame, len) = parse_frame(LIBZMQ_READY).unwrap();
+        assert_eq!(len, LIBZMQ_READY.len());
+        assert!(frame.is_ready());
+
+        let (frame, len) = parse_frame(READY).unwrap();
+        assert_eq!(len, READY.len());
+        assert!(frame.is_ready());
+    }
+
+    #[test]
+    fn message_frames() {
+        let mut stream = vec![0x01, 0x02, b'a', b'b', 0x00, 0x01, b'c'];
+        stream.extend_from_slice(&[0x02, 0, 0, 0, 0, 0, 0, 0, 0x03, b'd', b'e', b'f']);
+
+        let (frame, len) = parse_frame(&stream).unwrap();
+        assert_eq!(frame, Frame { command: false, more: true, body: b"ab" });
+        let stream = &stream[len..];
+
+        let (frame, len) = parse_frame(stream).unwrap();
+        assert_eq!(frame, Frame { command: false, more: false, body: b"c" });
+        let stream = &stream[len..];
+
+        let (frame, len) = parse_frame(stream).unwrap();
+        assert_eq!(frame, Frame { command: false, more: false, body: b"def" });
+        assert_eq!(len, stream.len());
+    }
+
+    #[test]
+    fn incomplete_and_malformed_frames() {
+        assert_eq!(parse_frame(&[]), Err(ZmtpError::Incomplete));
+        assert_eq!(parse_frame(&[0x00]), Err(ZmtpError::Incomplete));
+        assert_eq!(parse_frame(&[0x00, 0x03, b'a']), Err(ZmtpError::Incomplete));
+        assert_eq!(parse_frame(&[0x02, 0, 0, 0]), Err(ZmtpError::Incomplete));
+        assert_eq!(parse_frame(&[0x08, 0x00]), Err(ZmtpError::Malformed));
+        assert_eq!(parse_frame(&[0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), Err(ZmtpError::Malformed));
+    }
+}